@@ -0,0 +1,11 @@
+-- Create Unsubscribe Tokens Table
+CREATE TABLE unsubscribe_tokens(
+   unsubscribe_token TEXT NOT NULL,
+   subscriber_id uuid NOT NULL UNIQUE
+      REFERENCES subscriptions (id),
+   PRIMARY KEY (unsubscribe_token)
+);
+-- Backfill a token for every existing subscriber
+INSERT INTO unsubscribe_tokens (unsubscribe_token, subscriber_id)
+SELECT md5(random()::text || id::text), id
+FROM subscriptions;
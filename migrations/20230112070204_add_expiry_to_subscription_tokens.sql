@@ -0,0 +1,9 @@
+-- Track when a confirmation token was issued and when it expires
+ALTER TABLE subscription_tokens ADD COLUMN issued_at timestamptz NULL;
+ALTER TABLE subscription_tokens ADD COLUMN expires_at timestamptz NULL;
+-- Backfill existing tokens: they get a fresh validity window
+UPDATE subscription_tokens
+  SET issued_at = now(), expires_at = now() + interval '48 hours'
+  WHERE issued_at IS NULL;
+ALTER TABLE subscription_tokens ALTER COLUMN issued_at SET NOT NULL;
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at SET NOT NULL;
@@ -0,0 +1,15 @@
+-- Failed deliveries are retried with an exponential backoff
+ALTER TABLE issue_delivery_queue
+  ADD COLUMN n_retries INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE issue_delivery_queue
+  ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
+-- Deliveries that kept failing end up here, until an admin requeues them
+CREATE TABLE issue_delivery_dead_letters (
+  newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+  subscriber_email TEXT NOT NULL,
+  n_attempts INTEGER NOT NULL,
+  last_error TEXT NOT NULL,
+  failed_at timestamptz NOT NULL,
+  PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,31 @@
+-- One row per recipient of an issue, kept once the delivery task is gone
+CREATE TABLE newsletter_deliveries (
+  newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+  subscriber_email TEXT NOT NULL,
+  -- `pending`, `retrying`, `sent`, `failed` or `skipped`
+  status TEXT NOT NULL,
+  n_attempts INTEGER NOT NULL,
+  provider_message_id TEXT NULL,
+  last_error TEXT NULL,
+  created_at timestamptz NOT NULL,
+  updated_at timestamptz NOT NULL,
+  sent_at timestamptz NULL,
+  PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
+-- Backfill what we still know about
+INSERT INTO newsletter_deliveries (
+  newsletter_issue_id, subscriber_email, status, n_attempts,
+  created_at, updated_at
+)
+SELECT newsletter_issue_id, subscriber_email,
+  CASE WHEN n_retries = 0 THEN 'pending' ELSE 'retrying' END,
+  n_retries, now(), now()
+FROM issue_delivery_queue;
+INSERT INTO newsletter_deliveries (
+  newsletter_issue_id, subscriber_email, status, n_attempts, last_error,
+  created_at, updated_at
+)
+SELECT newsletter_issue_id, subscriber_email, 'failed', n_attempts,
+  last_error, failed_at, failed_at
+FROM issue_delivery_dead_letters;
@@ -0,0 +1,13 @@
+-- `sending`, `paused`, `cancelled` or `completed`
+-- Deliveries of a cancelled issue end up `cancelled` in `newsletter_deliveries`
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+-- Backfill `status` for historical entries
+UPDATE newsletter_issues i
+  SET status = CASE
+    WHEN EXISTS (
+      SELECT 1 FROM issue_delivery_queue q
+      WHERE q.newsletter_issue_id = i.newsletter_issue_id
+    ) THEN 'sending'
+    ELSE 'completed'
+  END;
+ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
@@ -0,0 +1,7 @@
+-- Issues can now also be `scheduled` or `unscheduled`
+-- Scheduled issues are not published yet
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+-- When a `scheduled` issue goes out, and the timezone
+-- the editor picked it in
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_for timestamptz NULL;
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_timezone TEXT NULL;
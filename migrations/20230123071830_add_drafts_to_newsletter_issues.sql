@@ -0,0 +1,7 @@
+-- Issues start out as `draft`s, editors iterate on them before publishing.
+-- `revision` is bumped on every edit, so that editors working on the
+-- same draft don't overwrite each other's changes without noticing.
+ALTER TABLE newsletter_issues
+  ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE newsletter_issues
+  ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
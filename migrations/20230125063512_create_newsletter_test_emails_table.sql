@@ -0,0 +1,10 @@
+-- Test emails sent to editors before publishing an issue
+CREATE TABLE newsletter_test_emails (
+  test_email_id uuid PRIMARY KEY,
+  newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+  recipient TEXT NOT NULL,
+  sent_by uuid NOT NULL REFERENCES users (user_id),
+  provider_message_id TEXT NULL,
+  sent_at timestamptz NOT NULL
+);
@@ -0,0 +1,3 @@
+-- The Markdown source the HTML and plain text bodies were generated from,
+-- NULL for issues written directly in HTML and plain text
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
@@ -0,0 +1,13 @@
+-- Admin-managed layouts wrapping the content of newsletter issues,
+-- both are Handlebars templates.
+CREATE TABLE email_layouts(
+  layout_id uuid PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  html_template TEXT NOT NULL,
+  text_template TEXT NOT NULL,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  updated_at timestamptz NOT NULL DEFAULT now()
+);
+-- NULL for issues sent as they are
+ALTER TABLE newsletter_issues
+  ADD COLUMN layout_id uuid NULL REFERENCES email_layouts (layout_id);
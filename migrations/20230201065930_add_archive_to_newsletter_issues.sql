@@ -0,0 +1,18 @@
+-- Published issues get a public page at `/issues/{slug}`,
+-- unless they are meant for subscribers only.
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
+ALTER TABLE newsletter_issues
+  ADD COLUMN subscribers_only BOOLEAN NOT NULL DEFAULT FALSE;
+-- Issues sent before the archive existed were written for
+-- subscribers: only those created from now on are public
+UPDATE newsletter_issues SET subscribers_only = TRUE;
+-- Issues published already, their id keeps slugs unique
+UPDATE newsletter_issues
+SET slug = COALESCE(
+    NULLIF(
+      trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')),
+      ''
+    ),
+    'issue'
+  ) || '-' || left(newsletter_issue_id::text, 8)
+WHERE published_at IS NOT NULL;
@@ -0,0 +1,5 @@
+-- `published_at` was stored as the text representation of `now()`,
+-- feeds and the archive need a real timestamp to sort and format it.
+ALTER TABLE newsletter_issues
+  ALTER COLUMN published_at TYPE timestamptz
+  USING published_at::timestamptz;
@@ -0,0 +1,16 @@
+-- Opens and clicks are only tracked for issues which opted in
+ALTER TABLE newsletter_issues
+  ADD COLUMN tracking BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE TABLE tracking_events (
+  event_id uuid PRIMARY KEY,
+  newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  -- `open` or `click`
+  kind TEXT NOT NULL,
+  -- Where clicks led to
+  url TEXT NULL,
+  created_at timestamptz NOT NULL
+);
+CREATE INDEX tracking_events_issue_idx
+  ON tracking_events (newsletter_issue_id, kind);
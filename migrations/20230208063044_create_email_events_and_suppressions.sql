@@ -0,0 +1,21 @@
+-- Bounces and spam complaints posted by the email provider
+CREATE TABLE email_events (
+  event_id uuid PRIMARY KEY,
+  provider TEXT NOT NULL,
+  -- Webhooks can be delivered more than once
+  provider_event_id TEXT NULL,
+  -- `hard_bounce`, `soft_bounce` or `spam_complaint`
+  kind TEXT NOT NULL,
+  provider_type TEXT NOT NULL,
+  email TEXT NOT NULL,
+  description TEXT NULL,
+  received_at timestamptz NOT NULL,
+  UNIQUE (provider, provider_event_id)
+);
+-- Addresses we must not send anything to anymore
+CREATE TABLE suppressed_emails (
+  email TEXT PRIMARY KEY,
+  -- The kind of the event which got the address suppressed
+  reason TEXT NOT NULL,
+  suppressed_at timestamptz NOT NULL
+);
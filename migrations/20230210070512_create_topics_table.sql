@@ -0,0 +1,15 @@
+-- The newsletters we run, subscribers pick the ones they want
+CREATE TABLE topics (
+  topic_id uuid PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  description TEXT NOT NULL,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE TABLE subscriber_topics (
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  topic_id uuid NOT NULL REFERENCES topics (topic_id),
+  PRIMARY KEY (subscriber_id, topic_id)
+);
+-- Issues without a topic go to every confirmed subscriber
+ALTER TABLE newsletter_issues
+  ADD COLUMN topic_id uuid NULL REFERENCES topics (topic_id);
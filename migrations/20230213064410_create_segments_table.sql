@@ -0,0 +1,12 @@
+-- Criteria picking the recipients of an issue, NULL criteria are ignored
+CREATE TABLE segments (
+  segment_id uuid PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  subscribed_after DATE NULL,
+  email_domain TEXT NULL,
+  opened_last_issues INT NULL,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
+-- Issues without a segment go to every subscriber of their topic
+ALTER TABLE newsletter_issues
+  ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
@@ -0,0 +1,21 @@
+-- Custom fields of subscribers, defined by admins
+CREATE TABLE subscriber_attributes (
+  attribute_id uuid PRIMARY KEY,
+  -- Used in forms and templates
+  name TEXT NOT NULL UNIQUE,
+  label TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  required BOOLEAN NOT NULL DEFAULT false,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
+-- Attributes without a value for a subscriber have no row
+CREATE TABLE subscriber_attribute_values (
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  attribute_id uuid NOT NULL REFERENCES subscriber_attributes (attribute_id),
+  value TEXT NOT NULL,
+  PRIMARY KEY (subscriber_id, attribute_id)
+);
+ALTER TABLE segments
+  ADD COLUMN attribute_id uuid NULL
+    REFERENCES subscriber_attributes (attribute_id),
+  ADD COLUMN attribute_value TEXT NULL;
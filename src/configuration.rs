@@ -6,13 +6,18 @@ use sqlx::postgres::PgSslMode;
 use sqlx::ConnectOptions;
 
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{
+    EmailSender, Outbox, OutboxEmailClient, PostmarkEmailClient,
+    SmtpEmailClient, SmtpTls,
+};
+use std::sync::Arc;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub issue_delivery: IssueDeliverySettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -25,6 +30,105 @@ pub struct ApplicationSettings {
     pub hmac_secret: Secret<String>,
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct IssueDeliverySettings {
+    // Number of workers delivering emails concurrently
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub n_workers: usize,
+    // Tasks a worker goes through in a row, each in its own transaction
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: u32,
+    // Attempts, the first one included, before a delivery
+    // is moved to the dead-letter table
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u32,
+    // Delay before the first retry, doubled after every failure
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub initial_backoff_milliseconds: u64,
+    // How long workers get to wrap up their batch on shutdown
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_milliseconds: u64,
+    pub rate_limit: SendRateLimitSettings,
+}
+
+impl IssueDeliverySettings {
+    /// Without workers, tasks or attempts nothing would ever be sent.
+    pub fn validate(&self) -> Result<(), String> {
+        let counts = [
+            ("n_workers", self.n_workers as u64),
+            ("batch_size", self.batch_size.into()),
+            ("max_attempts", self.max_attempts.into()),
+        ];
+        for (name, count) in counts {
+            if count == 0 {
+                return Err(format!(
+                    "issue_delivery.{} must be at least 1.",
+                    name
+                ));
+            }
+        }
+        self.rate_limit.validate()
+    }
+}
+
+/// Token-bucket limits on outgoing emails, shared by all workers.
+/// They are checked when the configuration is loaded.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SendRateLimitSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub emails_per_second: f64,
+    // How many emails can be sent at once after a quiet period
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: f64,
+    // Same as above, for each recipient domain (e.g. `gmail.com`)
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_domain_emails_per_second: f64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_domain_burst: f64,
+}
+
+impl SendRateLimitSettings {
+    /// Rates must be positive and buckets must hold at least one email:
+    /// workers would panic or wait forever otherwise.
+    pub fn validate(&self) -> Result<(), String> {
+        let rates = [
+            ("emails_per_second", self.emails_per_second),
+            (
+                "per_domain_emails_per_second",
+                self.per_domain_emails_per_second,
+            ),
+        ];
+        for (name, rate) in rates {
+            if !rate.is_finite() || rate <= 0. {
+                return Err(format!(
+                    "issue_delivery.rate_limit.{} must be a positive number, \
+                    got {}.",
+                    name, rate
+                ));
+            }
+        }
+        let bursts = [
+            ("burst", self.burst),
+            ("per_domain_burst", self.per_domain_burst),
+        ];
+        for (name, burst) in bursts {
+            if !burst.is_finite() || burst < 1. {
+                return Err(format!(
+                    "issue_delivery.rate_limit.{} must be at least 1, got {}.",
+                    name, burst
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl IssueDeliverySettings {
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.shutdown_timeout_milliseconds)
+    }
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
@@ -38,10 +142,47 @@ pub struct DatabaseSettings {
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub backend: EmailBackend,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    // Only required when `backend` is `smtp`
+    pub smtp: Option<SmtpSettings>,
+    // Only required when `backend` is `outbox`
+    pub outbox_directory: Option<String>,
+    pub webhook: WebhookSettings,
+}
+
+/// The HTTP Basic credentials the email provider must use
+/// to post bounces and spam complaints.
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// The service we hand our outgoing emails to
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailBackend {
+    #[default]
+    Postmark,
+    Smtp,
+    /// Write emails to a local directory instead of sending them
+    Outbox,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -72,7 +213,12 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
 
     // Try to convert the configuration values
     // it read into our Settings type
-    settings.try_deserialize::<Settings>()
+    let settings = settings.try_deserialize::<Settings>()?;
+    settings
+        .issue_delivery
+        .validate()
+        .map_err(config::ConfigError::Message)?;
+    Ok(settings)
 }
 
 /// The possible runtime environment for our application
@@ -133,16 +279,53 @@ impl DatabaseSettings {
 }
 
 impl EmailClientSettings {
-    pub fn client(self) -> EmailClient {
+    pub fn client(self) -> Arc<dyn EmailSender> {
         let sender_email =
             self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        match self.backend {
+            EmailBackend::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailBackend::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("Missing `smtp` settings for the SMTP backend.");
+                let credentials = smtp.username.zip(smtp.password);
+                Arc::new(
+                    SmtpEmailClient::new(
+                        &smtp.host,
+                        smtp.port,
+                        smtp.tls,
+                        credentials,
+                        sender_email,
+                        timeout,
+                    )
+                    .expect("Invalid SMTP settings."),
+                )
+            }
+            EmailBackend::Outbox => Arc::new(OutboxEmailClient::new(
+                sender_email,
+                self.outbox().expect(
+                    "Missing `outbox_directory` for the outbox backend.",
+                ),
+            )),
+        }
+    }
+
+    /// The directory capturing outgoing emails, if the
+    /// `outbox` backend is in use.
+    pub fn outbox(&self) -> Option<Outbox> {
+        match self.backend {
+            EmailBackend::Outbox => self
+                .outbox_directory
+                .as_ref()
+                .map(|d| Outbox::new(d.into())),
+            _ => None,
+        }
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -154,3 +337,98 @@ impl EmailClientSettings {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueDeliverySettings, SendRateLimitSettings};
+    use claim::{assert_err, assert_ok};
+
+    fn settings() -> SendRateLimitSettings {
+        SendRateLimitSettings {
+            emails_per_second: 10.,
+            burst: 10.,
+            per_domain_emails_per_second: 5.,
+            per_domain_burst: 1.,
+        }
+    }
+
+    #[test]
+    fn positive_rates_and_bursts_are_valid() {
+        assert_ok!(settings().validate());
+    }
+
+    #[test]
+    fn rates_must_be_positive_and_finite() {
+        for rate in [0., -1., f64::NAN, f64::INFINITY] {
+            assert_err!(SendRateLimitSettings {
+                emails_per_second: rate,
+                ..settings()
+            }
+            .validate());
+            assert_err!(SendRateLimitSettings {
+                per_domain_emails_per_second: rate,
+                ..settings()
+            }
+            .validate());
+        }
+    }
+
+    #[test]
+    fn buckets_must_hold_at_least_one_email() {
+        for burst in [0., 0.5, -1., f64::NAN] {
+            assert_err!(SendRateLimitSettings {
+                burst,
+                ..settings()
+            }
+            .validate());
+            assert_err!(SendRateLimitSettings {
+                per_domain_burst: burst,
+                ..settings()
+            }
+            .validate());
+        }
+    }
+
+    fn delivery_settings() -> IssueDeliverySettings {
+        IssueDeliverySettings {
+            n_workers: 4,
+            batch_size: 10,
+            max_attempts: 5,
+            initial_backoff_milliseconds: 0,
+            shutdown_timeout_milliseconds: 0,
+            rate_limit: settings(),
+        }
+    }
+
+    #[test]
+    fn delivery_needs_workers_tasks_and_attempts() {
+        assert_ok!(delivery_settings().validate());
+        assert_err!(IssueDeliverySettings {
+            n_workers: 0,
+            ..delivery_settings()
+        }
+        .validate());
+        assert_err!(IssueDeliverySettings {
+            batch_size: 0,
+            ..delivery_settings()
+        }
+        .validate());
+        assert_err!(IssueDeliverySettings {
+            max_attempts: 0,
+            ..delivery_settings()
+        }
+        .validate());
+    }
+
+    #[test]
+    fn delivery_checks_its_rate_limits() {
+        assert_err!(IssueDeliverySettings {
+            rate_limit: SendRateLimitSettings {
+                burst: 0.,
+                ..settings()
+            },
+            ..delivery_settings()
+        }
+        .validate());
+    }
+}
@@ -0,0 +1,163 @@
+/// What happened to an email after our provider accepted it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailEventKind {
+    // The address will never accept our emails
+    HardBounce,
+    // e.g. a full mailbox, it might work next time
+    SoftBounce,
+    SpamComplaint,
+}
+
+impl EmailEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailEventKind::HardBounce => "hard_bounce",
+            EmailEventKind::SoftBounce => "soft_bounce",
+            EmailEventKind::SpamComplaint => "spam_complaint",
+        }
+    }
+
+    /// Whether we must stop sending emails to the address.
+    pub fn suppresses(&self) -> bool {
+        match self {
+            EmailEventKind::HardBounce | EmailEventKind::SpamComplaint => true,
+            EmailEventKind::SoftBounce => false,
+        }
+    }
+}
+
+/// A bounce or a spam complaint, as reported by an email provider.
+#[derive(Debug)]
+pub struct EmailEvent {
+    pub kind: EmailEventKind,
+    pub email: String,
+    // Providers may notify us more than once about the same event
+    pub provider_event_id: Option<String>,
+    // The provider's classification, e.g. `HardBounce`
+    pub provider_type: String,
+    pub description: Option<String>,
+}
+
+// Bounce types Postmark deactivates addresses for
+const POSTMARK_HARD_BOUNCES: [&str; 3] =
+    ["HardBounce", "BadEmailAddress", "ManuallyDeactivated"];
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "ID")]
+    id: Option<serde_json::Value>,
+    #[serde(rename = "Type")]
+    event_type: Option<String>,
+    email: Option<String>,
+    description: Option<String>,
+}
+
+impl EmailEvent {
+    /// Parse the payload of a Postmark webhook.
+    ///
+    /// Records other than bounces and spam complaints (deliveries,
+    /// opens...) are of no interest to us: we return `None`.
+    pub fn from_postmark(payload: &[u8]) -> Result<Option<Self>, String> {
+        let event: PostmarkEvent = serde_json::from_slice(payload)
+            .map_err(|e| format!("Invalid Postmark payload: {}", e))?;
+        let kind = match event.record_type.as_str() {
+            "SpamComplaint" => EmailEventKind::SpamComplaint,
+            "Bounce" => {
+                let event_type = event.event_type.as_deref().unwrap_or("");
+                if POSTMARK_HARD_BOUNCES.contains(&event_type) {
+                    EmailEventKind::HardBounce
+                } else {
+                    EmailEventKind::SoftBounce
+                }
+            }
+            _ => return Ok(None),
+        };
+        let email = event
+            .email
+            .filter(|email| !email.trim().is_empty())
+            .ok_or_else(|| format!("The {} has no email.", kind.as_str()))?;
+        Ok(Some(Self {
+            kind,
+            email,
+            // Postmark uses numbers
+            provider_event_id: event.id.map(|id| match id {
+                serde_json::Value::String(id) => id,
+                id => id.to_string(),
+            }),
+            provider_type: event.event_type.unwrap_or(event.record_type),
+            description: event.description,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{EmailEvent, EmailEventKind};
+    use claim::{assert_err, assert_none, assert_some_eq};
+
+    fn parse(payload: serde_json::Value) -> Result<Option<EmailEvent>, String> {
+        EmailEvent::from_postmark(payload.to_string().as_bytes())
+    }
+
+    #[test]
+    fn hard_bounces_suppress_the_address() {
+        let event = parse(serde_json::json!({
+            "RecordType": "Bounce",
+            "ID": 4323372036854775807u64,
+            "Type": "HardBounce",
+            "TypeCode": 1,
+            "Email": "ursula@example.com",
+            "Description": "The server was unable to deliver your message",
+        }))
+        .unwrap()
+        .unwrap();
+        assert_eq!(event.kind, EmailEventKind::HardBounce);
+        assert!(event.kind.suppresses());
+        assert_eq!(event.email, "ursula@example.com");
+        assert_some_eq!(event.provider_event_id, "4323372036854775807");
+    }
+
+    #[test]
+    fn soft_bounces_do_not_suppress_the_address() {
+        let event = parse(serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "SoftBounce",
+            "Email": "ursula@example.com",
+        }))
+        .unwrap()
+        .unwrap();
+        assert_eq!(event.kind, EmailEventKind::SoftBounce);
+        assert!(!event.kind.suppresses());
+    }
+
+    #[test]
+    fn spam_complaints_suppress_the_address() {
+        let event = parse(serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "ID": 42,
+            "Type": "SpamComplaint",
+            "Email": "ursula@example.com",
+        }))
+        .unwrap()
+        .unwrap();
+        assert_eq!(event.kind, EmailEventKind::SpamComplaint);
+        assert!(event.kind.suppresses());
+    }
+
+    #[test]
+    fn other_records_are_ignored() {
+        let event = parse(serde_json::json!({
+            "RecordType": "Delivery",
+            "Recipient": "ursula@example.com",
+        }));
+        assert_none!(event.unwrap());
+    }
+
+    #[test]
+    fn bounces_must_have_an_email() {
+        assert_err!(parse(serde_json::json!({ "RecordType": "Bounce" })));
+        assert_err!(EmailEvent::from_postmark(b"not json"));
+    }
+}
@@ -0,0 +1,215 @@
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+
+/// The bodies of a newsletter issue.
+///
+/// Authors either write both bodies by hand or write Markdown once:
+/// we then generate a sanitized HTML body and a plain text body
+/// out of it, and keep the Markdown source around for later edits.
+#[derive(Debug)]
+pub struct IssueContent {
+    text: String,
+    html: String,
+    markdown: Option<String>,
+}
+
+impl IssueContent {
+    /// Markdown wins over the hand-written bodies if there is any.
+    pub fn new(markdown: &str, text: String, html: String) -> Self {
+        if markdown.trim().is_empty() {
+            Self {
+                text,
+                html,
+                markdown: None,
+            }
+        } else {
+            Self::from_markdown(markdown)
+        }
+    }
+
+    pub fn from_markdown(markdown: &str) -> Self {
+        Self {
+            text: markdown_to_text(markdown),
+            html: markdown_to_html(markdown),
+            markdown: Some(markdown.to_owned()),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    pub fn markdown(&self) -> Option<&str> {
+        self.markdown.as_deref()
+    }
+}
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+}
+
+// Markdown allows raw HTML: whatever we don't expect in an email,
+// e.g. scripts or event handlers, is stripped.
+fn markdown_to_html(markdown: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser(markdown));
+    ammonia::clean(&unsafe_html)
+}
+
+// Keep the structure readable without any markup: blank lines
+// between blocks, bullets for lists and URLs next to link texts.
+fn markdown_to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // `None` for bullet lists, the next number for ordered lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut links: Vec<String> = Vec::new();
+    let mut quote_depth = 0;
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::Paragraph)
+            | Event::Start(Tag::Heading(..))
+            | Event::Start(Tag::Table(_))
+            | Event::Start(Tag::CodeBlock(_))
+                if lists.is_empty() =>
+            {
+                start_block(&mut text, quote_depth)
+            }
+            Event::End(Tag::Paragraph)
+            | Event::End(Tag::Heading(..))
+            | Event::End(Tag::TableRow)
+            | Event::End(Tag::TableHead) => text.push('\n'),
+            Event::Start(Tag::BlockQuote) => quote_depth += 1,
+            Event::End(Tag::BlockQuote) => quote_depth -= 1,
+            Event::Start(Tag::List(first_number)) => {
+                if lists.is_empty() {
+                    start_block(&mut text, quote_depth);
+                }
+                lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::Start(Tag::Link(_, url, _))
+            | Event::Start(Tag::Image(_, url, _)) => {
+                links.push(url.to_string())
+            }
+            Event::End(Tag::Link(..)) | Event::End(Tag::Image(..)) => {
+                if let Some(url) = links.pop() {
+                    text.push_str(&format!(" ({})", url));
+                }
+            }
+            Event::Start(Tag::TableCell)
+                if !text.is_empty() && !text.ends_with('\n') =>
+            {
+                text.push_str(" | ")
+            }
+            Event::Text(s) | Event::Code(s) => {
+                text.push_str(&s);
+            }
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::Rule => {
+                start_block(&mut text, quote_depth);
+                text.push_str("---\n");
+            }
+            Event::TaskListMarker(done) => {
+                text.push_str(if done { "[x] " } else { "[ ] " })
+            }
+            Event::FootnoteReference(s) => {
+                text.push_str(&format!("[{}]", s));
+            }
+            _ => {}
+        }
+    }
+    text.trim_end().to_owned()
+}
+
+// Blocks are separated by a blank line
+fn start_block(text: &mut String, quote_depth: usize) {
+    if !text.is_empty() {
+        while !text.ends_with("\n\n") {
+            text.push('\n');
+        }
+    }
+    text.push_str(&"> ".repeat(quote_depth));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::IssueContent;
+
+    #[test]
+    fn hand_written_bodies_are_kept_as_they_are() {
+        let content =
+            IssueContent::new("  ", "Plain text".into(), "<p>HTML</p>".into());
+        assert_eq!(content.text(), "Plain text");
+        assert_eq!(content.html(), "<p>HTML</p>");
+        assert_eq!(content.markdown(), None);
+    }
+
+    #[test]
+    fn markdown_wins_over_hand_written_bodies() {
+        let content = IssueContent::new(
+            "Hello *world*!",
+            "Plain text".into(),
+            "<p>HTML</p>".into(),
+        );
+        assert_eq!(content.text(), "Hello world!");
+        assert_eq!(content.html(), "<p>Hello <em>world</em>!</p>\n");
+        assert_eq!(content.markdown(), Some("Hello *world*!"));
+    }
+
+    #[test]
+    fn the_html_body_is_sanitized() {
+        let content = IssueContent::from_markdown(
+            "Hi!\n\n<script>alert('pwned')</script>\n\n\
+            <a href=\"https://example.com\" onclick=\"steal()\">Click</a>",
+        );
+        assert!(!content.html().contains("script"));
+        assert!(!content.html().contains("onclick"));
+        assert!(content.html().contains("https://example.com"));
+    }
+
+    #[test]
+    fn the_plain_text_body_keeps_the_structure_readable() {
+        let content = IssueContent::from_markdown(
+            "# Our news\n\n\
+            A paragraph with a [link](https://example.com).\n\n\
+            - First\n\
+            - Second\n\n\
+            1. One\n\
+            2. Two\n\n\
+            > Quoted",
+        );
+        assert_eq!(
+            content.text(),
+            "Our news\n\n\
+            A paragraph with a link (https://example.com).\n\n\
+            - First\n\
+            - Second\n\n\
+            1. One\n\
+            2. Two\n\n\
+            > Quoted"
+        );
+    }
+}
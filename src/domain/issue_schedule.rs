@@ -0,0 +1,122 @@
+use chrono::{DateTime, LocalResult, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+// What `<input type="datetime-local">` submits
+const LOCAL_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// When a newsletter issue should go out, as picked by an editor
+/// in their own timezone.
+#[derive(Debug, Clone, Copy)]
+pub struct IssueSchedule {
+    at: DateTime<Utc>,
+    timezone: Tz,
+}
+
+impl IssueSchedule {
+    /// Parse a local time (`2023-01-20T09:30`) in an IANA timezone
+    /// (`Europe/Rome`).
+    pub fn parse(local_time: &str, timezone: &str) -> Result<Self, String> {
+        let timezone: Tz = timezone
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} is not a valid timezone.", timezone))?;
+        let local_time =
+            NaiveDateTime::parse_from_str(local_time.trim(), LOCAL_TIME_FORMAT)
+                .map_err(|_| {
+                    format!("{} is not a valid date and time.", local_time)
+                })?;
+        match timezone.from_local_datetime(&local_time) {
+            LocalResult::Single(at) => Ok(Self {
+                at: at.with_timezone(&Utc),
+                timezone,
+            }),
+            // Clocks going back or forward, we can't tell what was meant
+            LocalResult::Ambiguous(_, _) | LocalResult::None => Err(format!(
+                "{} does not exist or is ambiguous in {}.",
+                local_time, timezone
+            )),
+        }
+    }
+
+    /// Rebuild a schedule from what we stored in the database.
+    pub fn from_stored(
+        at: DateTime<Utc>,
+        timezone: Option<&str>,
+    ) -> Result<Self, String> {
+        let timezone = match timezone {
+            Some(timezone) => timezone.parse().map_err(|_| {
+                format!("{} is not a valid timezone.", timezone)
+            })?,
+            None => Tz::UTC,
+        };
+        Ok(Self { at, timezone })
+    }
+
+    pub fn at(&self) -> DateTime<Utc> {
+        self.at
+    }
+
+    /// Whether the scheduled time has come already.
+    pub fn is_due(&self) -> bool {
+        self.at <= Utc::now()
+    }
+
+    pub fn timezone(&self) -> &'static str {
+        self.timezone.name()
+    }
+
+    /// The scheduled time in the editor's timezone,
+    /// in the format `parse` expects.
+    pub fn local_time(&self) -> String {
+        self.at
+            .with_timezone(&self.timezone)
+            .format(LOCAL_TIME_FORMAT)
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::IssueSchedule;
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_local_time_is_converted_to_utc() {
+        let schedule =
+            IssueSchedule::parse("2023-01-20T09:30", "Europe/Rome").unwrap();
+        assert_eq!(
+            schedule.at(),
+            Utc.with_ymd_and_hms(2023, 1, 20, 8, 30, 0).unwrap()
+        );
+        assert_eq!(schedule.timezone(), "Europe/Rome");
+        assert_eq!(schedule.local_time(), "2023-01-20T09:30");
+    }
+
+    #[test]
+    fn daylight_saving_time_is_taken_into_account() {
+        let schedule =
+            IssueSchedule::parse("2023-07-20T09:30", "Europe/Rome").unwrap();
+        assert_eq!(
+            schedule.at(),
+            Utc.with_ymd_and_hms(2023, 7, 20, 7, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn an_unknown_timezone_is_rejected() {
+        assert_err!(IssueSchedule::parse("2023-01-20T09:30", "Mars/Olympus"));
+    }
+
+    #[test]
+    fn a_malformed_local_time_is_rejected() {
+        assert_err!(IssueSchedule::parse("20/01/2023 09:30", "UTC"));
+    }
+
+    #[test]
+    fn a_local_time_skipped_by_the_clocks_is_rejected() {
+        // Clocks go from 02:00 to 03:00
+        assert_err!(IssueSchedule::parse("2023-03-26T02:30", "Europe/Rome"));
+        assert_ok!(IssueSchedule::parse("2023-03-26T03:30", "Europe/Rome"));
+    }
+}
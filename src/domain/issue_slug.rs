@@ -0,0 +1,71 @@
+// Long enough to tell issues apart, short enough to share
+const MAX_LENGTH: usize = 80;
+
+/// How a published issue shows up in the URL of its public page,
+/// e.g. `/issues/our-january-update`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueSlug(String);
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl IssueSlug {
+    /// Lowercase ASCII letters and digits out of the title,
+    /// separated by dashes.
+    pub fn from_title(title: &str) -> Self {
+        let mut slug = String::new();
+        for word in title
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            if slug.len() + word.len() + 1 > MAX_LENGTH {
+                break;
+            }
+            if !slug.is_empty() {
+                slug.push('-');
+            }
+            slug.push_str(&word.to_ascii_lowercase());
+        }
+        if slug.is_empty() {
+            slug.push_str("issue");
+        }
+        Self(slug)
+    }
+
+    /// Tell apart issues with the same title: `title-2`, `title-3`...
+    pub fn with_suffix(&self, n: u32) -> Self {
+        Self(format!("{}-{}", self.0, n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::IssueSlug;
+
+    #[test]
+    fn titles_are_turned_into_dashed_words() {
+        let slug = IssueSlug::from_title("  Our January update: 2023!  ");
+        assert_eq!(slug.as_ref(), "our-january-update-2023");
+    }
+
+    #[test]
+    fn a_title_without_ascii_characters_still_gets_a_slug() {
+        assert_eq!(IssueSlug::from_title("¿¡!?").as_ref(), "issue");
+    }
+
+    #[test]
+    fn long_titles_are_cut_between_words() {
+        let slug = IssueSlug::from_title(&"newsletter ".repeat(20));
+        assert!(slug.as_ref().len() <= 80);
+        assert!(slug.as_ref().ends_with("newsletter"));
+    }
+
+    #[test]
+    fn a_suffix_can_be_added() {
+        let slug = IssueSlug::from_title("Hello").with_suffix(2);
+        assert_eq!(slug.as_ref(), "hello-2");
+    }
+}
@@ -1,7 +1,21 @@
+mod email_event;
+mod issue_content;
+mod issue_schedule;
+mod issue_slug;
 mod new_subscriber;
+mod segment;
+mod subscriber_attribute;
 mod subscriber_email;
 mod subscriber_name;
 
+pub use email_event::{EmailEvent, EmailEventKind};
+pub use issue_content::IssueContent;
+pub use issue_schedule::IssueSchedule;
+pub use issue_slug::IssueSlug;
 pub use new_subscriber::NewSubscriber;
+pub use segment::{Segment, SegmentCondition};
+pub use subscriber_attribute::{
+    AttributeDefinition, AttributeKind, AttributeName, AttributeValues,
+};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
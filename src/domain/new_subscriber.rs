@@ -1,7 +1,9 @@
+use crate::domain::AttributeValues;
 use crate::domain::SubscriberEmail;
 use crate::domain::SubscriberName;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub attributes: AttributeValues,
 }
@@ -0,0 +1,220 @@
+use crate::domain::AttributeDefinition;
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+// Nobody remembers their opens further back than that
+const MAX_OPENED_LAST_ISSUES: i32 = 50;
+
+/// One of the criteria a subscriber must match to be in a segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentCondition {
+    // Strictly after the day, in UTC
+    SubscribedAfter(NaiveDate),
+    // Lowercase, without the `@`
+    EmailDomain(String),
+    // Each of the last N tracked issues
+    OpenedLastIssues(i32),
+    // The value is normalised according to the kind of the attribute
+    AttributeEquals {
+        attribute_id: Uuid,
+        name: String,
+        value: String,
+    },
+}
+
+impl SegmentCondition {
+    /// Match subscribers whose attribute has the value,
+    /// checked against the kind of the attribute.
+    pub fn attribute_equals(
+        definition: &AttributeDefinition,
+        value: &str,
+    ) -> Result<Self, String> {
+        let value = definition
+            .kind
+            .parse_value(value)
+            .map_err(|e| format!("{}: {}", definition.label, e))?
+            .ok_or_else(|| {
+                format!("Which value of {} are you after?", definition.label)
+            })?;
+        Ok(Self::AttributeEquals {
+            attribute_id: definition.attribute_id,
+            name: definition.name.clone(),
+            value,
+        })
+    }
+}
+
+impl std::fmt::Display for SegmentCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SubscribedAfter(date) => {
+                write!(f, "subscribed after {}", date)
+            }
+            Self::EmailDomain(domain) => write!(f, "email at {}", domain),
+            Self::OpenedLastIssues(n) => {
+                write!(f, "opened the last {} tracked issues", n)
+            }
+            Self::AttributeEquals { name, value, .. } => {
+                write!(f, "{} is {}", name, value)
+            }
+        }
+    }
+}
+
+/// A subset of the subscribers, matching all of its conditions.
+/// A segment without conditions matches everybody.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Segment {
+    pub conditions: Vec<SegmentCondition>,
+}
+
+impl Segment {
+    /// Build a segment out of the fields of the admin form,
+    /// empty fields are left out.
+    pub fn parse(
+        subscribed_after: &str,
+        email_domain: &str,
+        opened_last_issues: &str,
+    ) -> Result<Self, String> {
+        let mut conditions = Vec::new();
+        if !subscribed_after.trim().is_empty() {
+            let date =
+                NaiveDate::parse_from_str(subscribed_after.trim(), "%Y-%m-%d")
+                    .map_err(|_| {
+                        format!("{} is not a valid date.", subscribed_after)
+                    })?;
+            conditions.push(SegmentCondition::SubscribedAfter(date));
+        }
+        if !email_domain.trim().is_empty() {
+            conditions.push(SegmentCondition::EmailDomain(parse_domain(
+                email_domain,
+            )?));
+        }
+        if !opened_last_issues.trim().is_empty() {
+            let n = opened_last_issues
+                .trim()
+                .parse()
+                .ok()
+                .filter(|n| (1..=MAX_OPENED_LAST_ISSUES).contains(n))
+                .ok_or_else(|| {
+                    format!(
+                        "The number of opened issues must be between 1 \
+                        and {}.",
+                        MAX_OPENED_LAST_ISSUES
+                    )
+                })?;
+            conditions.push(SegmentCondition::OpenedLastIssues(n));
+        }
+        Ok(Self { conditions })
+    }
+
+    pub fn subscribed_after(&self) -> Option<NaiveDate> {
+        self.conditions.iter().find_map(|c| match c {
+            SegmentCondition::SubscribedAfter(date) => Some(*date),
+            _ => None,
+        })
+    }
+
+    pub fn email_domain(&self) -> Option<&str> {
+        self.conditions.iter().find_map(|c| match c {
+            SegmentCondition::EmailDomain(domain) => Some(domain.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn opened_last_issues(&self) -> Option<i32> {
+        self.conditions.iter().find_map(|c| match c {
+            SegmentCondition::OpenedLastIssues(n) => Some(*n),
+            _ => None,
+        })
+    }
+
+    /// The attribute to match and its value.
+    pub fn attribute_equals(&self) -> Option<(Uuid, &str)> {
+        self.conditions.iter().find_map(|c| match c {
+            SegmentCondition::AttributeEquals {
+                attribute_id,
+                value,
+                ..
+            } => Some((*attribute_id, value.as_str())),
+            _ => None,
+        })
+    }
+}
+
+// `@example.com` is fine too
+fn parse_domain(domain: &str) -> Result<String, String> {
+    let domain = domain.trim().trim_start_matches('@').to_lowercase();
+    let is_valid = !domain.is_empty()
+        && domain.contains('.')
+        && !domain
+            .chars()
+            .any(|c| c.is_whitespace() || c == '@' || c == '/');
+    if !is_valid {
+        return Err(format!("{} is not a valid email domain.", domain));
+    }
+    Ok(domain)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{
+        AttributeDefinition, AttributeKind, Segment, SegmentCondition,
+    };
+    use chrono::NaiveDate;
+    use claim::{assert_err, assert_ok_eq};
+    use uuid::Uuid;
+
+    #[test]
+    fn empty_fields_are_left_out() {
+        assert_ok_eq!(Segment::parse("", " ", ""), Segment::default());
+    }
+
+    #[test]
+    fn every_field_is_a_condition() {
+        assert_ok_eq!(
+            Segment::parse("2023-01-31", "@Example.COM", "3"),
+            Segment {
+                conditions: vec![
+                    SegmentCondition::SubscribedAfter(
+                        NaiveDate::from_ymd_opt(2023, 1, 31).unwrap()
+                    ),
+                    SegmentCondition::EmailDomain("example.com".into()),
+                    SegmentCondition::OpenedLastIssues(3),
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected() {
+        assert_err!(Segment::parse("31/01/2023", "", ""));
+        for domain in ["example", "ursula@example.com", "exa mple.com"] {
+            assert_err!(Segment::parse("", domain, ""));
+        }
+        for n in ["0", "-1", "51", "many"] {
+            assert_err!(Segment::parse("", "", n));
+        }
+    }
+
+    #[test]
+    fn attribute_values_must_match_the_kind() {
+        let definition = AttributeDefinition {
+            attribute_id: Uuid::new_v4(),
+            name: "seats".into(),
+            label: "Seats".into(),
+            kind: AttributeKind::Number,
+            required: false,
+        };
+        assert_ok_eq!(
+            SegmentCondition::attribute_equals(&definition, " 12 "),
+            SegmentCondition::AttributeEquals {
+                attribute_id: definition.attribute_id,
+                name: "seats".into(),
+                value: "12".into(),
+            }
+        );
+        assert_err!(SegmentCondition::attribute_equals(&definition, "a few"));
+        assert_err!(SegmentCondition::attribute_equals(&definition, ""));
+    }
+}
@@ -0,0 +1,249 @@
+use chrono::NaiveDate;
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+// The fields of the subscription form attributes can't be named after
+const RESERVED_NAMES: [&str; 4] = ["name", "email", "topic_id", "content"];
+
+/// What the values of an attribute look like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeKind {
+    Text,
+    Number,
+    Boolean,
+    Date,
+}
+
+impl AttributeKind {
+    pub const ALL: [AttributeKind; 4] =
+        [Self::Text, Self::Number, Self::Boolean, Self::Date];
+
+    pub fn parse(kind: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == kind)
+            .ok_or_else(|| format!("{} is not a kind of attribute.", kind))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Number => "number",
+            Self::Boolean => "boolean",
+            Self::Date => "date",
+        }
+    }
+
+    /// What goes in templates when editors preview an issue.
+    pub fn sample(&self) -> &'static str {
+        match self {
+            Self::Text => "Sample",
+            Self::Number => "42",
+            Self::Boolean => "true",
+            Self::Date => "2023-01-31",
+        }
+    }
+
+    /// Check a value submitted for the attribute and normalise it:
+    /// values are compared as strings by segments.
+    /// Empty values are `None`.
+    pub fn parse_value(&self, value: &str) -> Result<Option<String>, String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        let valid = match self {
+            Self::Text => {
+                (value.graphemes(true).count() <= 256).then(|| value.into())
+            }
+            Self::Number => value
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(|_| value.into()),
+            Self::Boolean => match value.to_lowercase().as_str() {
+                "true" | "on" | "yes" => Some("true".into()),
+                "false" | "off" | "no" => Some("false".into()),
+                _ => None,
+            },
+            Self::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.to_string()),
+        };
+        valid.map(Some).ok_or_else(|| {
+            format!("{} is not a valid {}.", value, self.as_str())
+        })
+    }
+}
+
+/// How an attribute shows up in forms and templates,
+/// e.g. `{{ attributes.company }}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeName(String);
+
+impl AsRef<str> for AttributeName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AttributeName {
+    /// Lowercase ASCII letters, digits and underscores,
+    /// starting with a letter.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        let is_valid = name.len() <= 32
+            && name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name.chars().all(|c| {
+                c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'
+            });
+        if !is_valid {
+            return Err(format!(
+                "{} is not a valid attribute name: use lowercase letters, \
+                digits and underscores.",
+                name
+            ));
+        }
+        if RESERVED_NAMES.contains(&name) {
+            return Err(format!("{} is a reserved name.", name));
+        }
+        Ok(Self(name.into()))
+    }
+}
+
+/// A custom field of subscribers, defined by admins.
+#[derive(Debug, Clone)]
+pub struct AttributeDefinition {
+    pub attribute_id: Uuid,
+    pub name: String,
+    pub label: String,
+    pub kind: AttributeKind,
+    pub required: bool,
+}
+
+/// The values of a subscriber's attributes, checked against
+/// their definitions.
+#[derive(Debug, Default, PartialEq)]
+pub struct AttributeValues(Vec<(Uuid, String)>);
+
+impl AttributeValues {
+    /// `submitted` are the fields of a form, named after the attributes.
+    /// Fields which are not attributes are ignored.
+    pub fn parse(
+        definitions: &[AttributeDefinition],
+        submitted: &[(String, String)],
+    ) -> Result<Self, String> {
+        let mut values = Vec::new();
+        for definition in definitions {
+            let value = submitted
+                .iter()
+                .rev()
+                .find(|(name, _)| *name == definition.name)
+                .map(|(_, value)| value.as_str())
+                .unwrap_or("");
+            let value = definition
+                .kind
+                .parse_value(value)
+                .map_err(|e| format!("{}: {}", definition.label, e))?;
+            match value {
+                Some(value) => values.push((definition.attribute_id, value)),
+                None if definition.required => {
+                    return Err(format!("{} is required.", definition.label))
+                }
+                None => {}
+            }
+        }
+        Ok(Self(values))
+    }
+
+    pub fn attribute_ids(&self) -> Vec<Uuid> {
+        self.0.iter().map(|(id, _)| *id).collect()
+    }
+
+    pub fn values(&self) -> Vec<String> {
+        self.0.iter().map(|(_, value)| value.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{
+        AttributeDefinition, AttributeKind, AttributeName, AttributeValues,
+    };
+    use claim::{assert_err, assert_ok, assert_ok_eq};
+    use uuid::Uuid;
+
+    fn definition(
+        name: &str,
+        kind: AttributeKind,
+        required: bool,
+    ) -> AttributeDefinition {
+        AttributeDefinition {
+            attribute_id: Uuid::new_v4(),
+            name: name.into(),
+            label: name.to_uppercase(),
+            kind,
+            required,
+        }
+    }
+
+    #[test]
+    fn values_are_normalised() {
+        assert_ok_eq!(
+            AttributeKind::Text.parse_value(" Acme "),
+            Some("Acme".into())
+        );
+        assert_ok_eq!(
+            AttributeKind::Number.parse_value("-4.5"),
+            Some("-4.5".into())
+        );
+        assert_ok_eq!(
+            AttributeKind::Boolean.parse_value("on"),
+            Some("true".into())
+        );
+        assert_ok_eq!(
+            AttributeKind::Date.parse_value("2023-2-1"),
+            Some("2023-02-01".into())
+        );
+        assert_ok_eq!(AttributeKind::Number.parse_value(" "), None);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert_err!(AttributeKind::Text.parse_value(&"a".repeat(257)));
+        assert_err!(AttributeKind::Number.parse_value("NaN"));
+        assert_err!(AttributeKind::Number.parse_value("forty-two"));
+        assert_err!(AttributeKind::Boolean.parse_value("maybe"));
+        assert_err!(AttributeKind::Date.parse_value("31/01/2023"));
+    }
+
+    #[test]
+    fn attribute_names_are_identifiers() {
+        assert_ok!(AttributeName::parse("company_size2"));
+        for name in
+            ["", "Company", "2nd", "plan-type", "email", &"a".repeat(33)]
+        {
+            assert_err!(AttributeName::parse(name));
+        }
+    }
+
+    #[test]
+    fn required_attributes_must_be_filled_in() {
+        let definitions = [
+            definition("company", AttributeKind::Text, true),
+            definition("seats", AttributeKind::Number, false),
+        ];
+        let submitted = |fields: &[(&str, &str)]| {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            AttributeValues::parse(&definitions, &fields)
+        };
+        assert_err!(submitted(&[("seats", "3")]));
+        assert_err!(submitted(&[("company", " ")]));
+        assert_err!(submitted(&[("company", "Acme"), ("seats", "three")]));
+        let values = submitted(&[("company", "Acme"), ("name", "Ursula")]);
+        assert_eq!(values.unwrap().values(), ["Acme"]);
+    }
+}
@@ -0,0 +1,112 @@
+mod outbox;
+mod postmark;
+mod smtp;
+
+pub use outbox::{Outbox, OutboxEmailClient, OutboxMessage};
+pub use postmark::PostmarkEmailClient;
+pub use smtp::{SmtpEmailClient, SmtpTls};
+
+use crate::domain::SubscriberEmail;
+use crate::routes::error_chain_fmt;
+use std::time::Duration;
+
+/// A custom header to be attached to an outgoing email.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+/// An email the provider accepted to deliver.
+#[derive(Debug, Default)]
+pub struct SentEmail {
+    // The provider's identifier for the message, if it gives us one
+    pub message_id: Option<String>,
+}
+
+/// Why an email could not be delivered.
+///
+/// Callers use it to decide whether it makes sense to try again.
+#[derive(thiserror::Error)]
+pub enum SendEmailError {
+    #[error("The email provider did not answer in time.")]
+    Timeout(#[source] anyhow::Error),
+    #[error("The email provider is rate limiting us.")]
+    RateLimited {
+        // How long the provider asked us to wait, if it did
+        retry_after: Option<Duration>,
+    },
+    #[error("The email provider failed to process the request ({status}).")]
+    ProviderError { status: u16 },
+    // Our account or our requests are at fault (e.g. a revoked
+    // token): every email fails the same way until we fix it
+    #[error("The email provider refused to send emails on our behalf.")]
+    AccountError(#[source] anyhow::Error),
+    #[error("The email provider rejected the email: {message}")]
+    Rejected {
+        // Provider-specific, e.g. Postmark's `ErrorCode`
+        // or the SMTP reply code
+        error_code: Option<u32>,
+        message: String,
+    },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl SendEmailError {
+    /// Sending the very same email again will fail the very same way.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, SendEmailError::Rejected { .. })
+    }
+
+    /// The minimum delay before trying again, as requested by the provider.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            SendEmailError::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Debug for SendEmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Everything that is able to deliver an email on our behalf.
+///
+/// The backend in use is picked at startup based on
+/// `EmailClientSettings`: request handlers and the delivery
+/// worker only ever see a `dyn EmailSender`.
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    /// Send an email carrying additional custom headers
+    /// (e.g. `List-Unsubscribe`) on top of the standard ones.
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<SentEmail, SendEmailError>;
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<SentEmail, SendEmailError> {
+        self.send_email_with_headers(
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            &[],
+        )
+        .await
+    }
+}
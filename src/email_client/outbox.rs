@@ -0,0 +1,204 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailHeader, EmailSender, SendEmailError, SentEmail,
+};
+
+/// An email captured by the `outbox` backend.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    pub sent_at: DateTime<Utc>,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// A directory holding one JSON file per captured email.
+#[derive(Clone, Debug)]
+pub struct Outbox {
+    directory: PathBuf,
+}
+
+impl Outbox {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    async fn store(
+        &self,
+        message: &OutboxMessage,
+    ) -> Result<(), anyhow::Error> {
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .context("Failed to create the outbox directory.")?;
+        let path = self.directory.join(format!("{}.json", message.id));
+        let content = serde_json::to_vec_pretty(message)?;
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// All captured emails, the most recent first.
+    pub async fn list(&self) -> Result<Vec<OutboxMessage>, anyhow::Error> {
+        let mut messages = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.directory).await {
+            Ok(entries) => entries,
+            // Nothing has been sent yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(messages)
+            }
+            Err(e) => {
+                return Err(e).context("Failed to read the outbox directory.")
+            }
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let content = tokio::fs::read(&path).await?;
+            let message =
+                serde_json::from_slice(&content).with_context(|| {
+                    format!("Failed to parse {}", path.display())
+                })?;
+            messages.push(message);
+        }
+        messages.sort_by_key(|m: &OutboxMessage| std::cmp::Reverse(m.sent_at));
+        Ok(messages)
+    }
+
+    pub async fn get(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<OutboxMessage>, anyhow::Error> {
+        let path = self.directory.join(format!("{}.json", id));
+        match tokio::fs::read(&path).await {
+            Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+}
+
+/// Write outgoing emails to an `Outbox` instead of delivering them.
+///
+/// Meant for local development: no email provider (or mock of one)
+/// is required and confirmation links can be followed from the
+/// admin panel.
+pub struct OutboxEmailClient {
+    sender: SubscriberEmail,
+    outbox: Outbox,
+}
+
+impl OutboxEmailClient {
+    pub fn new(sender: SubscriberEmail, outbox: Outbox) -> Self {
+        Self { sender, outbox }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for OutboxEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<SentEmail, SendEmailError> {
+        let message = OutboxMessage {
+            id: Uuid::new_v4(),
+            sent_at: Utc::now(),
+            from: self.sender.as_ref().to_owned(),
+            to: recipient.as_ref().to_owned(),
+            subject: subject.to_owned(),
+            html_body: html_content.to_owned(),
+            text_body: text_content.to_owned(),
+            headers: headers
+                .iter()
+                .map(|h| (h.name.to_owned(), h.value.to_owned()))
+                .collect(),
+        };
+        self.outbox.store(&message).await?;
+        Ok(SentEmail {
+            message_id: Some(message.id.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailHeader, EmailSender, Outbox, OutboxEmailClient,
+    };
+    use claim::{assert_none, assert_some};
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.into()).unwrap()
+    }
+
+    fn outbox() -> Outbox {
+        let directory = uuid::Uuid::new_v4().to_string();
+        Outbox::new(std::env::temp_dir().join(directory))
+    }
+
+    #[tokio::test]
+    async fn an_empty_outbox_has_no_messages() {
+        let messages = outbox().list().await.unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sent_emails_are_captured_in_the_outbox() {
+        // Arrange
+        let outbox = outbox();
+        let sender = email("sender@example.com");
+        let email_client = OutboxEmailClient::new(sender, outbox.clone());
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe",
+            value: "<https://a.b/c>",
+        }];
+
+        // Act
+        email_client
+            .send_email(&email("a@example.com"), "First", "<p>1</p>", "1")
+            .await
+            .unwrap();
+        email_client
+            .send_email_with_headers(
+                &email("b@example.com"),
+                "Second",
+                "<p>2</p>",
+                "2",
+                &headers,
+            )
+            .await
+            .unwrap();
+
+        // Assert
+        let messages = outbox.list().await.unwrap();
+        assert_eq!(messages.len(), 2);
+        // Most recent first
+        assert_eq!(messages[0].subject, "Second");
+        assert_eq!(messages[0].to, "b@example.com");
+        assert_eq!(messages[0].from, "sender@example.com");
+        assert_eq!(
+            messages[0].headers,
+            vec![("List-Unsubscribe".into(), "<https://a.b/c>".into())]
+        );
+        assert_eq!(messages[1].subject, "First");
+        assert_some!(outbox.get(messages[1].id).await.unwrap());
+        assert_none!(outbox.get(uuid::Uuid::new_v4()).await.unwrap());
+    }
+}
@@ -0,0 +1,521 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailHeader, EmailSender, SendEmailError, SentEmail,
+};
+
+/// Deliver emails through Postmark's HTTP API.
+pub struct PostmarkEmailClient {
+    sender: SubscriberEmail,
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkEmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorisation_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            base_url,
+            sender,
+            authorization_token: authorisation_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for PostmarkEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<SentEmail, SendEmailError> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers,
+        };
+        let response = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            // `send` is async so we need to await
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    SendEmailError::Timeout(e.into())
+                } else {
+                    SendEmailError::UnexpectedError(e.into())
+                }
+            })?;
+
+        let status = response.status();
+        if status.is_success() {
+            // The email has been accepted, failing to parse
+            // the body must not get it sent twice
+            let message_id = response
+                .json::<PostmarkSendEmailResponse>()
+                .await
+                .ok()
+                .map(|r| r.message_id);
+            return Ok(SentEmail { message_id });
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(SendEmailError::RateLimited {
+                retry_after: retry_after(response.headers()),
+            });
+        }
+        if status.is_server_error() {
+            return Err(SendEmailError::ProviderError {
+                status: status.as_u16(),
+            });
+        }
+        // Postmark explains what went wrong in the response body
+        let error = response.json::<PostmarkErrorResponse>().await.ok();
+        match error {
+            Some(error)
+                if status == StatusCode::UNPROCESSABLE_ENTITY
+                    && RECIPIENT_ERROR_CODES.contains(&error.error_code) =>
+            {
+                Err(SendEmailError::Rejected {
+                    error_code: Some(error.error_code),
+                    message: error.message,
+                })
+            }
+            // Anything else is about our account or our request (e.g. a
+            // revoked server token): every email would fail the same way.
+            Some(error) => Err(SendEmailError::AccountError(anyhow::anyhow!(
+                "Postmark refused the request ({}): {} (error code {})",
+                status,
+                error.message,
+                error.error_code
+            ))),
+            None => Err(SendEmailError::ProviderError {
+                status: status.as_u16(),
+            }),
+        }
+    }
+}
+
+// The errors specific to the recipient, sending to them again is
+// pointless: an invalid address (300) or an inactive recipient (406).
+const RECIPIENT_ERROR_CODES: [u32; 2] = [300, 406];
+
+#[derive(serde::Deserialize)]
+struct PostmarkSendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkErrorResponse {
+    error_code: u32,
+    message: String,
+}
+
+/// Parse a `Retry-After` header, either a number of seconds
+/// or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means we can try again right away
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader<'a>],
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailHeader, EmailSender, PostmarkEmailClient, SendEmailError,
+    };
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en;
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    // this is a unit struct
+    // w/o any fields
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            // Try to parse the body as a JSON value
+            let result: Result<serde_json::Value, _> =
+                serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // Check that all the mandatory fields are
+                // populated without inspecting the field value
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                // If parsing failed, do not match
+                // the request
+                false
+            }
+        }
+    }
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        en::Sentence(1..2).fake()
+    }
+    /// Generate a random email content
+    fn content() -> String {
+        en::Paragraph(1..10).fake()
+    }
+
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    /// Get a test instance of `PostmarkEmailClient`.
+    fn email_client(base_url: String) -> PostmarkEmailClient {
+        PostmarkEmailClient::new(
+            base_url,
+            email(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(matchers::header_exists("X-Postmark-Server-Token"))
+            .and(matchers::header("Content-Type", "application/json"))
+            .and(matchers::path("/email"))
+            .and(matchers::method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // ☝️ A wiremock::Mock becomes effective
+        // only after it has been mounted on
+        // a wiremock::Mockserver - that’s
+        // what our call to Mock::mount is about.
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        // ‼️ We don't need to write an explicit
+        // assert because the mounted Mock above
+        // has an `expect(1)` which  checks that
+        // this test should receive exactly one
+        // request with the given `matchers`
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_sends_the_custom_headers() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(matchers::path("/email"))
+            .and(matchers::method("POST"))
+            .and(SendEmailBodyMatcher)
+            .and(matchers::body_partial_json(serde_json::json!({
+                "Headers": [
+                    { "Name": "List-Unsubscribe", "Value": "<https://a.b/c>" }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe",
+            value: "<https://a.b/c>",
+        }];
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &headers,
+            )
+            .await;
+
+        // Assert
+        claim::assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        // We do not copy in all the matchers we have in the other test.
+        // The purpose of this test is not to assert on the request we
+        // are sending out!
+        // We add the bare minimum needed to trigger the path we want
+        // to test in `send_email`.
+        Mock::given(matchers::any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        claim::assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_the_postmark_message_id() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let body = serde_json::json!({
+            "To": "receiver@example.com",
+            "SubmittedAt": "2014-02-17T07:25:01.4178645-05:00",
+            "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+            "ErrorCode": 0,
+            "Message": "OK"
+        });
+        Mock::given(matchers::any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(
+            outcome.unwrap().message_id.as_deref(),
+            Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(matchers::any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(SendEmailError::ProviderError { status: 500 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_the_postmark_error_code_on_rejection() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let body = serde_json::json!({
+            "ErrorCode": 406,
+            "Message": "You tried to send to a recipient that has been \
+                marked as inactive."
+        });
+        Mock::given(matchers::any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        let error = outcome.unwrap_err();
+        assert!(error.is_permanent());
+        assert!(matches!(
+            error,
+            SendEmailError::Rejected {
+                error_code: Some(406),
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn account_errors_are_not_permanent_rejections() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let test_cases = [
+            (401, 10, "Bad or missing Server API token"),
+            (422, 400, "Sender signature not found"),
+        ];
+
+        for (status, error_code, message) in test_cases {
+            let _mock_guard = Mock::given(matchers::any())
+                .respond_with(ResponseTemplate::new(status).set_body_json(
+                    serde_json::json!({
+                        "ErrorCode": error_code,
+                        "Message": message,
+                    }),
+                ))
+                .expect(1)
+                .mount_as_scoped(&mock_server)
+                .await;
+
+            // Act
+            let outcome = email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await;
+
+            // Assert
+            let error = outcome.unwrap_err();
+            assert!(
+                !error.is_permanent(),
+                "A {} with error code {} was deemed permanent",
+                status,
+                error_code
+            );
+            assert!(matches!(error, SendEmailError::AccountError(_)));
+            assert!(format!("{:?}", error).contains(message));
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_when_it_is_rate_limited() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(matchers::any())
+            .respond_with(
+                ResponseTemplate::new(429).insert_header("Retry-After", "30"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        let error = outcome.unwrap_err();
+        assert!(!error.is_permanent());
+        assert_eq!(
+            error.retry_after(),
+            Some(std::time::Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn retry_after_can_be_an_http_date() {
+        let in_a_minute = chrono::Utc::now() + chrono::Duration::seconds(60);
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            in_a_minute.to_rfc2822().parse().unwrap(),
+        );
+
+        let retry_after = super::retry_after(&headers).unwrap();
+
+        assert!(retry_after > std::time::Duration::from_secs(55));
+        assert!(retry_after <= std::time::Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200)
+            // 3 minutes!
+            .set_delay(std::time::Duration::from_secs(180));
+        Mock::given(matchers::any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(outcome, Err(SendEmailError::Timeout(_))));
+    }
+}
@@ -0,0 +1,284 @@
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::MultiPart;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailHeader, EmailSender, SendEmailError, SentEmail,
+};
+
+/// How the connection to the SMTP relay is secured.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Connect in plain text, then upgrade with `STARTTLS`
+    #[default]
+    StartTls,
+    /// Implicit TLS from the very first byte (a.k.a. SMTPS)
+    Tls,
+    /// No encryption at all - only meant for local SMTP sinks
+    None,
+}
+
+/// Deliver emails to an SMTP relay.
+pub struct SmtpEmailClient {
+    sender: SubscriberEmail,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        let builder = match tls {
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            }
+        };
+        let mut builder = builder.port(port).timeout(Some(timeout));
+        // `AUTH` is only performed if we have been given credentials
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+        Ok(Self {
+            sender,
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<SentEmail, SendEmailError> {
+        let mut builder = Message::builder()
+            .from(self.sender.as_ref().parse().map_err(anyhow::Error::from)?)
+            .to(recipient.as_ref().parse().map_err(rejected)?)
+            .subject(subject)
+            // Generate one, relays don't report theirs in a standard way
+            .message_id(None);
+        for header in headers {
+            let name = HeaderName::new_from_ascii(header.name.to_owned())
+                .map_err(anyhow::Error::from)?;
+            builder = builder
+                .raw_header(HeaderValue::new(name, header.value.to_owned()));
+        }
+        let message = builder
+            .multipart(MultiPart::alternative_plain_html(
+                text_content.to_owned(),
+                html_content.to_owned(),
+            ))
+            .map_err(anyhow::Error::from)?;
+        let message_id = message
+            .headers()
+            .get_raw("Message-ID")
+            .map(|id| id.to_owned());
+        self.transport.send(message).await.map_err(classify)?;
+        Ok(SentEmail { message_id })
+    }
+}
+
+fn rejected(e: impl std::fmt::Display) -> SendEmailError {
+    SendEmailError::Rejected {
+        error_code: None,
+        message: e.to_string(),
+    }
+}
+
+// 4xx replies are transient, 5xx replies are permanent (RFC 5321)
+fn classify(e: lettre::transport::smtp::Error) -> SendEmailError {
+    let code = e.status().map(u16::from);
+    if e.is_timeout() {
+        SendEmailError::Timeout(e.into())
+    } else if e.is_permanent() {
+        SendEmailError::Rejected {
+            error_code: code.map(u32::from),
+            message: e.to_string(),
+        }
+    } else if let (true, Some(status)) = (e.is_transient(), code) {
+        SendEmailError::ProviderError { status }
+    } else {
+        SendEmailError::UnexpectedError(e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailHeader, EmailSender, SendEmailError, SmtpEmailClient, SmtpTls,
+    };
+    use secrecy::Secret;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// A bare-bones SMTP sink: it accepts a single session, replies
+    /// `rcpt_reply` to `RCPT TO` and returns the full transcript of
+    /// what the client sent.
+    async fn smtp_sink(rcpt_reply: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            let mut transcript = String::new();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                transcript.push_str(&line);
+                let reply: &[u8] = if in_data {
+                    if line != ".\r\n" {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 Queued\r\n"
+                } else {
+                    let command = line.to_uppercase();
+                    if command.starts_with("EHLO") {
+                        b"250-localhost\r\n250 AUTH PLAIN LOGIN\r\n"
+                    } else if command.starts_with("AUTH") {
+                        b"235 Authenticated\r\n"
+                    } else if command.starts_with("RCPT") {
+                        rcpt_reply.as_bytes()
+                    } else if command.starts_with("DATA") {
+                        in_data = true;
+                        b"354 Go ahead\r\n"
+                    } else if command.starts_with("QUIT") {
+                        writer.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    } else {
+                        b"250 OK\r\n"
+                    }
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+        (port, handle)
+    }
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.into()).unwrap()
+    }
+
+    fn email_client(port: u16) -> SmtpEmailClient {
+        SmtpEmailClient::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::None,
+            Some(("user".into(), Secret::new("password".into()))),
+            email("sender@example.com"),
+            std::time::Duration::from_secs(5),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message_to_the_smtp_relay() {
+        // Arrange
+        let (port, sink) = smtp_sink("250 OK\r\n").await;
+        let email_client = email_client(port);
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe",
+            value: "<https://a.b/c>",
+        }];
+
+        // Act
+        let outcome = email_client
+            .send_email_with_headers(
+                &email("recipient@example.com"),
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+                &headers,
+            )
+            .await;
+
+        // Assert
+        let message_id = outcome.unwrap().message_id.unwrap();
+        let transcript = sink.await.unwrap();
+        assert!(transcript.contains(&format!("Message-ID: {}", message_id)));
+        assert!(transcript.contains("AUTH PLAIN"));
+        assert!(transcript.contains("MAIL FROM:<sender@example.com>"));
+        assert!(transcript.contains("RCPT TO:<recipient@example.com>"));
+        assert!(transcript.contains("Subject: Newsletter title"));
+        assert!(transcript.contains("List-Unsubscribe: <https://a.b/c>"));
+        assert!(transcript.contains("Newsletter body as plain text"));
+        assert!(transcript.contains("<p>Newsletter body as HTML</p>"));
+    }
+
+    #[tokio::test]
+    async fn send_email_is_rejected_if_the_relay_rejects_the_recipient() {
+        // Arrange
+        let (port, _sink) = smtp_sink("550 No such user\r\n").await;
+        let email_client = email_client(port);
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email("recipient@example.com"),
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+            )
+            .await;
+
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(SendEmailError::Rejected {
+                error_code: Some(550),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_can_be_retried_if_the_relay_fails_temporarily() {
+        // Arrange
+        let (port, _sink) = smtp_sink("451 Try again later\r\n").await;
+        let email_client = email_client(port);
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email("recipient@example.com"),
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+            )
+            .await;
+
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(SendEmailError::ProviderError { status: 451 })
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+use handlebars::{
+    no_escape, Context, Handlebars, Helper, HelperResult, Output,
+    RenderContext, RenderError,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const BODY: &str = "body";
+const LAYOUT: &str = "layout";
+// Layouts include the body of the email with `{{ content }}`
+const CONTENT: &str = "content";
+
+static NO_ATTRIBUTES: BTreeMap<String, String> = BTreeMap::new();
+
+/// What wraps the body of emails: header, footer, branding...
+#[derive(Debug, Clone)]
+pub struct EmailLayout {
+    pub html: String,
+    pub text: String,
+}
+
+impl EmailLayout {
+    /// Make sure both templates are valid and include the body,
+    /// by rendering them with `sample` variables.
+    pub fn parse(
+        html: String,
+        text: String,
+        sample: &IssueVariables,
+    ) -> Result<Self, String> {
+        let layout = Self { html, text };
+        // A marker no real issue contains
+        let marker = "\u{1F4F0}-content-\u{1F4F0}";
+        let template = EmailTemplate::parse(marker, marker, Some(&layout))?;
+        let rendered = template.render(sample)?;
+        if !rendered.html.contains(marker) {
+            return Err("The HTML layout must include {{ content }}.".into());
+        }
+        if !rendered.text.contains(marker) {
+            return Err("The text layout must include {{ content }}.".into());
+        }
+        Ok(layout)
+    }
+}
+
+/// The variables newsletter issues and their layouts can use.
+#[derive(Serialize)]
+pub struct IssueVariables<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+    // The custom attributes of the subscriber, by name:
+    // `{{ attributes.company }}`
+    pub attributes: &'a BTreeMap<String, String>,
+}
+
+impl IssueVariables<'static> {
+    /// What editors see in previews and test emails.
+    pub fn sample() -> Self {
+        Self {
+            name: "Ursula Le Guin",
+            email: "ursula_le_guin@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe",
+            attributes: &NO_ATTRIBUTES,
+        }
+    }
+
+    /// Readers of the public archive: we don't know who they are.
+    pub fn anonymous() -> Self {
+        Self {
+            name: "reader",
+            email: "",
+            unsubscribe_url: "",
+            attributes: &NO_ATTRIBUTES,
+        }
+    }
+}
+
+pub struct RenderedEmail {
+    pub html: String,
+    pub text: String,
+}
+
+/// The HTML and plain text bodies of an email, optionally wrapped
+/// in a layout, ready to be rendered for each recipient.
+///
+/// Templates use the Handlebars syntax, e.g. `Hi {{ name }}!`.
+/// Values are HTML-escaped in the HTML body, and using a variable
+/// that does not exist is an error.
+#[derive(Debug)]
+pub struct EmailTemplate {
+    html: Handlebars<'static>,
+    text: Handlebars<'static>,
+}
+
+impl EmailTemplate {
+    pub fn parse(
+        html_body: &str,
+        text_body: &str,
+        layout: Option<&EmailLayout>,
+    ) -> Result<Self, String> {
+        let mut html = registry();
+        html.register_template_string(BODY, html_body)
+            .map_err(|e| format!("The HTML content is invalid: {}", e))?;
+        let mut text = registry();
+        text.register_escape_fn(no_escape);
+        text.register_template_string(BODY, text_body)
+            .map_err(|e| format!("The text content is invalid: {}", e))?;
+        if let Some(layout) = layout {
+            html.register_template_string(LAYOUT, &layout.html)
+                .map_err(|e| format!("The HTML layout is invalid: {}", e))?;
+            text.register_template_string(LAYOUT, &layout.text)
+                .map_err(|e| format!("The text layout is invalid: {}", e))?;
+        }
+        Ok(Self { html, text })
+    }
+
+    /// Parse the templates and render them with sample variables:
+    /// better finding out about mistakes before sending anything.
+    pub fn check(
+        html_body: &str,
+        text_body: &str,
+        layout: Option<&EmailLayout>,
+    ) -> Result<Self, String> {
+        let template = Self::parse(html_body, text_body, layout)?;
+        template.render(&IssueVariables::sample())?;
+        Ok(template)
+    }
+
+    pub fn render(
+        &self,
+        variables: &impl Serialize,
+    ) -> Result<RenderedEmail, String> {
+        Ok(RenderedEmail {
+            html: render(&self.html, variables)
+                .map_err(|e| format!("Failed to render the HTML: {}", e))?,
+            text: render(&self.text, variables)
+                .map_err(|e| format!("Failed to render the text: {}", e))?,
+        })
+    }
+}
+
+fn registry() -> Handlebars<'static> {
+    let mut registry = Handlebars::new();
+    registry.set_strict_mode(true);
+    registry.register_helper(CONTENT, Box::new(content_helper));
+    registry
+}
+
+// The body is rendered on its own first, then handed over to the layout
+fn render(
+    registry: &Handlebars<'static>,
+    variables: &impl Serialize,
+) -> Result<String, RenderError> {
+    let body = registry.render(BODY, variables)?;
+    if !registry.has_template(LAYOUT) {
+        return Ok(body);
+    }
+    let mut data = serde_json::to_value(variables)?;
+    if let Some(data) = data.as_object_mut() {
+        data.insert(CONTENT.into(), body.into());
+    }
+    registry.render(LAYOUT, &data)
+}
+
+// The body has been escaped already, if needed
+fn content_helper<'reg, 'rc>(
+    _: &Helper<'reg, 'rc>,
+    _: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    _: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let content = ctx
+        .data()
+        .get(CONTENT)
+        .and_then(|content| content.as_str())
+        .ok_or_else(|| {
+            RenderError::new("{{ content }} can only be used in layouts")
+        })?;
+    out.write(content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::email_template::{EmailLayout, EmailTemplate, IssueVariables};
+    use claim::{assert_err, assert_ok};
+    use std::collections::BTreeMap;
+
+    fn variables() -> IssueVariables<'static> {
+        IssueVariables {
+            name: "<b>Tom</b>",
+            email: "tom@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=abc",
+            ..IssueVariables::sample()
+        }
+    }
+
+    #[test]
+    fn variables_are_escaped_in_html_only() {
+        let template = EmailTemplate::parse(
+            "<p>Hi {{ name }}!</p>",
+            "Hi {{ name }}!",
+            None,
+        )
+        .unwrap();
+        let rendered = template.render(&variables()).unwrap();
+        assert_eq!(rendered.html, "<p>Hi &lt;b&gt;Tom&lt;/b&gt;!</p>");
+        assert_eq!(rendered.text, "Hi <b>Tom</b>!");
+    }
+
+    #[test]
+    fn the_layout_wraps_the_body() {
+        let layout = EmailLayout::parse(
+            "<header>News</header>{{ content }}\
+            <a href=\"{{ unsubscribe_url }}\">Bye</a>"
+                .into(),
+            "News\n\n{{ content }}\n\n{{ unsubscribe_url }}".into(),
+            &IssueVariables::sample(),
+        )
+        .unwrap();
+        let template =
+            EmailTemplate::parse("<p>Hi</p>", "Hi", Some(&layout)).unwrap();
+        let rendered = template.render(&variables()).unwrap();
+        assert_eq!(
+            rendered.html,
+            "<header>News</header><p>Hi</p>\
+            <a href=\"https://example.com/unsubscribe?token&#x3D;abc\">Bye</a>"
+        );
+        assert_eq!(
+            rendered.text,
+            "News\n\nHi\n\nhttps://example.com/unsubscribe?token=abc"
+        );
+    }
+
+    #[test]
+    fn unknown_variables_are_caught_by_the_check() {
+        assert_ok!(EmailTemplate::parse("{{ nmae }}", "", None));
+        assert_err!(EmailTemplate::check("{{ nmae }}", "", None));
+        assert_ok!(EmailTemplate::check("{{ name }}", "{{ email }}", None));
+    }
+
+    #[test]
+    fn attributes_are_variables() {
+        let attributes =
+            BTreeMap::from([("company".to_string(), "Acme".to_string())]);
+        let variables = IssueVariables {
+            attributes: &attributes,
+            ..variables()
+        };
+        let template = EmailTemplate::parse(
+            "Hi {{ attributes.company }}",
+            "Hi {{ attributes.company }}",
+            None,
+        )
+        .unwrap();
+        assert_eq!(template.render(&variables).unwrap().text, "Hi Acme");
+        // Unknown attributes are mistakes too
+        assert!(template.render(&IssueVariables::sample()).is_err());
+    }
+
+    #[test]
+    fn malformed_templates_are_rejected() {
+        assert_err!(EmailTemplate::parse("{{#if name}}", "", None));
+    }
+
+    #[test]
+    fn a_layout_must_include_the_body() {
+        let sample = IssueVariables::sample();
+        assert_err!(EmailLayout::parse(
+            "<header>News</header>".into(),
+            "{{ content }}".into(),
+            &sample
+        ));
+        assert_err!(EmailLayout::parse(
+            "{{ content }}".into(),
+            "News".into(),
+            &sample
+        ));
+    }
+
+    #[test]
+    fn the_body_cannot_include_itself() {
+        assert_err!(EmailTemplate::check("{{ content }}", "", None));
+    }
+}
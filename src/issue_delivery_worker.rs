@@ -1,76 +1,655 @@
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::{configuration::Settings, startup::get_connection_pool};
+use crate::configuration::{IssueDeliverySettings, Settings};
+use crate::domain::{IssueSlug, SubscriberEmail};
+use crate::email_client::{
+    EmailHeader, EmailSender, SendEmailError, SentEmail,
+};
+use crate::email_template::{
+    EmailLayout, EmailTemplate, IssueVariables, RenderedEmail,
+};
+use crate::segments::get_issue_audience;
+use crate::send_rate_limiter::SendRateLimiter;
+use crate::startup::{get_connection_pool, HmacSecret};
+use crate::subscriber_attributes::{
+    get_attribute_definitions, get_template_attributes, sample_attributes,
+};
+use crate::suppressions::is_suppressed;
+use crate::tracking::TrackedDelivery;
 
-use sqlx::{PgPool, Postgres, Transaction};
+use chrono::Utc;
+use sqlx::postgres::PgListener;
+use sqlx::{
+    PgConnection, PgExecutor, PgPool, Postgres, QueryBuilder, Transaction,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::Duration;
-use tracing::{field::display, Span};
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinSet;
+use tracing::Span;
 use uuid::Uuid;
 
+/// The channel used to wake up workers when new tasks are enqueued.
+pub const NEW_TASKS_CHANNEL: &str = "issue_delivery_queue";
+
+// Safety net: nudge the workers even if no notification came in
+const MAX_IDLE_TIME: Duration = Duration::from_secs(60);
+// How often we look for tasks if we can't LISTEN for notifications
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(10);
+// Used in the unsubscribe links of test emails
+const TEST_UNSUBSCRIBE_TOKEN: &str = "test-email";
+// How long we hold off when rate limited without a `Retry-After`
+const DEFAULT_RATE_LIMIT_PAUSE: Duration = Duration::from_secs(5);
+// How often we check whether somebody fixed our account
+// with the email provider
+const ACCOUNT_ERROR_PAUSE: Duration = Duration::from_secs(60);
+
+/// Where the links of issues point to, and how tracking links are signed.
+#[derive(Clone)]
+pub struct LinkSettings {
+    pub base_url: String,
+    pub hmac_secret: HmacSecret,
+}
+
 async fn worker_loop(
     pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailSender>,
+    links: LinkSettings,
+    settings: IssueDeliverySettings,
+    rate_limiter: Arc<SendRateLimiter>,
+    new_tasks: Arc<Notify>,
+    mut stop: watch::Receiver<bool>,
 ) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
+    while !should_stop(&stop) {
+        // Register interest before looking at the queue,
+        // so that we can't miss a notification in between
+        let notified = new_tasks.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+
+        match execute_tasks(
+            &pool,
+            email_client.as_ref(),
+            &links,
+            &settings,
+            &rate_limiter,
+            || should_stop(&stop),
+        )
+        .await
+        {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                let idle_time = match next_task_due_in(&pool).await {
+                    Ok(Some(due_in)) => due_in.min(MAX_IDLE_TIME),
+                    _ => MAX_IDLE_TIME,
+                };
+                tokio::select! {
+                    _ = notified => {}
+                    _ = tokio::time::sleep(idle_time) => {}
+                    _ = stop.changed() => {}
+                }
             }
             Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    _ = stop.changed() => {}
+                }
+            }
+            Ok(ExecutionOutcome::Throttled(wait_time)) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(wait_time) => {}
+                    _ = stop.changed() => {}
+                }
             }
             Ok(ExecutionOutcome::TaskCompleted) => {}
         }
     }
+    Ok(())
+}
+
+/// Publish scheduled issues once their time has come.
+///
+/// Editors changing a schedule wake us up through the
+/// same channel used for new tasks.
+async fn scheduler_loop(
+    pool: PgPool,
+    new_tasks: Arc<Notify>,
+    mut stop: watch::Receiver<bool>,
+) {
+    while !should_stop(&stop) {
+        let notified = new_tasks.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+
+        if let Err(e) = publish_due_issues(&pool).await {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to publish scheduled newsletter issues.",
+            );
+        }
+        let idle_time = match next_issue_due_in(&pool).await {
+            Ok(Some(due_in)) => due_in.min(MAX_IDLE_TIME),
+            _ => MAX_IDLE_TIME,
+        };
+        tokio::select! {
+            _ = notified => {}
+            _ = tokio::time::sleep(idle_time) => {}
+            _ = stop.changed() => {}
+        }
+    }
+}
+
+/// Enqueue the deliveries of scheduled issues that are due.
+/// Returns how many issues have been published.
+#[tracing::instrument(skip_all, fields(n_issues=tracing::field::Empty))]
+pub async fn publish_due_issues(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let issues = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND scheduled_for <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        "#,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    Span::current().record("n_issues", issues.len());
+    for issue in &issues {
+        publish_issue(&mut transaction, issue.newsletter_issue_id).await?;
+    }
+    transaction.commit().await?;
+    Ok(issues.len())
+}
+
+#[tracing::instrument(skip_all)]
+async fn next_issue_due_in(
+    pool: &PgPool,
+) -> Result<Option<Duration>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT MIN(scheduled_for) AS "scheduled_for"
+        FROM newsletter_issues
+        WHERE status = 'scheduled'
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(r.scheduled_for.map(|scheduled_for| {
+        (scheduled_for - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO)
+    }))
+}
+
+/// Mark an issue as published and enqueue a delivery task
+/// for every subscriber in its audience.
+#[tracing::instrument(skip(transaction))]
+pub async fn publish_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sending', published_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    assign_slug(transaction, newsletter_issue_id).await?;
+    let audience =
+        get_issue_audience(&mut *transaction, newsletter_issue_id).await?;
+    let mut query = QueryBuilder::new(
+        "INSERT INTO issue_delivery_queue \
+        (newsletter_issue_id, subscriber_email) SELECT ",
+    );
+    query
+        .push_bind(newsletter_issue_id)
+        .push(", subscriptions.email FROM subscriptions WHERE ");
+    audience.push_filter(&mut query);
+    let n_tasks = query.build().execute(&mut *transaction).await?;
+    if n_tasks.rows_affected() == 0 {
+        // Nobody to send it to, we are already done
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'completed'
+            WHERE newsletter_issue_id = $1
+            "#,
+            newsletter_issue_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            status,
+            n_attempts,
+            created_at,
+            updated_at
+        )
+        SELECT newsletter_issue_id, subscriber_email, 'pending', 0, now(), now()
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    notify_new_tasks(transaction).await?;
+    Ok(())
+}
+
+// The first free slug out of the title: `title`, `title-2`...
+async fn assign_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let title_slug = IssueSlug::from_title(&issue.title);
+    let mut slug = title_slug.clone();
+    for n in 2.. {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET slug = $2
+            WHERE
+            newsletter_issue_id = $1 AND
+            NOT EXISTS (SELECT 1 FROM newsletter_issues WHERE slug = $2)
+            "#,
+            newsletter_issue_id,
+            slug.as_ref(),
+        )
+        .execute(&mut *transaction)
+        .await?;
+        if updated.rows_affected() == 1 {
+            break;
+        }
+        slug = title_slug.with_suffix(n);
+    }
+    Ok(())
+}
+
+// We also stop if the other side of the channel is gone
+fn should_stop(stop: &watch::Receiver<bool>) -> bool {
+    *stop.borrow() || stop.has_changed().is_err()
+}
+
+/// Wake the workers up whenever new tasks are enqueued.
+///
+/// A single connection is used to LISTEN on behalf of all workers.
+/// If it can't be established we wake them up at regular
+/// intervals instead.
+async fn listen_for_new_tasks(pool: PgPool, new_tasks: Arc<Notify>) {
+    let mut listener = None;
+    loop {
+        let Some(l) = &mut listener else {
+            listener = connect_listener(&pool).await;
+            if listener.is_none() {
+                tokio::time::sleep(FALLBACK_POLL_INTERVAL).await;
+                new_tasks.notify_waiters();
+            }
+            continue;
+        };
+        match l.try_recv().await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                // It is re-established on the next call to `try_recv`
+                tracing::warn!(
+                    "Lost the connection used to listen for new tasks."
+                );
+            }
+            Err(e) => {
+                tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to listen for new tasks. Falling back to polling.",
+                );
+                listener = None;
+            }
+        }
+        // Notifications might have been missed if the connection
+        // dropped: waking the workers up is always safe.
+        new_tasks.notify_waiters();
+    }
+}
+
+async fn connect_listener(pool: &PgPool) -> Option<PgListener> {
+    let listener = async {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(NEW_TASKS_CHANNEL).await?;
+        Ok::<_, sqlx::Error>(listener)
+    };
+    match listener.await {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to listen for new tasks. Falling back to polling.",
+            );
+            None
+        }
+    }
+}
+
+/// How long until the first postponed task can be retried.
+#[tracing::instrument(skip_all)]
+async fn next_task_due_in(
+    pool: &PgPool,
+) -> Result<Option<Duration>, anyhow::Error> {
+    // Tasks of paused issues wait for them to be resumed
+    let r = sqlx::query!(
+        r#"
+        SELECT MIN(q.execute_after) AS "execute_after"
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i
+            ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE i.status = 'sending'
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(r.execute_after.map(|execute_after| {
+        (execute_after - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO)
+    }))
+}
+
+/// Wake up idle workers once the transaction is committed.
+#[tracing::instrument(skip_all)]
+pub async fn notify_new_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT pg_notify($1, '')", NEW_TASKS_CHANNEL)
+        .execute(transaction)
+        .await?;
+    Ok(())
 }
 
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    // We can't send more emails for that long
+    Throttled(Duration),
+}
+
+/// Process up to `batch_size` tasks, one after the other.
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    links: &LinkSettings,
+    settings: &IssueDeliverySettings,
+    rate_limiter: &SendRateLimiter,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    execute_tasks(pool, email_client, links, settings, rate_limiter, || false)
+        .await
+}
+
+// Every task is dequeued, sent and recorded in a transaction of its own:
+// if anything goes wrong midway (or we get aborted), only the email in
+// flight might go out twice. If we are asked to stop, the tasks we
+// haven't dequeued yet are left in the queue.
+#[tracing::instrument(skip_all, fields(n_tasks=tracing::field::Empty), err)]
+async fn execute_tasks(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    links: &LinkSettings,
+    settings: &IssueDeliverySettings,
+    rate_limiter: &SendRateLimiter,
+    should_stop: impl Fn() -> bool,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut n_tasks = 0;
+    while n_tasks < settings.batch_size && !should_stop() {
+        let mut transaction = pool.begin().await?;
+        // Only tasks of issues still being sent are dequeued: pausing
+        // or cancelling an issue stops its deliveries at the next task
+        let Some(task) = dequeue_task(&mut transaction).await? else {
+            break;
+        };
+        // We don't wait for our turn while holding the task:
+        // rolling back leaves it in the queue untouched
+        if let Err(wait_time) = rate_limiter.try_acquire_global() {
+            return Ok(ExecutionOutcome::Throttled(wait_time));
+        }
+        n_tasks += 1;
+        Span::current().record("n_tasks", n_tasks);
+        let issue_id = task.newsletter_issue_id;
+        execute_task(
+            &mut transaction,
+            pool,
+            email_client,
+            links,
+            settings,
+            rate_limiter,
+            task,
+        )
+        .await?;
+        transaction.commit().await?;
+        // Our deletion is visible now: whoever commits the last task
+        // of an issue is the one marking it as completed.
+        mark_completed_issue(pool, issue_id).await?;
+    }
+    if n_tasks == 0 {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn mark_completed_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues i
+        SET status = 'completed'
+        WHERE
+        i.newsletter_issue_id = $1 AND
+        i.status = 'sending' AND
+        NOT EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = i.newsletter_issue_id
+        )
+        "#,
+        issue_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
     skip_all,
     fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
+        newsletter_issue_id=%task.newsletter_issue_id,
+        subscriber_email=%task.subscriber_email,
+        n_retries=task.n_retries
         ),
         err
     )]
-pub async fn try_execute_task(
+async fn execute_task(
+    transaction: &mut PgTransaction,
     pool: &PgPool,
-    email_client: &EmailClient,
-) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
-        return Ok(ExecutionOutcome::EmptyQueue);
-    }
-    let (transaction, issue_id, email) = task.unwrap();
-    Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-
-    // send email
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
+    email_client: &dyn EmailSender,
+    links: &LinkSettings,
+    settings: &IssueDeliverySettings,
+    rate_limiter: &SendRateLimiter,
+    task: DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    let DeliveryTask {
+        newsletter_issue_id: issue_id,
+        subscriber_email: email,
+        n_retries,
+    } = task;
+
+    let outcome =
+        send_issue(pool, email_client, rate_limiter, links, issue_id, &email)
+            .await?;
+    let n_attempts = n_retries + 1;
+    match outcome {
+        DeliveryOutcome::Sent(sent_email) => {
+            record_delivery(
+                transaction,
+                issue_id,
+                &email,
+                DeliveryRecord {
+                    status: "sent",
+                    n_attempts,
+                    provider_message_id: sent_email.message_id,
+                    last_error: None,
+                },
+            )
+            .await?;
+            delete_task(transaction, issue_id, &email).await?;
+        }
+        DeliveryOutcome::Skipped(reason) => {
+            record_delivery(
+                transaction,
+                issue_id,
+                &email,
+                DeliveryRecord {
+                    status: "skipped",
+                    n_attempts: n_retries,
+                    provider_message_id: None,
+                    last_error: Some(reason.into()),
+                },
+            )
+            .await?;
+            delete_task(transaction, issue_id, &email).await?;
+        }
+        DeliveryOutcome::Throttled(wait_time) => {
+            postpone_task(transaction, issue_id, &email, wait_time).await?;
+        }
+        // Going too fast, or a problem with our account, is on us:
+        // it doesn't count as an attempt and everybody holds off
+        DeliveryOutcome::Failed(
+            e @ (SendEmailError::RateLimited { .. }
+            | SendEmailError::AccountError(_)),
+        ) => {
+            let pause = match e {
+                SendEmailError::RateLimited { retry_after } => {
+                    tracing::warn!(
+                        "The email provider rate limited us. Retrying the \
+                        delivery once the pause is over."
+                    );
+                    retry_after.unwrap_or(DEFAULT_RATE_LIMIT_PAUSE)
+                }
+                _ => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "The email provider refused to send on our behalf. \
+                        Retrying the delivery once the pause is over."
+                    );
+                    ACCOUNT_ERROR_PAUSE
+                }
+            };
+            rate_limiter.pause(pause);
+            record_delivery(
+                transaction,
+                issue_id,
+                &email,
+                DeliveryRecord {
+                    status: "retrying",
+                    n_attempts: n_retries,
+                    provider_message_id: None,
+                    last_error: Some(format!("{:?}", e)),
+                },
+            )
+            .await?;
+            postpone_task(transaction, issue_id, &email, pause).await?;
+        }
+        DeliveryOutcome::Failed(e) => {
+            let give_up = if e.is_permanent() {
+                tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "The email provider rejected an issue for a confirmed \
+                subscriber. Giving up.",
+                );
+                true
+            } else if n_attempts as u32 >= settings.max_attempts {
                 tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
                 "Failed to deliver issue to a confirmed subscriber. \
-                Skipping.",
+                Giving up after {} attempts.",
+                n_attempts
                 );
+                true
+            } else {
+                tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to deliver issue to a confirmed subscriber. \
+                Retrying later.",
+                );
+                false
             };
+            record_delivery(
+                transaction,
+                issue_id,
+                &email,
+                DeliveryRecord {
+                    status: if give_up { "failed" } else { "retrying" },
+                    n_attempts,
+                    provider_message_id: None,
+                    last_error: Some(format!("{:?}", e)),
+                },
+            )
+            .await?;
+            if give_up {
+                dead_letter_task(transaction, issue_id, &email, n_attempts, &e)
+                    .await?;
+            } else {
+                let backoff = retry_backoff(settings, n_retries as u32);
+                retry_task(transaction, issue_id, &email, backoff).await?;
+            }
         }
+    }
+    Ok(())
+}
+
+enum DeliveryOutcome {
+    Sent(SentEmail),
+    // There was nobody to deliver the issue to
+    Skipped(&'static str),
+    // The recipient's domain had its share of emails for now
+    Throttled(Duration),
+    Failed(SendEmailError),
+}
+
+/// Try to send an issue to a subscriber.
+///
+/// Errors are failures on our side (e.g. the database),
+/// delivery failures are reported as a `DeliveryOutcome`.
+async fn send_issue(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    rate_limiter: &SendRateLimiter,
+    links: &LinkSettings,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    let email = match SubscriberEmail::parse(email.to_owned()) {
+        Ok(email) => email,
         Err(e) => {
             tracing::error!(
             error.cause_chain = ?e,
@@ -78,41 +657,93 @@ pub async fn try_execute_task(
             "Skipping a confirmed subscriber. \
             Their stored contact details are invalid",
             );
+            return Ok(DeliveryOutcome::Skipped("Invalid email address"));
         }
+    };
+    // The address may have bounced since the issue was published
+    if is_suppressed(pool, email.as_ref()).await? {
+        tracing::info!("Skipping a subscriber whose address is suppressed.");
+        return Ok(DeliveryOutcome::Skipped("Suppressed address"));
     }
+    let subscriber = match get_subscriber(pool, email.as_ref()).await? {
+        Some(subscriber) => subscriber,
+        None => {
+            tracing::info!("Skipping a subscriber who is no longer confirmed.");
+            return Ok(DeliveryOutcome::Skipped("No longer subscribed"));
+        }
+    };
+    let issue = get_issue(pool, issue_id).await?;
+    let attributes = get_template_attributes(pool, subscriber.id).await?;
+    // We don't wait for it while holding the task
+    if let Err(wait_time) = rate_limiter.try_acquire_domain(&email) {
+        return Ok(DeliveryOutcome::Throttled(wait_time));
+    }
+    let tracking = issue.tracking.then_some(TrackedDelivery {
+        newsletter_issue_id: issue_id,
+        subscriber_id: subscriber.id,
+    });
+    let recipient = Recipient {
+        email: &email,
+        name: &subscriber.name,
+        unsubscribe_token: &subscriber.unsubscribe_token,
+        attributes: &attributes,
+        tracking: tracking.as_ref().map(|t| (t, &links.hmac_secret)),
+    };
+    let outcome = send_rendered_issue(
+        email_client,
+        &recipient,
+        &issue.title,
+        &issue,
+        &links.base_url,
+    )
+    .await;
+    Ok(match outcome {
+        Ok(sent_email) => DeliveryOutcome::Sent(sent_email),
+        Err(e) => DeliveryOutcome::Failed(e),
+    })
+}
 
-    delete_task(transaction, issue_id, &email).await?;
+// Never wait more than an hour between two attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
 
-    Ok(ExecutionOutcome::TaskCompleted)
+/// Exponential backoff: the delay doubles after every failed attempt.
+fn retry_backoff(settings: &IssueDeliverySettings, n_retries: u32) -> Duration {
+    let initial = Duration::from_millis(settings.initial_backoff_milliseconds);
+    initial
+        .checked_mul(2u32.saturating_pow(n_retries))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i32,
+}
+
+// Tasks waiting for their backoff to expire are left alone
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
-    pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+    transaction: &mut PgTransaction,
+) -> Result<Option<DeliveryTask>, anyhow::Error> {
+    let task = sqlx::query_as!(
+        DeliveryTask,
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        SELECT q.newsletter_issue_id, q.subscriber_email, q.n_retries
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i
+            ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.execute_after <= now() AND i.status = 'sending'
+        FOR UPDATE OF q
         SKIP LOCKED
         LIMIT 1
         "#,
     )
-    .fetch_optional(&mut transaction)
+    .fetch_optional(transaction)
     .await?;
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
+    Ok(task)
 }
 
 // When you've processed the task
@@ -121,7 +752,7 @@ async fn dequeue_task(
 // `issue_delivery_queue`
 #[tracing::instrument(skip_all)]
 async fn delete_task(
-    mut transaction: PgTransaction,
+    transaction: &mut PgTransaction,
     issue_id: Uuid,
     email: &str,
 ) -> Result<(), anyhow::Error> {
@@ -135,43 +766,493 @@ async fn delete_task(
         issue_id,
         email
     )
-    .execute(&mut transaction)
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn retry_task(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    backoff: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff)?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET n_retries = n_retries + 1, execute_after = $3
+        WHERE
+        newsletter_issue_id = $1 AND
+        subscriber_email = $2
+        "#,
+        issue_id,
+        email,
+        execute_after
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+// Try again later, without counting it as an attempt
+#[tracing::instrument(skip_all)]
+async fn postpone_task(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(delay)?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = $3
+        WHERE
+        newsletter_issue_id = $1 AND
+        subscriber_email = $2
+        "#,
+        issue_id,
+        email,
+        execute_after
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+struct DeliveryRecord {
+    status: &'static str,
+    n_attempts: i32,
+    provider_message_id: Option<String>,
+    last_error: Option<String>,
+}
+
+// Keep track of every attempt in `newsletter_deliveries`
+#[tracing::instrument(skip_all)]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    record: DeliveryRecord,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            status,
+            n_attempts,
+            provider_message_id,
+            last_error,
+            created_at,
+            updated_at,
+            sent_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, now(), now(),
+            CASE WHEN $3 = 'sent' THEN now() END
+        )
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            status = EXCLUDED.status,
+            n_attempts = EXCLUDED.n_attempts,
+            provider_message_id = EXCLUDED.provider_message_id,
+            last_error = EXCLUDED.last_error,
+            updated_at = EXCLUDED.updated_at,
+            sent_at = EXCLUDED.sent_at
+        "#,
+        issue_id,
+        email,
+        record.status,
+        record.n_attempts,
+        record.provider_message_id,
+        record.last_error,
+    )
+    .execute(transaction)
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
 
+// Move the task out of the queue, admins can requeue it later on
+#[tracing::instrument(skip_all)]
+async fn dead_letter_task(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    n_attempts: i32,
+    error: &SendEmailError,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_dead_letters (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        issue_id,
+        email,
+        n_attempts,
+        format!("{:?}", error)
+    )
+    .execute(&mut *transaction)
+    .await?;
+    delete_task(transaction, issue_id, email).await
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    layout_html: Option<String>,
+    layout_text: Option<String>,
+    tracking: bool,
+}
+
+impl NewsletterIssue {
+    fn layout(&self) -> Option<EmailLayout> {
+        Some(EmailLayout {
+            html: self.layout_html.clone()?,
+            text: self.layout_text.clone()?,
+        })
+    }
+
+    /// Render the issue, in its layout, for a single recipient.
+    fn render(
+        &self,
+        variables: &IssueVariables,
+    ) -> Result<RenderedEmail, String> {
+        let rendered = self.render_content(variables)?;
+        Ok(with_unsubscribe_link(rendered, variables.unsubscribe_url))
+    }
+
+    // Without the unsubscribe footer
+    fn render_content(
+        &self,
+        variables: &IssueVariables,
+    ) -> Result<RenderedEmail, String> {
+        let template = EmailTemplate::parse(
+            &self.html_content,
+            &self.text_content,
+            self.layout().as_ref(),
+        )?;
+        template.render(variables)
+    }
+}
+
+/// Append an unsubscribe footer to both the HTML and
+/// the plain text bodies of an email.
+fn with_unsubscribe_link(
+    email: RenderedEmail,
+    unsubscribe_link: &str,
+) -> RenderedEmail {
+    RenderedEmail {
+        html: format!(
+            "{}<p>Don't want to receive these emails anymore? \
+            <a href=\"{}\">Unsubscribe</a>.</p>",
+            email.html, unsubscribe_link
+        ),
+        text: format!(
+            "{}\n\nDon't want to receive these emails anymore? \
+            Visit {} to unsubscribe.",
+            email.text, unsubscribe_link
+        ),
+    }
+}
+
+struct Recipient<'a> {
+    email: &'a SubscriberEmail,
+    name: &'a str,
+    unsubscribe_token: &'a str,
+    attributes: &'a BTreeMap<String, String>,
+    // Set if the issue tracks opens and clicks
+    tracking: Option<(&'a TrackedDelivery, &'a HmacSecret)>,
+}
+
+// Send an issue the way subscribers get it
+async fn send_rendered_issue(
+    email_client: &dyn EmailSender,
+    recipient: &Recipient<'_>,
+    subject: &str,
+    issue: &NewsletterIssue,
+    base_url: &str,
+) -> Result<SentEmail, SendEmailError> {
+    let unsubscribe_token = recipient.unsubscribe_token;
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+        base_url, unsubscribe_token
+    );
+    let variables = IssueVariables {
+        name: recipient.name,
+        email: recipient.email.as_ref(),
+        unsubscribe_url: &unsubscribe_link,
+        attributes: recipient.attributes,
+    };
+    // The templates have been checked when the issue was published
+    let mut rendered = issue
+        .render_content(&variables)
+        .map_err(|e| SendEmailError::UnexpectedError(anyhow::anyhow!(e)))?;
+    // Managing the subscription is never tracked, whether the link
+    // is the one we add or one the layout or content put in
+    if let Some((delivery, secret)) = recipient.tracking {
+        let preferences_link = format!(
+            "{}/subscriptions/preferences?unsubscribe_token={}",
+            base_url, unsubscribe_token
+        );
+        rendered.html = delivery.track(
+            &rendered.html,
+            base_url,
+            secret,
+            &[&unsubscribe_link, &preferences_link],
+        );
+    }
+    let rendered = with_unsubscribe_link(rendered, &unsubscribe_link);
+    // RFC 8058 headers, required by the bulk-sender
+    // guidelines of the major mailbox providers
+    let list_unsubscribe = format!(
+        "<{}/subscriptions/unsubscribe/one-click\
+        ?unsubscribe_token={}>",
+        base_url, unsubscribe_token
+    );
+    let headers = [
+        EmailHeader {
+            name: "List-Unsubscribe",
+            value: &list_unsubscribe,
+        },
+        EmailHeader {
+            name: "List-Unsubscribe-Post",
+            value: "List-Unsubscribe=One-Click",
+        },
+    ];
+    email_client
+        .send_email_with_headers(
+            recipient.email,
+            subject,
+            &rendered.html,
+            &rendered.text,
+            &headers,
+        )
+        .await
+}
+
+/// Send an issue to an editor, as subscribers would get it.
+///
+/// Test emails are not rate limited and go through no queue:
+/// the caller gets to know right away if the provider refused them.
+#[tracing::instrument(skip(pool, email_client, base_url))]
+pub async fn send_test_issue(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    base_url: &str,
+    issue_id: Uuid,
+    recipient: &SubscriberEmail,
+) -> Result<Result<SentEmail, SendEmailError>, anyhow::Error> {
+    let issue = get_issue(pool, issue_id).await?;
+    let attributes = sample_attributes(&get_attribute_definitions(pool).await?);
+    let subject = format!("[Test] {}", issue.title);
+    // Editors are not necessarily subscribers: the unsubscribe
+    // link is there for them to see, it doesn't work
+    let recipient = Recipient {
+        email: recipient,
+        name: IssueVariables::sample().name,
+        unsubscribe_token: TEST_UNSUBSCRIBE_TOKEN,
+        attributes: &attributes,
+        tracking: None,
+    };
+    Ok(send_rendered_issue(
+        email_client,
+        &recipient,
+        &subject,
+        &issue,
+        base_url,
+    )
+    .await)
+}
+
+/// Render an issue for a made-up subscriber, the way previews show it.
+///
+/// Mistakes in the templates of the issue, or of its layout, are
+/// returned as an error message: publishing such an issue would
+/// fail every single delivery.
+#[tracing::instrument(skip(connection))]
+pub async fn render_sample_issue(
+    connection: &mut PgConnection,
+    issue_id: Uuid,
+) -> Result<Result<RenderedEmail, String>, anyhow::Error> {
+    let issue = get_issue(&mut *connection, issue_id).await?;
+    let attributes =
+        sample_attributes(&get_attribute_definitions(connection).await?);
+    Ok(issue.render(&IssueVariables {
+        attributes: &attributes,
+        ..IssueVariables::sample()
+    }))
 }
 
 #[tracing::instrument(skip_all)]
 async fn get_issue(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     issue_id: Uuid,
 ) -> Result<NewsletterIssue, anyhow::Error> {
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
-        FROM newsletter_issues
+        SELECT
+            i.title,
+            i.text_content,
+            i.html_content,
+            l.html_template AS "layout_html?",
+            l.text_template AS "layout_text?",
+            i.tracking
+        FROM newsletter_issues i
+        LEFT JOIN email_layouts l ON l.layout_id = i.layout_id
         WHERE 
-        newsletter_issue_id = $1
+        i.newsletter_issue_id = $1
         "#,
         issue_id
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
     Ok(issue)
 }
 
+struct Subscriber {
+    id: Uuid,
+    name: String,
+    unsubscribe_token: String,
+}
+
+// Subscribers may leave the list after the issue has been
+// enqueued: we only return confirmed subscribers.
+#[tracing::instrument(skip_all)]
+async fn get_subscriber(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT subscriptions.id, subscriptions.name, unsubscribe_token
+        FROM unsubscribe_tokens
+        JOIN subscriptions ON subscriptions.id = unsubscribe_tokens.subscriber_id
+        WHERE
+        subscriptions.email = $1 AND
+        subscriptions.status = 'confirmed'
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(subscriber)
+}
+
+/// Run `n_workers` delivery workers until `stop` is set to `true`.
+///
+/// Workers stop dequeuing straight away but they get up to
+/// `shutdown_timeout_milliseconds` to wrap up the task they are
+/// working on. Past the deadline they are aborted: the task in flight
+/// is rolled back and picked up again on the next start, its email
+/// might go out twice.
 pub async fn run_worker_until_stopped(
     configuration: Settings,
+    mut stop: watch::Receiver<bool>,
 ) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
-
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let settings = configuration.issue_delivery;
+    let rate_limiter =
+        Arc::new(SendRateLimiter::new(settings.rate_limit.clone()));
+
+    let links = LinkSettings {
+        base_url: configuration.application.base_url.clone(),
+        hmac_secret: HmacSecret(configuration.application.hmac_secret.clone()),
+    };
+
+    let new_tasks = Arc::new(Notify::new());
+    let listener = tokio::spawn(listen_for_new_tasks(
+        connection_pool.clone(),
+        new_tasks.clone(),
+    ));
+    let scheduler = tokio::spawn(scheduler_loop(
+        connection_pool.clone(),
+        new_tasks.clone(),
+        stop.clone(),
+    ));
+    let mut workers = JoinSet::new();
+    for _ in 0..settings.n_workers {
+        workers.spawn(worker_loop(
+            connection_pool.clone(),
+            email_client.clone(),
+            links.clone(),
+            settings.clone(),
+            rate_limiter.clone(),
+            new_tasks.clone(),
+            stop.clone(),
+        ));
+    }
+
+    // Either we are asked to stop or the sender is gone
+    let _ = stop.wait_for(|stop| *stop).await;
+    listener.abort();
+    // Publishing is transactional, we can stop at any point
+    scheduler.abort();
+    let wrap_up = async {
+        while let Some(outcome) = workers.join_next().await {
+            outcome??;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    match tokio::time::timeout(settings.shutdown_timeout(), wrap_up).await {
+        Ok(outcome) => outcome,
+        Err(_) => {
+            tracing::warn!(
+                "Delivery workers did not stop in time. \
+                Their current task will be retried."
+            );
+            workers.abort_all();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_backoff, MAX_BACKOFF};
+    use crate::configuration::{IssueDeliverySettings, SendRateLimitSettings};
+    use std::time::Duration;
+
+    fn settings(initial_backoff_milliseconds: u64) -> IssueDeliverySettings {
+        IssueDeliverySettings {
+            n_workers: 1,
+            batch_size: 1,
+            max_attempts: 5,
+            initial_backoff_milliseconds,
+            shutdown_timeout_milliseconds: 1000,
+            rate_limit: SendRateLimitSettings {
+                emails_per_second: 1.,
+                burst: 1.,
+                per_domain_emails_per_second: 1.,
+                per_domain_burst: 1.,
+            },
+        }
+    }
+
+    #[test]
+    fn the_backoff_doubles_after_every_failure() {
+        let settings = settings(1000);
+        assert_eq!(retry_backoff(&settings, 0), Duration::from_secs(1));
+        assert_eq!(retry_backoff(&settings, 1), Duration::from_secs(2));
+        assert_eq!(retry_backoff(&settings, 4), Duration::from_secs(16));
+    }
+
+    #[test]
+    fn the_backoff_is_capped() {
+        let settings = settings(1000);
+        assert_eq!(retry_backoff(&settings, 20), MAX_BACKOFF);
+        assert_eq!(retry_backoff(&settings, 1000), MAX_BACKOFF);
+    }
 }
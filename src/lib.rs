@@ -2,10 +2,16 @@ pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_template;
 pub mod idempotency;
 pub mod issue_delivery_worker;
 pub mod routes;
+pub mod segments;
+pub mod send_rate_limiter;
 pub mod session_state;
 pub mod startup;
+pub mod subscriber_attributes;
+pub mod suppressions;
 pub mod telemetry;
+pub mod tracking;
 pub mod utils;
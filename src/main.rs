@@ -1,4 +1,5 @@
 use std::fmt::{Debug, Display};
+use tokio::sync::watch;
 use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
@@ -20,24 +21,65 @@ async fn main() -> anyhow::Result<()> {
     // Run the API application
     let application = Application::build(configuration.clone()).await?;
     // spawn a `tokio` task
-    let application_task = tokio::spawn(application.run_until_stopped());
+    let mut application_task = tokio::spawn(application.run_until_stopped());
 
     // Run the background worker for processing
     // the newsletters from the queue
     // by spawning it as a `tokio` task
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let (stop_worker, worker_stop) = watch::channel(false);
+    let mut worker_task =
+        tokio::spawn(run_worker_until_stopped(configuration, worker_stop));
 
     // `tokio::select!` will run these tasks concurrently
     // and will return as soon as one of the two tasks completes
-    // or errors out.
+    // or errors out - or as soon as we are asked to shut down.
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
+        o = &mut application_task => report_exit("API", o),
+        o = &mut worker_task => {
+            report_exit("Background worker", o);
+            return Ok(());
+        }
+        _ = shutdown_signal() => {
+            tracing::info!("Received a shutdown signal");
+            let _ = stop_worker.send(true);
+            // The API server catches the signal as well:
+            // it stops accepting connections and drains in-flight requests
+            report_exit("API", application_task.await);
+        }
     };
 
+    // The worker stops dequeuing and gets a chance
+    // to complete in-flight deliveries
+    let _ = stop_worker.send(true);
+    report_exit("Background worker", worker_task.await);
+
     Ok(())
 }
 
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install the Ctrl+C handler.");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(
+            tokio::signal::unix::SignalKind::terminate(),
+        )
+        .expect("Failed to install the SIGTERM handler.")
+        .recv()
+        .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
 fn report_exit(
     task_name: &str,
     outcome: Result<Result<(), impl Debug + Display>, JoinError>,
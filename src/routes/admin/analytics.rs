@@ -0,0 +1,117 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::e500;
+
+struct IssueEngagement {
+    newsletter_issue_id: Uuid,
+    title: String,
+    n_sent: i64,
+    n_opened: i64,
+    n_clicked: i64,
+}
+
+/// How many subscribers opened tracked issues, or clicked their links.
+pub async fn admin_analytics(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut rows = String::new();
+    for issue in get_engagement(&pool).await.map_err(e500)? {
+        writeln!(
+            rows,
+            r#"<tr>
+            <td><a href="/admin/issues/{issue_id}">{title}</a></td>
+            <td>{n_sent}</td>
+            <td>{n_opened} ({open_rate})</td>
+            <td>{n_clicked} ({click_rate})</td>
+        </tr>"#,
+            issue_id = issue.newsletter_issue_id,
+            title = htmlescape::encode_minimal(&issue.title),
+            n_sent = issue.n_sent,
+            n_opened = issue.n_opened,
+            open_rate = rate(issue.n_opened, issue.n_sent),
+            n_clicked = issue.n_clicked,
+            click_rate = rate(issue.n_clicked, issue.n_sent),
+        )
+        .unwrap();
+    }
+    if rows.is_empty() {
+        rows.push_str(r#"<tr><td colspan="4">No tracked issues</td></tr>"#);
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Analytics</title>
+</head>
+<body>
+    <h1>Analytics</h1>
+    <p>Opens are only recorded when readers display images.</p>
+    <table>
+        <tr>
+            <th>Title</th>
+            <th>Sent</th>
+            <th>Unique opens</th>
+            <th>Unique clicks</th>
+        </tr>
+        {rows}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+fn rate(n: i64, n_sent: i64) -> String {
+    if n_sent == 0 {
+        return "-".into();
+    }
+    format!("{:.1}%", n as f64 * 100. / n_sent as f64)
+}
+
+// Readers opening an issue twice, or clicking two links, count once
+#[tracing::instrument(name = "Get the engagement of issues", skip(pool))]
+async fn get_engagement(
+    pool: &PgPool,
+) -> Result<Vec<IssueEngagement>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueEngagement,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            (
+                SELECT COUNT(*) FROM newsletter_deliveries d
+                WHERE
+                d.newsletter_issue_id = i.newsletter_issue_id AND
+                d.status = 'sent'
+            ) AS "n_sent!",
+            (
+                SELECT COUNT(DISTINCT e.subscriber_id) FROM tracking_events e
+                WHERE
+                e.newsletter_issue_id = i.newsletter_issue_id AND
+                e.kind = 'open'
+            ) AS "n_opened!",
+            (
+                SELECT COUNT(DISTINCT e.subscriber_id) FROM tracking_events e
+                WHERE
+                e.newsletter_issue_id = i.newsletter_issue_id AND
+                e.kind = 'click'
+            ) AS "n_clicked!"
+        FROM newsletter_issues i
+        WHERE i.tracking AND i.published_at IS NOT NULL
+        ORDER BY i.published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the engagement of newsletter issues.")?;
+    Ok(issues)
+}
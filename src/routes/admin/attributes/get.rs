@@ -0,0 +1,199 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::domain::{AttributeDefinition, AttributeKind};
+use crate::subscriber_attributes::get_attribute_definitions;
+use crate::utils::e500;
+
+pub async fn admin_attributes(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut attributes = String::new();
+    for definition in get_attribute_definitions(pool.get_ref())
+        .await
+        .map_err(e500)?
+    {
+        writeln!(
+            attributes,
+            r#"<li><a href="/admin/attributes/{}">{}</a>: <code>{}</code>, {}{}</li>"#,
+            definition.attribute_id,
+            htmlescape::encode_minimal(&definition.label),
+            definition.name,
+            definition.kind.as_str(),
+            if definition.required { ", required" } else { "" },
+        )
+        .unwrap();
+    }
+    if attributes.is_empty() {
+        attributes.push_str("<li>No attributes</li>");
+    }
+    let mut kind_options = String::new();
+    for kind in AttributeKind::ALL {
+        write!(
+            kind_options,
+            r#"<option value="{0}">{0}</option>"#,
+            kind.as_str()
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber attributes</title>
+</head>
+<body>
+    {msg_html}
+    <p>Custom fields of subscribers. They show up in the subscription
+    form, issues use them with <code>{{{{ attributes.name }}}}</code>.</p>
+    <ul>
+        {attributes}
+    </ul>
+    <h2>New attribute</h2>
+    <form action="/admin/attributes" method="post">
+        <label>Name (lowercase letters, digits and underscores):<br>
+            <input type="text" placeholder="company" name="name">
+        </label>
+        <br>
+        <label>Kind:
+            <select name="kind">
+                {kind_options}
+            </select>
+        </label>
+        <br>
+        {settings}
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            settings = settings_inputs("", false),
+        )))
+}
+
+pub async fn admin_attribute(
+    attribute_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let attribute_id = attribute_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let attribute = sqlx::query!(
+        r#"
+        SELECT name, label, kind, required
+        FROM subscriber_attributes
+        WHERE attribute_id = $1
+        "#,
+        attribute_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a subscriber attribute.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown attribute"))?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit attribute</title>
+</head>
+<body>
+    {msg_html}
+    <p>Name: <code>{name}</code>, kind: {kind}. They can't be changed:
+    templates and stored values depend on them.</p>
+    <form action="/admin/attributes/{attribute_id}" method="post">
+        {settings}
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/attributes">&lt;- Back</a></p>
+</body>
+</html>"#,
+            name = attribute.name,
+            kind = htmlescape::encode_minimal(&attribute.kind),
+            settings = settings_inputs(&attribute.label, attribute.required),
+        )))
+}
+
+// What can be changed once an attribute exists
+fn settings_inputs(label: &str, required: bool) -> String {
+    format!(
+        r#"<label>Label (shown in forms):<br>
+            <input
+                type="text"
+                placeholder="Company"
+                name="label"
+                value="{label}"
+            >
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="required" value="true"{required}>
+            Required to subscribe
+        </label>
+        <br>"#,
+        label = htmlescape::encode_attribute(label),
+        required = if required { " checked" } else { "" },
+    )
+}
+
+/// The form fields to fill in the attributes of a subscriber,
+/// named after the attributes.
+pub(crate) fn attribute_inputs(
+    definitions: &[AttributeDefinition],
+    values: &BTreeMap<String, String>,
+) -> String {
+    let mut inputs = String::new();
+    for definition in definitions {
+        let value = values
+            .get(&definition.name)
+            .map(String::as_str)
+            .unwrap_or("");
+        let input = match definition.kind {
+            AttributeKind::Boolean => format!(
+                r#"<input type="checkbox" name="{}" value="true"{}>"#,
+                definition.name,
+                if value == "true" { " checked" } else { "" },
+            ),
+            kind => format!(
+                r#"<input type="{}" name="{}" value="{}"{}>"#,
+                match kind {
+                    AttributeKind::Number => r#"number" step="any"#,
+                    AttributeKind::Date => "date",
+                    _ => "text",
+                },
+                definition.name,
+                htmlescape::encode_attribute(value),
+                if definition.required { " required" } else { "" },
+            ),
+        };
+        writeln!(
+            inputs,
+            r#"<label>{}: {}</label>
+        <br>"#,
+            htmlescape::encode_minimal(&definition.label),
+            input,
+        )
+        .unwrap();
+    }
+    inputs
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub(crate) use get::attribute_inputs;
+pub use get::{admin_attribute, admin_attributes};
+pub use post::{create_attribute, update_attribute};
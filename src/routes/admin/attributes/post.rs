@@ -0,0 +1,121 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{AttributeKind, AttributeName};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct NewAttributeFormData {
+    name: String,
+    kind: String,
+    label: String,
+    #[serde(default)]
+    required: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SettingsFormData {
+    label: String,
+    // Checked to make the attribute mandatory
+    #[serde(default)]
+    required: bool,
+}
+
+#[tracing::instrument(name = "Create a subscriber attribute", skip(form, pool))]
+pub async fn create_attribute(
+    form: web::Form<NewAttributeFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let validated = AttributeName::parse(&form.name).and_then(|name| {
+        let kind = AttributeKind::parse(&form.kind)?;
+        let label = validate_label(&form.label)?;
+        Ok((name, kind, label))
+    });
+    let (name, kind, label) = match validated {
+        Ok(valid) => valid,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/attributes"));
+        }
+    };
+    let attribute_id = Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attributes (
+            attribute_id,
+            name,
+            label,
+            kind,
+            required
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        attribute_id,
+        name.as_ref(),
+        label,
+        kind.as_str(),
+        form.required,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store a new subscriber attribute.")
+    .map_err(e500)?;
+    if inserted.rows_affected() == 0 {
+        FlashMessage::error(format!(
+            "There is an attribute named {} already.",
+            name.as_ref()
+        ))
+        .send();
+        return Ok(see_other("/admin/attributes"));
+    }
+    FlashMessage::info("The attribute has been created.").send();
+    Ok(see_other(&format!("/admin/attributes/{}", attribute_id)))
+}
+
+#[tracing::instrument(name = "Update a subscriber attribute", skip(form, pool))]
+pub async fn update_attribute(
+    attribute_id: web::Path<Uuid>,
+    form: web::Form<SettingsFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let attribute_id = attribute_id.into_inner();
+    let back = format!("/admin/attributes/{}", attribute_id);
+    let label = match validate_label(&form.label) {
+        Ok(label) => label,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&back));
+        }
+    };
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriber_attributes
+        SET label = $2, required = $3
+        WHERE attribute_id = $1
+        "#,
+        attribute_id,
+        label,
+        form.required,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update a subscriber attribute.")
+    .map_err(e500)?;
+    if updated.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorNotFound("Unknown attribute"));
+    }
+    FlashMessage::info("The attribute has been saved.").send();
+    Ok(see_other(&back))
+}
+
+fn validate_label(label: &str) -> Result<String, String> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err("The attribute needs a label.".into());
+    }
+    Ok(label.to_owned())
+}
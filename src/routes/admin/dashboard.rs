@@ -2,8 +2,10 @@ use actix_web::http::header::ContentType;
 use actix_web::web;
 use actix_web::HttpResponse;
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use reqwest::header::LOCATION;
 use sqlx::PgPool;
+use std::fmt::Write;
 use uuid::Uuid;
 
 use crate::session_state::TypedSession;
@@ -20,6 +22,20 @@ pub async fn admin_dashboard(
             .insert_header((LOCATION, "/login"))
             .finish());
     };
+    let mut drafts = String::new();
+    for draft in get_drafts(&pool).await.map_err(e500)? {
+        writeln!(
+            drafts,
+            r#"<li><a href="/admin/newsletters/{}/edit">{}</a> (last edited {})</li>"#,
+            draft.newsletter_issue_id,
+            htmlescape::encode_minimal(&draft.title),
+            draft.updated_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    if drafts.is_empty() {
+        drafts = "<li>No drafts</li>".into();
+    }
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -34,13 +50,26 @@ pub async fn admin_dashboard(
     <p>Available actions:</p>
     <ol>
         <li><a href="/admin/password">Change password</a></li>
-        <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/newsletters">Write a newsletter issue</a></li>
+        <li><a href="/admin/issues">Track delivery of issues</a></li>
+        <li><a href="/admin/analytics">See who opens and clicks</a></li>
+        <li><a href="/admin/layouts">Manage email layouts</a></li>
+        <li><a href="/admin/topics">Manage topics</a></li>
+        <li><a href="/admin/segments">Manage segments</a></li>
+        <li><a href="/admin/attributes">Manage subscriber attributes</a></li>
+        <li><a href="/admin/subscribers">Find a subscriber</a></li>
+        <li><a href="/admin/dead-letters">Inspect failed deliveries</a></li>
+        <li><a href="/admin/outbox">Browse the email outbox</a></li>
 <li>
         <form name="logoutForm" action="/admin/logout" method="post">
             <input type="submit" value="Logout">
         </form>
 </li>
     </ol>
+    <p>Drafts:</p>
+    <ul>
+        {drafts}
+    </ul>
 </body>
 </html>"#,
         )))
@@ -64,3 +93,26 @@ pub async fn get_username(
     .context("Failed to perform a query to retrieve a username.")?;
     Ok(row.username)
 }
+
+struct DraftSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<DraftSummary>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT newsletter_issue_id, title, updated_at
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY updated_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve drafts.")?;
+    Ok(drafts)
+}
@@ -0,0 +1,149 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::issue_delivery_worker::notify_new_tasks;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+#[tracing::instrument(
+    name = "Requeue a dead-lettered delivery",
+    skip(form, pool),
+    fields(
+        newsletter_issue_id = %form.newsletter_issue_id,
+        subscriber_email = %form.subscriber_email
+    )
+)]
+pub async fn requeue_dead_letter(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcome =
+        requeue(&pool, form.newsletter_issue_id, &form.subscriber_email)
+            .await
+            .map_err(e500)?;
+    match outcome {
+        RequeueOutcome::Requeued => {
+            FlashMessage::info("The delivery has been requeued.").send()
+        }
+        RequeueOutcome::NotDeadLettered => {
+            FlashMessage::error("The delivery is no longer dead-lettered.")
+                .send()
+        }
+        RequeueOutcome::IssueCancelled => FlashMessage::error(
+            "The issue has been cancelled, its deliveries can't be requeued.",
+        )
+        .send(),
+    }
+    Ok(see_other("/admin/dead-letters"))
+}
+
+enum RequeueOutcome {
+    Requeued,
+    NotDeadLettered,
+    IssueCancelled,
+}
+
+// Move the task back to the queue with a clean slate.
+async fn requeue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<RequeueOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Lock the issue: cancelling it waits for us, then drops
+    // the task we queue
+    let issue = sqlx::query!(
+        r#"
+        SELECT status FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        issue_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look up the issue of a dead-lettered delivery.")?;
+    match issue {
+        None => return Ok(RequeueOutcome::NotDeadLettered),
+        Some(issue) if issue.status == "cancelled" => {
+            return Ok(RequeueOutcome::IssueCancelled)
+        }
+        Some(_) => {}
+    }
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_dead_letters
+        WHERE
+        newsletter_issue_id = $1 AND
+        subscriber_email = $2
+        "#,
+        issue_id,
+        email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove a dead-lettered delivery.")?;
+    if deleted.rows_affected() == 0 {
+        return Ok(RequeueOutcome::NotDeadLettered);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enqueue a dead-lettered delivery.")?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_deliveries
+        SET status = 'pending', n_attempts = 0, updated_at = now()
+        WHERE
+        newsletter_issue_id = $1 AND
+        subscriber_email = $2
+        "#,
+        issue_id,
+        email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to reset the delivery log of a requeued delivery.")?;
+    // The issue might have been completed since the delivery failed
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sending'
+        WHERE newsletter_issue_id = $1 AND status = 'completed'
+        "#,
+        issue_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to resume the issue of a requeued delivery.")?;
+    notify_new_tasks(&mut transaction)
+        .await
+        .context("Failed to notify workers of a requeued delivery.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to requeue a delivery.")?;
+    Ok(RequeueOutcome::Requeued)
+}
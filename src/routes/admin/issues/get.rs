@@ -0,0 +1,386 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::domain::IssueSchedule;
+use crate::utils::e500;
+
+// We don't paginate, searching narrows the list down instead
+const MAX_LISTED_RECIPIENTS: i64 = 100;
+
+struct IssueProgress {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: Option<DateTime<Utc>>,
+    status: String,
+    scheduled_for: Option<DateTime<Utc>>,
+    scheduled_timezone: Option<String>,
+    slug: Option<String>,
+    subscribers_only: bool,
+    n_sent: i64,
+    n_failed: i64,
+    n_pending: i64,
+    n_skipped: i64,
+    n_cancelled: i64,
+}
+
+struct Delivery {
+    subscriber_email: String,
+    status: String,
+    n_attempts: i32,
+    provider_message_id: Option<String>,
+    last_error: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    search: Option<String>,
+}
+
+pub async fn admin_issues(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut rows = String::new();
+    for issue in get_issues_progress(&pool, None).await.map_err(e500)? {
+        writeln!(
+            rows,
+            r#"<tr>
+            <td><a href="/admin/issues/{issue_id}">{title}</a></td>
+            <td>{published_at}</td>
+            <td>{status}</td>
+            <td>{n_sent}</td>
+            <td>{n_failed}</td>
+            <td>{n_pending}</td>
+            <td>{n_skipped}</td>
+        </tr>"#,
+            issue_id = issue.newsletter_issue_id,
+            title = htmlescape::encode_minimal(&issue.title),
+            published_at = issue
+                .published_at
+                .map(|published_at| published_at.to_rfc3339())
+                .unwrap_or_default(),
+            status = issue.status,
+            n_sent = issue.n_sent,
+            n_failed = issue.n_failed,
+            n_pending = issue.n_pending,
+            n_skipped = issue.n_skipped,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter issues</title>
+</head>
+<body>
+    <table>
+        <tr>
+            <th>Issue</th>
+            <th>Published at</th>
+            <th>Status</th>
+            <th>Sent</th>
+            <th>Failed</th>
+            <th>Pending</th>
+            <th>Skipped</th>
+        </tr>
+        {rows}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn admin_issue(
+    issue_id: web::Path<Uuid>,
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let issue_id = issue_id.into_inner();
+    let issue = get_issues_progress(&pool, Some(issue_id))
+        .await
+        .map_err(e500)?
+        .pop()
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown issue"))?;
+    let search = query.0.search.unwrap_or_default();
+    let deliveries = get_deliveries(&pool, issue_id, &search)
+        .await
+        .map_err(e500)?;
+
+    let mut actions = String::new();
+    let schedule = issue
+        .scheduled_for
+        .map(|at| {
+            IssueSchedule::from_stored(at, issue.scheduled_timezone.as_deref())
+        })
+        .transpose()
+        .map_err(e500)?;
+    if let Some(schedule) = &schedule {
+        writeln!(
+            actions,
+            "<p>Scheduled for: {} ({})</p>",
+            schedule.local_time().replace('T', " "),
+            schedule.timezone(),
+        )
+        .unwrap();
+    }
+    if issue.status == "draft" {
+        writeln!(
+            actions,
+            r#"<p><a href="/admin/newsletters/{issue_id}/edit">Edit the draft</a></p>"#,
+        )
+        .unwrap();
+    }
+    if ["scheduled", "unscheduled"].contains(&issue.status.as_str()) {
+        writeln!(
+            actions,
+            r#"<form action="/admin/issues/{issue_id}/schedule" method="post">
+        <label>Send at
+            <input
+                type="datetime-local"
+                name="scheduled_for"
+                value="{local_time}"
+            >
+        </label>
+        <label>Timezone
+            <input
+                type="text"
+                name="timezone"
+                value="{timezone}"
+            >
+        </label>
+        <button type="submit">Reschedule</button>
+    </form>"#,
+            local_time = schedule.map(|s| s.local_time()).unwrap_or_default(),
+            timezone = htmlescape::encode_attribute(
+                issue.scheduled_timezone.as_deref().unwrap_or("UTC")
+            ),
+        )
+        .unwrap();
+    }
+    if issue.status != "draft" {
+        if let (Some(slug), false) = (&issue.slug, issue.subscribers_only) {
+            writeln!(
+                actions,
+                r#"<p>Public page: <a href="/issues/{slug}">/issues/{slug}</a></p>"#,
+            )
+            .unwrap();
+        }
+        writeln!(
+            actions,
+            r#"<form action="/admin/issues/{issue_id}/visibility" method="post">
+        <input hidden type="text" name="subscribers_only" value="{subscribers_only}">
+        <button type="submit">{label}</button>
+    </form>"#,
+            subscribers_only = !issue.subscribers_only,
+            label = if issue.subscribers_only {
+                "Add to the public archive"
+            } else {
+                "Remove from the public archive"
+            },
+        )
+        .unwrap();
+    }
+    let available_actions: &[_] = match issue.status.as_str() {
+        "scheduled" => &["unschedule", "cancel"],
+        "unscheduled" => &["cancel"],
+        "sending" => &["pause", "cancel"],
+        "paused" => &["resume", "cancel"],
+        _ => &[],
+    };
+    for action in available_actions {
+        writeln!(
+            actions,
+            r#"<form action="/admin/issues/{issue_id}/{action}" method="post">
+        <button type="submit">{label}</button>
+    </form>"#,
+            label = match *action {
+                "pause" => "Pause",
+                "resume" => "Resume",
+                "unschedule" => "Unschedule",
+                _ => "Cancel",
+            },
+        )
+        .unwrap();
+    }
+
+    let mut rows = String::new();
+    for d in deliveries {
+        writeln!(
+            rows,
+            r#"<tr>
+            <td>{subscriber_email}</td>
+            <td>{status}</td>
+            <td>{n_attempts}</td>
+            <td>{provider_message_id}</td>
+            <td>{last_error}</td>
+            <td>{updated_at}</td>
+        </tr>"#,
+            subscriber_email = htmlescape::encode_minimal(&d.subscriber_email),
+            status = htmlescape::encode_minimal(&d.status),
+            n_attempts = d.n_attempts,
+            provider_message_id = htmlescape::encode_minimal(
+                d.provider_message_id.as_deref().unwrap_or_default()
+            ),
+            last_error = htmlescape::encode_minimal(
+                d.last_error.as_deref().unwrap_or_default()
+            ),
+            updated_at = d.updated_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    <p>Status: {status}</p>
+    {actions}
+    <ul>
+        <li>Sent: {n_sent}</li>
+        <li>Failed: {n_failed}</li>
+        <li>Pending: {n_pending}</li>
+        <li>Skipped: {n_skipped}</li>
+        <li>Cancelled: {n_cancelled}</li>
+    </ul>
+    <form action="/admin/issues/{issue_id}" method="get">
+        <label>Search recipients
+            <input
+                type="text"
+                name="search"
+                value="{search}"
+            >
+        </label>
+        <button type="submit">Search</button>
+    </form>
+    <table>
+        <tr>
+            <th>Subscriber</th>
+            <th>Status</th>
+            <th>Attempts</th>
+            <th>Provider message ID</th>
+            <th>Last error</th>
+            <th>Updated at</th>
+        </tr>
+        {rows}
+    </table>
+    <p><a href="/admin/issues">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            n_sent = issue.n_sent,
+            n_failed = issue.n_failed,
+            n_pending = issue.n_pending,
+            n_skipped = issue.n_skipped,
+            n_cancelled = issue.n_cancelled,
+            status = issue.status,
+            search = htmlescape::encode_attribute(&search),
+        )))
+}
+
+// Retries still count as pending, they are not done yet.
+// Issues which haven't gone out yet come last.
+#[tracing::instrument(name = "Get the progress of issues", skip(pool))]
+async fn get_issues_progress(
+    pool: &PgPool,
+    issue_id: Option<Uuid>,
+) -> Result<Vec<IssueProgress>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueProgress,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            i.status,
+            i.scheduled_for,
+            i.scheduled_timezone,
+            i.slug,
+            i.subscribers_only,
+            COUNT(*) FILTER (WHERE d.status = 'sent') AS "n_sent!",
+            COUNT(*) FILTER (WHERE d.status = 'failed') AS "n_failed!",
+            COUNT(*) FILTER (
+                WHERE d.status IN ('pending', 'retrying')
+            ) AS "n_pending!",
+            COUNT(*) FILTER (WHERE d.status = 'skipped') AS "n_skipped!",
+            COUNT(*) FILTER (
+                WHERE d.status = 'cancelled'
+            ) AS "n_cancelled!"
+        FROM newsletter_issues i
+        LEFT JOIN newsletter_deliveries d
+            ON d.newsletter_issue_id = i.newsletter_issue_id
+        WHERE $1::uuid IS NULL OR i.newsletter_issue_id = $1
+        GROUP BY i.newsletter_issue_id
+        ORDER BY
+            i.published_at DESC NULLS LAST,
+            i.title,
+            i.newsletter_issue_id
+        "#,
+        issue_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the progress of newsletter issues.")?;
+    Ok(issues)
+}
+
+#[tracing::instrument(name = "Get the deliveries of an issue", skip(pool))]
+async fn get_deliveries(
+    pool: &PgPool,
+    issue_id: Uuid,
+    search: &str,
+) -> Result<Vec<Delivery>, anyhow::Error> {
+    // Match the search term literally
+    let pattern = format!(
+        "%{}%",
+        search
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT
+            subscriber_email,
+            status,
+            n_attempts,
+            provider_message_id,
+            last_error,
+            updated_at
+        FROM newsletter_deliveries
+        WHERE newsletter_issue_id = $1 AND subscriber_email ILIKE $2
+        ORDER BY subscriber_email
+        LIMIT $3
+        "#,
+        issue_id,
+        pattern,
+        MAX_LISTED_RECIPIENTS,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the deliveries of a newsletter issue.")?;
+    Ok(deliveries)
+}
@@ -0,0 +1,8 @@
+mod get;
+mod post;
+
+pub use get::{admin_issue, admin_issues};
+pub use post::{
+    cancel_issue, pause_issue, resume_issue, schedule_issue,
+    set_issue_visibility, unschedule_issue,
+};
@@ -0,0 +1,305 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::IssueSchedule;
+use crate::issue_delivery_worker::notify_new_tasks;
+use crate::utils::{e400, e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleFormData {
+    scheduled_for: String,
+    timezone: String,
+}
+
+#[tracing::instrument(name = "Schedule a newsletter issue", skip(form, pool))]
+pub async fn schedule_issue(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<ScheduleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let schedule = IssueSchedule::parse(&form.scheduled_for, &form.timezone)
+        .map_err(e400)?;
+    if schedule.is_due() {
+        return Err(e400("The scheduled time must be in the future."));
+    }
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'scheduled',
+            scheduled_for = $2,
+            scheduled_timezone = $3
+        WHERE
+        newsletter_issue_id = $1 AND
+        status IN ('scheduled', 'unscheduled')
+        "#,
+        issue_id,
+        schedule.at(),
+        schedule.timezone(),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to schedule a newsletter issue.")
+    .map_err(e500)?;
+    let scheduled = updated.rows_affected() == 1;
+    if scheduled {
+        // The scheduler might have to wake up earlier
+        notify_new_tasks(&mut transaction)
+            .await
+            .context("Failed to notify the scheduler.")
+            .map_err(e500)?;
+    }
+    commit(transaction).await.map_err(e500)?;
+    if scheduled {
+        FlashMessage::info(format!(
+            "The issue has been scheduled for {} ({}).",
+            schedule.local_time().replace('T', " "),
+            schedule.timezone()
+        ))
+        .send();
+    } else {
+        FlashMessage::error("The issue has been published already.").send();
+    }
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct VisibilityFormData {
+    subscribers_only: bool,
+}
+
+/// Add an issue to the public archive, or remove it from there.
+#[tracing::instrument(
+    name = "Change the visibility of a newsletter issue",
+    skip(form, pool)
+)]
+pub async fn set_issue_visibility(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<VisibilityFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET subscribers_only = $2
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        form.subscribers_only,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to change the visibility of a newsletter issue.")
+    .map_err(e500)?;
+    if updated.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorNotFound("Unknown issue"));
+    }
+    if form.subscribers_only {
+        FlashMessage::info(
+            "The issue has been removed from the public archive.",
+        )
+        .send();
+    } else {
+        FlashMessage::info("The issue has been added to the public archive.")
+            .send();
+    }
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(name = "Unschedule a newsletter issue", skip(pool))]
+pub async fn unschedule_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    // The timezone is kept around for the next time it gets scheduled
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'unscheduled', scheduled_for = NULL
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        issue_id,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to unschedule a newsletter issue.")
+    .map_err(e500)?;
+    if updated.rows_affected() == 1 {
+        FlashMessage::info("The issue has been unscheduled.").send();
+    } else {
+        FlashMessage::error("Only a scheduled issue can be unscheduled.")
+            .send();
+    }
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(name = "Pause a newsletter issue", skip(pool))]
+pub async fn pause_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let paused = set_status(&mut transaction, issue_id, &["sending"], "paused")
+        .await
+        .map_err(e500)?;
+    commit(transaction).await.map_err(e500)?;
+    if paused {
+        FlashMessage::info("The issue has been paused.").send();
+    } else {
+        FlashMessage::error("Only an issue being sent can be paused.").send();
+    }
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(name = "Resume a newsletter issue", skip(pool))]
+pub async fn resume_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let resumed =
+        set_status(&mut transaction, issue_id, &["paused"], "sending")
+            .await
+            .map_err(e500)?;
+    if resumed {
+        notify_new_tasks(&mut transaction)
+            .await
+            .context("Failed to notify workers of a resumed issue.")
+            .map_err(e500)?;
+    }
+    commit(transaction).await.map_err(e500)?;
+    if resumed {
+        FlashMessage::info("The issue has been resumed.").send();
+    } else {
+        FlashMessage::error("Only a paused issue can be resumed.").send();
+    }
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(name = "Cancel a newsletter issue", skip(pool))]
+pub async fn cancel_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let cancelled = set_status(
+        &mut transaction,
+        issue_id,
+        &["scheduled", "unscheduled", "sending", "paused"],
+        "cancelled",
+    )
+    .await
+    .map_err(e500)?;
+    // Committed on its own: workers stop picking the issue's tasks
+    // straight away, instead of once we get hold of the queue
+    commit(transaction).await.map_err(e500)?;
+    if cancelled {
+        let mut transaction = begin(&pool).await.map_err(e500)?;
+        drop_pending_deliveries(&mut transaction, issue_id)
+            .await
+            .map_err(e500)?;
+        commit(transaction).await.map_err(e500)?;
+        FlashMessage::info("The issue has been cancelled.").send();
+    } else {
+        FlashMessage::error(
+            "The issue has been cancelled or completed already.",
+        )
+        .send();
+    }
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+async fn begin(
+    pool: &PgPool,
+) -> Result<Transaction<'static, Postgres>, anyhow::Error> {
+    pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+}
+
+async fn commit(
+    transaction: Transaction<'static, Postgres>,
+) -> Result<(), anyhow::Error> {
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update an issue.")
+}
+
+// Returns `false` if the issue wasn't in one of the `from` states.
+async fn set_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    from: &[&str],
+    to: &str,
+) -> Result<bool, anyhow::Error> {
+    // The query macro only takes owned strings for text arrays
+    let from: Vec<String> = from.iter().map(|s| s.to_string()).collect();
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $3
+        WHERE newsletter_issue_id = $1 AND status = ANY($2)
+        "#,
+        issue_id,
+        &from,
+        to,
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to update the status of a newsletter issue.")?;
+    Ok(updated.rows_affected() == 1)
+}
+
+// The issue is cancelled already: workers are done with the task they
+// hold once we get its lock, what is left in the queue or dead-lettered
+// is never going to be sent.
+async fn drop_pending_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove the delivery tasks of a cancelled issue.")?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_deliveries
+        SET status = 'cancelled', updated_at = now()
+        WHERE
+        newsletter_issue_id = $1 AND
+        status IN ('pending', 'retrying')
+        "#,
+        issue_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the delivery log of a cancelled issue.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_dead_letters
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove the dead letters of a cancelled issue.")?;
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::e500;
+
+const TEMPLATE_HELP: &str = "Layouts are Handlebars templates: include \
+    the content of the issue with <code>{{ content }}</code>, personalize \
+    it with <code>{{ name }}</code>, <code>{{ email }}</code>, \
+    <code>{{ unsubscribe_url }}</code> and custom attributes such as \
+    <code>{{ attributes.company }}</code>.";
+
+pub struct Layout {
+    pub layout_id: Uuid,
+    pub name: String,
+}
+
+pub async fn admin_layouts(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut layouts = String::new();
+    for layout in get_layouts(&pool).await.map_err(e500)? {
+        writeln!(
+            layouts,
+            r#"<li><a href="/admin/layouts/{}">{}</a></li>"#,
+            layout.layout_id,
+            htmlescape::encode_minimal(&layout.name),
+        )
+        .unwrap();
+    }
+    if layouts.is_empty() {
+        layouts.push_str("<li>No layouts</li>");
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email layouts</title>
+</head>
+<body>
+    {msg_html}
+    <ul>
+        {layouts}
+    </ul>
+    <h2>New layout</h2>
+    {form}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            form = layout_form("/admin/layouts", "", "", ""),
+        )))
+}
+
+pub async fn admin_layout(
+    layout_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout_id = layout_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let layout = sqlx::query!(
+        r#"
+        SELECT name, html_template, text_template
+        FROM email_layouts
+        WHERE layout_id = $1
+        "#,
+        layout_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve an email layout.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown layout"))?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit layout</title>
+</head>
+<body>
+    {msg_html}
+    {form}
+    <p><a href="/admin/layouts">&lt;- Back</a></p>
+</body>
+</html>"#,
+            form = layout_form(
+                &format!("/admin/layouts/{}", layout_id),
+                &layout.name,
+                &layout.html_template,
+                &layout.text_template,
+            ),
+        )))
+}
+
+fn layout_form(
+    action: &str,
+    name: &str,
+    html_template: &str,
+    text_template: &str,
+) -> String {
+    format!(
+        r#"<p>{TEMPLATE_HELP}</p>
+    <form action="{action}" method="post">
+        <label>Name:<br>
+            <input
+                type="text"
+                placeholder="Enter the layout name"
+                name="name"
+                value="{name}"
+            >
+        </label>
+        <br>
+        <label>HTML template:<br>
+            <textarea
+                placeholder="Enter the layout in HTML format"
+                name="html_template"
+                rows="20"
+                cols="50"
+            >{html_template}</textarea>
+        </label>
+        <br>
+        <label>Plain text template:<br>
+            <textarea
+                placeholder="Enter the layout in plain text"
+                name="text_template"
+                rows="20"
+                cols="50"
+            >{text_template}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>"#,
+        name = htmlescape::encode_attribute(name),
+        html_template = htmlescape::encode_minimal(html_template),
+        text_template = htmlescape::encode_minimal(text_template),
+    )
+}
+
+#[tracing::instrument(name = "Get email layouts", skip(pool))]
+pub async fn get_layouts(pool: &PgPool) -> Result<Vec<Layout>, anyhow::Error> {
+    let layouts = sqlx::query_as!(
+        Layout,
+        r#"
+        SELECT layout_id, name
+        FROM email_layouts
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve email layouts.")?;
+    Ok(layouts)
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub use get::{admin_layout, admin_layouts};
+pub(super) use get::{get_layouts, Layout};
+pub use post::{create_layout, update_layout};
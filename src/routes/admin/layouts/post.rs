@@ -0,0 +1,164 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::email_template::{EmailLayout, IssueVariables};
+use crate::subscriber_attributes::{
+    get_attribute_definitions, sample_attributes,
+};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    html_template: String,
+    text_template: String,
+}
+
+#[tracing::instrument(name = "Create an email layout", skip(form, pool))]
+pub async fn create_layout(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let attributes = get_sample_attributes(&pool).await?;
+    let (name, layout) = match validate(form.0, &attributes) {
+        Ok(valid) => valid,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/layouts"));
+        }
+    };
+    if is_name_taken(&pool, &name, None).await.map_err(e500)? {
+        FlashMessage::error(name_taken_message(&name)).send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    let layout_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_layouts(
+            layout_id, name, html_template, text_template
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        layout_id,
+        name,
+        layout.html,
+        layout.text,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store a new email layout.")
+    .map_err(e500)?;
+    FlashMessage::info("The layout has been created.").send();
+    Ok(see_other(&format!("/admin/layouts/{}", layout_id)))
+}
+
+/// Issues using the layout, even those being sent,
+/// pick the changes up.
+#[tracing::instrument(name = "Update an email layout", skip(form, pool))]
+pub async fn update_layout(
+    layout_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout_id = layout_id.into_inner();
+    let back = format!("/admin/layouts/{}", layout_id);
+    let attributes = get_sample_attributes(&pool).await?;
+    let (name, layout) = match validate(form.0, &attributes) {
+        Ok(valid) => valid,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&back));
+        }
+    };
+    if is_name_taken(&pool, &name, Some(layout_id))
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(name_taken_message(&name)).send();
+        return Ok(see_other(&back));
+    }
+    let updated = sqlx::query!(
+        r#"
+        UPDATE email_layouts
+        SET
+            name = $2,
+            html_template = $3,
+            text_template = $4,
+            updated_at = now()
+        WHERE layout_id = $1
+        "#,
+        layout_id,
+        name,
+        layout.html,
+        layout.text,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update an email layout.")
+    .map_err(e500)?;
+    if updated.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorNotFound("Unknown layout"));
+    }
+    FlashMessage::info("The layout has been saved.").send();
+    Ok(see_other(&back))
+}
+
+// Error messages are HTML, ready to be flashed
+fn validate(
+    form: FormData,
+    attributes: &BTreeMap<String, String>,
+) -> Result<(String, EmailLayout), String> {
+    let name = form.name.trim().to_owned();
+    if name.is_empty() {
+        return Err("The layout needs a name.".into());
+    }
+    let sample = IssueVariables {
+        attributes,
+        ..IssueVariables::sample()
+    };
+    let layout =
+        EmailLayout::parse(form.html_template, form.text_template, &sample)
+            .map_err(|e| htmlescape::encode_minimal(&e))?;
+    Ok((name, layout))
+}
+
+async fn get_sample_attributes(
+    pool: &PgPool,
+) -> Result<BTreeMap<String, String>, actix_web::Error> {
+    let definitions = get_attribute_definitions(pool)
+        .await
+        .context("Failed to retrieve the subscriber attributes.")
+        .map_err(e500)?;
+    Ok(sample_attributes(&definitions))
+}
+
+fn name_taken_message(name: &str) -> String {
+    format!(
+        "There is a layout named {} already.",
+        htmlescape::encode_minimal(name)
+    )
+}
+
+#[tracing::instrument(skip(pool))]
+async fn is_name_taken(
+    pool: &PgPool,
+    name: &str,
+    layout_id: Option<Uuid>,
+) -> Result<bool, anyhow::Error> {
+    let layout = sqlx::query!(
+        r#"
+        SELECT layout_id FROM email_layouts
+        WHERE name = $1 AND layout_id IS DISTINCT FROM $2
+        "#,
+        name,
+        layout_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up email layouts by name.")?;
+    Ok(layout.is_some())
+}
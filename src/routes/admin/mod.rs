@@ -1,9 +1,27 @@
+mod analytics;
+mod attributes;
 mod dashboard;
+mod dead_letters;
+mod issues;
+mod layouts;
 mod logout;
 mod newsletter;
+mod outbox;
 mod password;
+mod segments;
+mod subscribers;
+mod topics;
 
+pub use analytics::admin_analytics;
+pub use attributes::*;
 pub use dashboard::admin_dashboard;
+pub use dead_letters::*;
+pub use issues::*;
+pub use layouts::*;
 pub use logout::log_out;
 pub use newsletter::*;
+pub use outbox::{admin_outbox, admin_outbox_message};
 pub use password::*;
+pub use segments::*;
+pub use subscribers::*;
+pub use topics::*;
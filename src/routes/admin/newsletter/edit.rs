@@ -0,0 +1,544 @@
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::get::{
+    count_recipients_script, layout_options, segment_options, topic_options,
+};
+use super::post::{
+    check_templates, parse_optional_id, parse_schedule, release_draft,
+    success_message, SubmitAction,
+};
+use crate::authentication::UserId;
+use crate::domain::IssueContent;
+use crate::routes::admin::layouts::get_layouts;
+use crate::routes::admin::segments::get_segments;
+use crate::routes::admin::topics::get_topics;
+use crate::utils::{e400, e500, see_other};
+
+// How often the edit page saves changes on its own
+const AUTOSAVE_INTERVAL_MILLISECONDS: u64 = 10_000;
+
+const CONFLICT_MESSAGE: &str = "This draft has been changed by someone \
+    else since you opened it. Saving again will overwrite their changes.";
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    markdown_content: String,
+    // Left empty for no layout
+    #[serde(default)]
+    layout_id: String,
+    // Left empty to send the issue to every subscriber
+    #[serde(default)]
+    topic_id: String,
+    // Left empty to send the issue to the whole topic
+    #[serde(default)]
+    segment_id: String,
+    #[serde(default)]
+    subscribers_only: bool,
+    #[serde(default)]
+    tracking: bool,
+    // The revision the editor started from
+    revision: i32,
+    #[serde(default)]
+    scheduled_for: String,
+    #[serde(default)]
+    timezone: String,
+    // Comma-separated, for the "Send test" button
+    #[serde(default)]
+    pub(super) test_recipients: String,
+}
+
+struct Draft {
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: String,
+    layout_id: Option<Uuid>,
+    topic_id: Option<Uuid>,
+    segment_id: Option<Uuid>,
+    subscribers_only: bool,
+    tracking: bool,
+    revision: i32,
+    test_recipients: String,
+}
+
+pub(super) enum SaveOutcome {
+    Saved { revision: i32 },
+    // Somebody else saved the draft in the meantime
+    Conflict { revision: i32 },
+    NotADraft,
+}
+
+pub async fn edit_draft_form(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let issue = sqlx::query!(
+        r#"
+        SELECT
+            title, text_content, html_content, markdown_content,
+            layout_id, topic_id, segment_id, subscribers_only, tracking,
+            revision, status
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a newsletter issue.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown issue"))?;
+    if issue.status != "draft" {
+        FlashMessage::error("Only drafts can be edited.").send();
+        return Ok(see_other(&format!("/admin/issues/{}", issue_id)));
+    }
+    let draft = Draft {
+        title: issue.title,
+        text_content: issue.text_content,
+        html_content: issue.html_content,
+        markdown_content: issue.markdown_content.unwrap_or_default(),
+        layout_id: issue.layout_id,
+        topic_id: issue.topic_id,
+        segment_id: issue.segment_id,
+        subscribers_only: issue.subscribers_only,
+        tracking: issue.tracking,
+        revision: issue.revision,
+        // Editors usually send tests to the same addresses
+        test_recipients: get_last_test_recipients(&pool, **user_id)
+            .await
+            .map_err(e500)?
+            .join(", "),
+    };
+    edit_page(&pool, StatusCode::OK, issue_id, &draft, &msg_html).await
+}
+
+#[tracing::instrument(name = "Save a draft", skip(form, pool))]
+pub async fn edit_draft(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let outcome = save_draft(&mut transaction, issue_id, &form).await?;
+    commit(transaction).await.map_err(e500)?;
+    match outcome {
+        SaveOutcome::Saved { .. } => {
+            success_message(SubmitAction::SaveDraft, None).send();
+            Ok(see_other(&format!("/admin/newsletters/{}/edit", issue_id)))
+        }
+        SaveOutcome::Conflict { revision } => {
+            conflict_page(&pool, issue_id, form.0, revision).await
+        }
+        SaveOutcome::NotADraft => {
+            FlashMessage::error("Only drafts can be edited.").send();
+            Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+        }
+    }
+}
+
+/// Called in the background by the edit page.
+/// Returns the new revision of the draft.
+#[tracing::instrument(name = "Autosave a draft", skip(form, pool))]
+pub async fn autosave_draft(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    let outcome = save_draft(&mut transaction, issue_id, &form).await?;
+    commit(transaction).await.map_err(e500)?;
+    Ok(match outcome {
+        SaveOutcome::Saved { revision } => {
+            HttpResponse::Ok().body(revision.to_string())
+        }
+        SaveOutcome::Conflict { .. } => {
+            HttpResponse::Conflict().body(CONFLICT_MESSAGE)
+        }
+        SaveOutcome::NotADraft => HttpResponse::Conflict()
+            .body("This issue is no longer a draft, it can't be edited."),
+    })
+}
+
+/// Save the latest changes to a draft and publish (or schedule) it.
+#[tracing::instrument(name = "Publish a draft", skip(form, pool))]
+pub async fn publish_draft(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let schedule =
+        parse_schedule(&form.scheduled_for, &form.timezone).map_err(e400)?;
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    match save_draft(&mut transaction, issue_id, &form).await? {
+        SaveOutcome::Saved { .. } => {}
+        SaveOutcome::Conflict { revision } => {
+            return conflict_page(&pool, issue_id, form.0, revision).await;
+        }
+        SaveOutcome::NotADraft => {
+            FlashMessage::error("The issue has been published already.").send();
+            return Ok(see_other(&format!("/admin/issues/{}", issue_id)));
+        }
+    }
+    check_templates(&mut transaction, issue_id).await?;
+    release_draft(&mut transaction, issue_id, schedule.as_ref())
+        .await
+        .map_err(e500)?;
+    commit(transaction).await.map_err(e500)?;
+    success_message(SubmitAction::Publish, schedule.as_ref()).send();
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+pub(super) async fn begin(
+    pool: &PgPool,
+) -> Result<Transaction<'static, Postgres>, anyhow::Error> {
+    pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+}
+
+pub(super) async fn commit(
+    transaction: Transaction<'static, Postgres>,
+) -> Result<(), anyhow::Error> {
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save a draft.")
+}
+
+// The draft is only updated if nobody saved it
+// since `form.revision`.
+pub(super) async fn save_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    form: &DraftFormData,
+) -> Result<SaveOutcome, actix_web::Error> {
+    let layout_id =
+        parse_optional_id(&form.layout_id, "layout").map_err(e400)?;
+    let topic_id = parse_optional_id(&form.topic_id, "topic").map_err(e400)?;
+    let segment_id =
+        parse_optional_id(&form.segment_id, "segment").map_err(e400)?;
+    let content = IssueContent::new(
+        &form.markdown_content,
+        form.text_content.clone(),
+        form.html_content.clone(),
+    );
+    let saved = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            markdown_content = $6,
+            layout_id = $7,
+            subscribers_only = $8,
+            tracking = $9,
+            topic_id = $10,
+            segment_id = $11,
+            revision = revision + 1,
+            updated_at = now()
+        WHERE
+        newsletter_issue_id = $1 AND
+        status = 'draft' AND
+        revision = $5
+        RETURNING revision
+        "#,
+        issue_id,
+        form.title,
+        content.text(),
+        content.html(),
+        form.revision,
+        content.markdown(),
+        layout_id,
+        form.subscribers_only,
+        form.tracking,
+        topic_id,
+        segment_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to save a draft.")
+    .map_err(e500)?;
+    if let Some(saved) = saved {
+        return Ok(SaveOutcome::Saved {
+            revision: saved.revision,
+        });
+    }
+    let current = sqlx::query!(
+        r#"
+        SELECT status, revision FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the current revision of a draft.")
+    .map_err(e500)?;
+    Ok(match current {
+        Some(current) if current.status == "draft" => SaveOutcome::Conflict {
+            revision: current.revision,
+        },
+        _ => SaveOutcome::NotADraft,
+    })
+}
+
+// Give the editor their changes back, on top of the latest revision
+pub(super) async fn conflict_page(
+    pool: &PgPool,
+    issue_id: Uuid,
+    form: DraftFormData,
+    revision: i32,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = Draft {
+        layout_id: parse_optional_id(&form.layout_id, "layout")
+            .map_err(e400)?,
+        topic_id: parse_optional_id(&form.topic_id, "topic").map_err(e400)?,
+        segment_id: parse_optional_id(&form.segment_id, "segment")
+            .map_err(e400)?,
+        subscribers_only: form.subscribers_only,
+        tracking: form.tracking,
+        title: form.title,
+        text_content: form.text_content,
+        html_content: form.html_content,
+        markdown_content: form.markdown_content,
+        revision,
+        test_recipients: form.test_recipients,
+    };
+    edit_page(
+        pool,
+        StatusCode::CONFLICT,
+        issue_id,
+        &draft,
+        &format!("<p><i>{}</i></p>", CONFLICT_MESSAGE),
+    )
+    .await
+}
+
+async fn edit_page(
+    pool: &PgPool,
+    status: StatusCode,
+    issue_id: Uuid,
+    draft: &Draft,
+    msg_html: &str,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layouts = get_layouts(pool).await.map_err(e500)?;
+    let topics = get_topics(pool).await.map_err(e500)?;
+    let segments = get_segments(pool).await.map_err(e500)?;
+    Ok(HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit draft</title>
+</head>
+<body>
+    {msg_html}
+    <form id="draft" action="/admin/newsletters/{issue_id}/edit" method="post">
+        <label>Title:<br>
+            <input
+                type="text"
+                placeholder="Enter the issue title"
+                name="title"
+                value="{title}"
+            >
+        </label>
+        <br>
+        <label>Markdown content (if filled in, the plain text and HTML
+            content are generated out of it):<br>
+            <textarea
+                placeholder="Enter the content in Markdown"
+                name="markdown_content"
+                rows="20"
+                cols="50"
+            >{markdown_content}</textarea>
+        </label>
+        <br>
+        <label>Plain text content:<br>
+            <textarea
+                placeholder="Enter the content in plain text"
+                name="text_content"
+                rows="20"
+                cols="50"
+            >{text_content}</textarea>
+        </label>
+        <br>
+        <label>HTML content:<br>
+            <textarea
+                placeholder="Enter the content in HTML format"
+                name="html_content"
+                rows="20"
+                cols="50"
+            >{html_content}</textarea>
+        </label>
+        <br>
+        <label>Layout:
+            <select name="layout_id">
+                {layout_options}
+            </select>
+        </label>
+        <br>
+        <label>Send to:
+            <select name="topic_id">
+                {topic_options}
+            </select>
+        </label>
+        <label>Segment:
+            <select name="segment_id">
+                {segment_options}
+            </select>
+        </label>
+        <button type="button" id="count-recipients">Preview recipient count</button>
+        <span id="recipient-count"></span>
+        <br>
+        <label>
+            <input
+                type="checkbox"
+                name="subscribers_only"
+                value="true"{subscribers_only}
+            >
+            Subscribers only (leave it out of the public archive)
+        </label>
+        <br>
+        <label>
+            <input
+                type="checkbox"
+                name="tracking"
+                value="true"{tracking}
+            >
+            Track opens and clicks
+        </label>
+        <br>
+        <label>Schedule for (leave empty to publish right away):<br>
+            <input
+                type="datetime-local"
+                name="scheduled_for"
+            >
+        </label>
+        <label>Timezone:
+            <input
+                type="text"
+                placeholder="Europe/Rome"
+                name="timezone"
+                value="UTC"
+            >
+        </label>
+        <br>
+        <label>Send a test to:
+            <input
+                type="text"
+                placeholder="editor@example.com, ..."
+                name="test_recipients"
+                value="{test_recipients}"
+            >
+        </label>
+        <button
+            type="submit"
+            formaction="/admin/newsletters/{issue_id}/test"
+        >Send test</button>
+        <br>
+        <input hidden type="text" name="revision" value="{revision}">
+        <button type="submit">Save</button>
+        <button
+            type="submit"
+            formaction="/admin/newsletters/{issue_id}/publish"
+        >Publish</button>
+    </form>
+    <p id="autosave-status"></p>
+    {count_recipients_script}
+    <p><a href="/admin/newsletters/{issue_id}/preview">Preview</a></p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    <script>
+        const form = document.getElementById("draft");
+        const autosaveStatus = document.getElementById("autosave-status");
+        let dirty = false;
+        form.addEventListener("input", () => {{ dirty = true; }});
+        setInterval(async () => {{
+            if (!dirty) {{
+                return;
+            }}
+            dirty = false;
+            const response = await fetch(
+                "/admin/newsletters/{issue_id}/autosave",
+                {{ method: "POST", body: new URLSearchParams(new FormData(form)) }}
+            );
+            if (response.ok) {{
+                form.elements.revision.value = await response.text();
+                autosaveStatus.textContent =
+                    "Saved at " + new Date().toLocaleTimeString();
+            }} else {{
+                autosaveStatus.textContent = await response.text();
+            }}
+        }}, {AUTOSAVE_INTERVAL_MILLISECONDS});
+    </script>
+</body>
+</html>"#,
+            title = htmlescape::encode_attribute(&draft.title),
+            text_content = htmlescape::encode_minimal(&draft.text_content),
+            html_content = htmlescape::encode_minimal(&draft.html_content),
+            markdown_content =
+                htmlescape::encode_minimal(&draft.markdown_content),
+            revision = draft.revision,
+            test_recipients =
+                htmlescape::encode_attribute(&draft.test_recipients),
+            layout_options = layout_options(&layouts, draft.layout_id),
+            topic_options = topic_options(&topics, draft.topic_id),
+            segment_options = segment_options(&segments, draft.segment_id),
+            count_recipients_script = count_recipients_script("draft"),
+            subscribers_only = if draft.subscribers_only {
+                " checked"
+            } else {
+                ""
+            },
+            tracking = if draft.tracking { " checked" } else { "" },
+        )))
+}
+
+#[tracing::instrument(name = "Get the last test recipients", skip(pool))]
+async fn get_last_test_recipients(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    let recipients = sqlx::query!(
+        r#"
+        SELECT recipient
+        FROM newsletter_test_emails
+        WHERE sent_at = (
+            SELECT MAX(sent_at) FROM newsletter_test_emails
+            WHERE sent_by = $1
+        ) AND sent_by = $1
+        ORDER BY recipient
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the last test recipients.")?;
+    Ok(recipients.into_iter().map(|r| r.recipient).collect())
+}
@@ -1,9 +1,17 @@
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::routes::admin::layouts::{get_layouts, Layout};
+use crate::routes::admin::segments::{get_segments, SegmentSummary};
+use crate::routes::admin::topics::{get_topics, Topic};
+use crate::utils::e500;
 
 pub async fn publish_newsletter_form(
+    pool: web::Data<PgPool>,
     flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
@@ -11,6 +19,13 @@ pub async fn publish_newsletter_form(
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    let layouts = get_layouts(&pool).await.map_err(e500)?;
+    let layout_options = layout_options(&layouts, None);
+    let topics = get_topics(&pool).await.map_err(e500)?;
+    let topic_options = topic_options(&topics, None);
+    let segments = get_segments(&pool).await.map_err(e500)?;
+    let segment_options = segment_options(&segments, None);
+    let count_recipients_script = count_recipients_script("publish");
     let idempotency_key = uuid::Uuid::new_v4();
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -23,7 +38,7 @@ pub async fn publish_newsletter_form(
 </head>
 <body>
     {msg_html}
-    <form action="/admin/newsletters" method="post">
+    <form id="publish" action="/admin/newsletters" method="post">
         <label>Title:<br>
             <input
                 type="text"
@@ -32,6 +47,16 @@ pub async fn publish_newsletter_form(
             >
         </label>
         <br>
+        <label>Markdown content (if filled in, the plain text and HTML
+            content are generated out of it):<br>
+            <textarea
+                placeholder="Enter the content in Markdown"
+                name="markdown_content"
+                rows="20"
+                cols="50"
+            ></textarea>
+        </label>
+        <br>
         <label>Plain text content:<br>
             <textarea
                 placeholder="Enter the content in plain text"
@@ -50,11 +75,166 @@ pub async fn publish_newsletter_form(
             ></textarea>
         </label>
         <br>
+        <label>Layout:
+            <select name="layout_id">
+                {layout_options}
+            </select>
+        </label>
+        <br>
+        <label>Send to:
+            <select name="topic_id">
+                {topic_options}
+            </select>
+        </label>
+        <label>Segment:
+            <select name="segment_id">
+                {segment_options}
+            </select>
+        </label>
+        <button type="button" id="count-recipients">Preview recipient count</button>
+        <span id="recipient-count"></span>
+        <br>
+        <label>
+            <input
+                type="checkbox"
+                name="subscribers_only"
+                value="true"
+            >
+            Subscribers only (leave it out of the public archive)
+        </label>
+        <br>
+        <label>
+            <input
+                type="checkbox"
+                name="tracking"
+                value="true"
+            >
+            Track opens and clicks
+        </label>
+        <br>
+        <label>Schedule for (leave empty to publish right away):<br>
+            <input
+                type="datetime-local"
+                name="scheduled_for"
+            >
+        </label>
+        <label>Timezone:
+            <input
+                type="text"
+                placeholder="Europe/Rome"
+                name="timezone"
+                value="UTC"
+            >
+        </label>
+        <br>
+        <label>Send a test to:
+            <input
+                type="text"
+                placeholder="editor@example.com, ..."
+                name="test_recipients"
+            >
+        </label>
+        <button type="submit" name="action" value="send_test">Send test</button>
+        <br>
         <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
-        <button type="submit">Publish</button>
+        <button type="submit" name="action" value="save_draft">Save draft</button>
+        <button type="submit" name="action" value="publish">Publish</button>
     </form>
+    {count_recipients_script}
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>"#,
         )))
 }
+
+/// The `<option>`s to pick the layout of an issue.
+pub(super) fn layout_options(
+    layouts: &[Layout],
+    selected: Option<Uuid>,
+) -> String {
+    let mut options = String::from(r#"<option value="">No layout</option>"#);
+    for layout in layouts {
+        write!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            layout.layout_id,
+            if selected == Some(layout.layout_id) {
+                " selected"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&layout.name),
+        )
+        .unwrap();
+    }
+    options
+}
+
+/// The `<option>`s to pick who an issue goes out to.
+pub(super) fn topic_options(
+    topics: &[Topic],
+    selected: Option<Uuid>,
+) -> String {
+    let mut options =
+        String::from(r#"<option value="">Every subscriber</option>"#);
+    for topic in topics {
+        write!(
+            options,
+            r#"<option value="{}"{}>{} ({} subscribers)</option>"#,
+            topic.topic_id,
+            if selected == Some(topic.topic_id) {
+                " selected"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&topic.name),
+            topic.n_subscribers,
+        )
+        .unwrap();
+    }
+    options
+}
+
+/// The `<option>`s to narrow down who an issue goes out to.
+pub(super) fn segment_options(
+    segments: &[SegmentSummary],
+    selected: Option<Uuid>,
+) -> String {
+    let mut options = String::from(r#"<option value="">No segment</option>"#);
+    for segment in segments {
+        write!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            segment.segment_id,
+            if selected == Some(segment.segment_id) {
+                " selected"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&segment.name),
+        )
+        .unwrap();
+    }
+    options
+}
+
+/// Ask how many subscribers the audience picked in the form
+/// (with the id `form_id`) has, without leaving the page.
+pub(super) fn count_recipients_script(form_id: &str) -> String {
+    format!(
+        r#"<script>
+        document.getElementById("count-recipients").addEventListener(
+            "click",
+            async () => {{
+                const form = document.getElementById("{form_id}");
+                const response = await fetch(
+                    "/admin/newsletters/recipients",
+                    {{ method: "POST", body: new URLSearchParams(new FormData(form)) }}
+                );
+                document.getElementById("recipient-count").textContent =
+                    await response.text();
+            }}
+        );
+    </script>"#
+    )
+}
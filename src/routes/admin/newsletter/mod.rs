@@ -1,5 +1,14 @@
+mod edit;
 mod get;
 mod post;
+mod preview;
+mod recipients;
+mod test_email;
 
+pub use edit::{autosave_draft, edit_draft, edit_draft_form, publish_draft};
 pub use get::publish_newsletter_form;
+pub(crate) use post::parse_optional_id;
 pub use post::publish_newsletter;
+pub use preview::preview_newsletter;
+pub use recipients::count_recipients;
+pub use test_email::send_test_email;
@@ -1,7 +1,14 @@
+use super::test_email::{parse_recipients, send_test_emails};
 use crate::authentication::UserId;
+use crate::domain::{IssueContent, IssueSchedule};
+use crate::email_client::EmailSender;
 use crate::idempotency::{
     save_response, try_processing, IdempotencyKey, NextAction,
 };
+use crate::issue_delivery_worker::{
+    notify_new_tasks, publish_issue, render_sample_issue,
+};
+use crate::startup::ApplicationBaseUrl;
 use crate::utils::{e400, e500, see_other};
 
 use actix_web::web::ReqData;
@@ -15,9 +22,50 @@ use uuid::Uuid;
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
+    // Generated out of `markdown_content` when there is one
+    #[serde(default)]
     text_content: String,
+    #[serde(default)]
     html_content: String,
+    #[serde(default)]
+    markdown_content: String,
+    // Left empty for no layout
+    #[serde(default)]
+    layout_id: String,
+    // Left empty to send the issue to every subscriber
+    #[serde(default)]
+    topic_id: String,
+    // Left empty to send the issue to the whole topic
+    #[serde(default)]
+    segment_id: String,
+    // Checked to leave the issue out of the public archive
+    #[serde(default)]
+    subscribers_only: bool,
+    // Checked to track opens and clicks
+    #[serde(default)]
+    tracking: bool,
+    // Comma-separated, for the "Send test" button
+    #[serde(default)]
+    test_recipients: String,
     idempotency_key: String,
+    // Left empty to publish right away
+    #[serde(default)]
+    scheduled_for: String,
+    #[serde(default)]
+    timezone: String,
+    #[serde(default)]
+    action: SubmitAction,
+}
+
+/// Which button of the form has been used.
+#[derive(serde::Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmitAction {
+    #[default]
+    Publish,
+    SaveDraft,
+    // Save a draft and send it to `test_recipients`
+    SendTest,
 }
 
 #[tracing::instrument(
@@ -28,6 +76,8 @@ pub struct FormData {
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
     user_id: ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
@@ -38,10 +88,72 @@ pub async fn publish_newsletter(
         title,
         text_content,
         html_content,
+        markdown_content,
+        layout_id,
+        topic_id,
+        segment_id,
+        subscribers_only,
+        tracking,
+        test_recipients,
         idempotency_key,
+        scheduled_for,
+        timezone,
+        action,
     } = form.0;
     let idempotency_key: IdempotencyKey =
         idempotency_key.try_into().map_err(e400)?;
+    let schedule = match action {
+        SubmitAction::Publish => {
+            parse_schedule(&scheduled_for, &timezone).map_err(e400)?
+        }
+        // Drafts are scheduled when they get published
+        SubmitAction::SaveDraft | SubmitAction::SendTest => None,
+    };
+    let content =
+        IssueContent::new(&markdown_content, text_content, html_content);
+    let settings = IssueSettings {
+        layout_id: parse_optional_id(&layout_id, "layout").map_err(e400)?,
+        topic_id: parse_optional_id(&topic_id, "topic").map_err(e400)?,
+        segment_id: parse_optional_id(&segment_id, "segment").map_err(e400)?,
+        subscribers_only,
+        tracking,
+    };
+
+    // Like tests of existing drafts, they stay away
+    // from the idempotency table
+    if action == SubmitAction::SendTest {
+        let recipients = parse_recipients(&test_recipients).map_err(e400)?;
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")
+            .map_err(e500)?;
+        let issue_id = insert_newsletter_issue(
+            &mut transaction,
+            &title,
+            &content,
+            &settings,
+        )
+        .await
+        .context("Failed to store newsletter issue details")
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store a draft.")
+            .map_err(e500)?;
+        success_message(action, None).send();
+        send_test_emails(
+            &pool,
+            email_client.as_ref(),
+            &base_url.0,
+            issue_id,
+            recipients,
+            *user_id,
+        )
+        .await?;
+        return Ok(see_other(&format!("/admin/newsletters/{}/edit", issue_id)));
+    }
 
     let mut transaction =
         match try_processing(&pool, &idempotency_key, *user_id)
@@ -52,52 +164,178 @@ pub async fn publish_newsletter(
             // Return early if we have a saved response in the database
             NextAction::ReturnSavedResponse(saved_response) => {
                 // send a `FlashMessage`
-                success_message().send();
+                success_message(action, schedule.as_ref()).send();
                 return Ok(saved_response);
             }
         };
 
     // insert newsletter_issue
-    let issue_id = insert_newsletter_issue(
-        &mut transaction,
-        &title,
-        &text_content,
-        &html_content,
-    )
-    .await
-    .context("Failed to store newsletter issue details")
-    .map_err(e500)?;
-
-    // enqueue the delivery task
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+    let issue_id =
+        insert_newsletter_issue(&mut transaction, &title, &content, &settings)
+            .await
+            .context("Failed to store newsletter issue details")
+            .map_err(e500)?;
 
-    let response = see_other("/admin/newsletters");
+    let response = match action {
+        SubmitAction::SaveDraft | SubmitAction::SendTest => {
+            see_other(&format!("/admin/newsletters/{}/edit", issue_id))
+        }
+        SubmitAction::Publish => {
+            check_templates(&mut transaction, issue_id).await?;
+            release_draft(&mut transaction, issue_id, schedule.as_ref())
+                .await
+                .map_err(e500)?;
+            see_other("/admin/newsletters")
+        }
+    };
     let response =
         save_response(transaction, &idempotency_key, *user_id, response)
             .await
             .map_err(e500)?;
 
     // send a `FlashMessage`
-    success_message().send();
+    success_message(action, schedule.as_ref()).send();
     Ok(response)
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info(
-        "The newsletter issue has been accepted - \
-        emails will go out shortly.",
+/// Schedule a draft or publish it right away.
+/// Returns `false` if the issue is not a draft (anymore).
+#[tracing::instrument(skip(transaction))]
+pub(super) async fn release_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    schedule: Option<&IssueSchedule>,
+) -> Result<bool, anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT status FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        issue_id,
     )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the status of a newsletter issue")?;
+    if issue.is_none_or(|issue| issue.status != "draft") {
+        return Ok(false);
+    }
+    match schedule {
+        Some(schedule) => {
+            sqlx::query!(
+                r#"
+                UPDATE newsletter_issues
+                SET
+                    status = 'scheduled',
+                    scheduled_for = $2,
+                    scheduled_timezone = $3
+                WHERE newsletter_issue_id = $1
+                "#,
+                issue_id,
+                schedule.at(),
+                schedule.timezone(),
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to schedule a newsletter issue")?;
+            // Let the scheduler know it might have to wake up earlier
+            notify_new_tasks(transaction)
+                .await
+                .context("Failed to notify the scheduler")?;
+        }
+        None => {
+            // enqueue the delivery task
+            publish_issue(transaction, issue_id)
+                .await
+                .context("Failed to enqueue delivery tasks")?;
+        }
+    }
+    Ok(true)
+}
+
+/// Templates are checked before anything goes out: a mistake
+/// would fail every single delivery otherwise.
+pub(super) async fn check_templates(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), actix_web::Error> {
+    render_sample_issue(transaction, issue_id)
+        .await
+        .map_err(e500)?
+        .map_err(|e| e400(format!("The issue can't be published. {}", e)))?;
+    Ok(())
+}
+
+/// The id picked in one of the `<select>`s of the form,
+/// e.g. the layout. The empty option stands for none.
+pub(crate) fn parse_optional_id(
+    id: &str,
+    kind: &str,
+) -> Result<Option<Uuid>, String> {
+    if id.trim().is_empty() {
+        return Ok(None);
+    }
+    id.trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("{} is not a valid {}.", id, kind))
+}
+
+/// An empty `scheduled_for` means "publish right away".
+pub(super) fn parse_schedule(
+    scheduled_for: &str,
+    timezone: &str,
+) -> Result<Option<IssueSchedule>, String> {
+    if scheduled_for.trim().is_empty() {
+        return Ok(None);
+    }
+    let timezone = if timezone.trim().is_empty() {
+        "UTC"
+    } else {
+        timezone
+    };
+    let schedule = IssueSchedule::parse(scheduled_for, timezone)?;
+    if schedule.is_due() {
+        return Err("The scheduled time must be in the future.".into());
+    }
+    Ok(Some(schedule))
+}
+
+pub(super) fn success_message(
+    action: SubmitAction,
+    schedule: Option<&IssueSchedule>,
+) -> FlashMessage {
+    if action != SubmitAction::Publish {
+        return FlashMessage::info("The draft has been saved.");
+    }
+    match schedule {
+        None => FlashMessage::info(
+            "The newsletter issue has been accepted - \
+            emails will go out shortly.",
+        ),
+        Some(schedule) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {} ({}).",
+            schedule.local_time().replace('T', " "),
+            schedule.timezone()
+        )),
+    }
+}
+
+/// Everything about an issue but its title and content.
+pub(super) struct IssueSettings {
+    pub(super) layout_id: Option<Uuid>,
+    pub(super) topic_id: Option<Uuid>,
+    pub(super) segment_id: Option<Uuid>,
+    pub(super) subscribers_only: bool,
+    pub(super) tracking: bool,
 }
 
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
-    text_content: &str,
-    html_content: &str,
+    content: &IssueContent,
+    settings: &IssueSettings,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -107,38 +345,28 @@ async fn insert_newsletter_issue(
         title,
         text_content,
         html_content,
-        published_at
+        markdown_content,
+        layout_id,
+        topic_id,
+        segment_id,
+        subscribers_only,
+        tracking,
+        status
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, 'draft')
         "#,
         newsletter_issue_id,
         title,
-        text_content,
-        html_content,
+        content.text(),
+        content.html(),
+        content.markdown(),
+        settings.layout_id,
+        settings.topic_id,
+        settings.segment_id,
+        settings.subscribers_only,
+        settings.tracking,
     )
     .execute(transaction)
     .await?;
     Ok(newsletter_issue_id)
 }
-
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id,
-    )
-    .execute(transaction)
-    .await?;
-    Ok(())
-}
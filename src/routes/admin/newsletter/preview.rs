@@ -0,0 +1,74 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::issue_delivery_worker::render_sample_issue;
+use crate::utils::e500;
+
+/// Show an issue the way subscribers are going to see it,
+/// personalized for a made-up subscriber.
+pub async fn preview_newsletter(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, status
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a newsletter issue.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown issue"))?;
+    let back = if issue.status == "draft" {
+        format!("/admin/newsletters/{}/edit", issue_id)
+    } else {
+        format!("/admin/issues/{}", issue_id)
+    };
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let content = match render_sample_issue(&mut connection, issue_id)
+        .await
+        .map_err(e500)?
+    {
+        Ok(rendered) => format!(
+            r#"<h2>HTML content</h2>
+    <iframe srcdoc="{}" width="100%" height="400"></iframe>
+    <h2>Plain text content</h2>
+    <pre>{}</pre>"#,
+            htmlescape::encode_attribute(&rendered.html),
+            htmlescape::encode_minimal(&rendered.text),
+        ),
+        Err(e) => format!(
+            "<p><i>The issue can't be rendered. {}</i></p>",
+            htmlescape::encode_minimal(&e)
+        ),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preview</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    {content}
+    <p><a href="{back}">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+        )))
+}
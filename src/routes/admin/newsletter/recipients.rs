@@ -0,0 +1,47 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::post::parse_optional_id;
+use crate::segments::{get_segment, Audience};
+use crate::utils::{e400, e500};
+
+// The whole publish (or edit) form gets posted,
+// we only care about the audience.
+#[derive(serde::Deserialize)]
+pub struct AudienceFormData {
+    #[serde(default)]
+    topic_id: String,
+    #[serde(default)]
+    segment_id: String,
+}
+
+/// How many subscribers an issue would go out to, if it was
+/// published right now.
+#[tracing::instrument(name = "Preview the recipient count", skip(form, pool))]
+pub async fn count_recipients(
+    form: web::Form<AudienceFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let topic_id = parse_optional_id(&form.topic_id, "topic").map_err(e400)?;
+    let segment =
+        match parse_optional_id(&form.segment_id, "segment").map_err(e400)? {
+            None => None,
+            Some(segment_id) => Some(
+                get_segment(pool.get_ref(), segment_id)
+                    .await
+                    .context("Failed to retrieve a segment.")
+                    .map_err(e500)?
+                    .ok_or_else(|| e400("Unknown segment"))?,
+            ),
+        };
+    let n = Audience { topic_id, segment }
+        .count_recipients(pool.get_ref())
+        .await
+        .context("Failed to count the recipients of an issue.")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().body(match n {
+        1 => "1 recipient".to_string(),
+        n => format!("{} recipients", n),
+    }))
+}
@@ -0,0 +1,178 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::edit::{
+    begin, commit, conflict_page, save_draft, DraftFormData, SaveOutcome,
+};
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailSender, SentEmail};
+use crate::issue_delivery_worker::send_test_issue;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e400, e500, see_other};
+
+// Tests are for editors, not for a mailing list
+const MAX_TEST_RECIPIENTS: usize = 10;
+
+/// Save the latest changes to a draft and send it to
+/// the addresses picked by the editor.
+///
+/// Test emails go out right away: they never touch the delivery
+/// queue and don't count as a delivery of the issue.
+#[tracing::instrument(
+    name = "Send a test email",
+    skip(form, pool, email_client, base_url),
+    fields(user_id=%&*user_id)
+)]
+pub async fn send_test_email(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let recipients = parse_recipients(&form.test_recipients).map_err(e400)?;
+    let mut transaction = begin(&pool).await.map_err(e500)?;
+    match save_draft(&mut transaction, issue_id, &form).await? {
+        SaveOutcome::Saved { .. } => {}
+        SaveOutcome::Conflict { revision } => {
+            return conflict_page(&pool, issue_id, form.0, revision).await;
+        }
+        SaveOutcome::NotADraft => {
+            FlashMessage::error("Only drafts can be edited.").send();
+            return Ok(see_other(&format!("/admin/issues/{}", issue_id)));
+        }
+    }
+    commit(transaction).await.map_err(e500)?;
+    send_test_emails(
+        &pool,
+        email_client.as_ref(),
+        &base_url.0,
+        issue_id,
+        recipients,
+        **user_id,
+    )
+    .await?;
+    Ok(see_other(&format!("/admin/newsletters/{}/edit", issue_id)))
+}
+
+/// Send a draft to each recipient, the outcome is reported
+/// in flash messages.
+pub(super) async fn send_test_emails(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    base_url: &str,
+    issue_id: Uuid,
+    recipients: Vec<SubscriberEmail>,
+    user_id: Uuid,
+) -> Result<(), actix_web::Error> {
+    let sent_at = Utc::now();
+    let mut delivered = Vec::new();
+    for recipient in recipients {
+        match send_test_issue(
+            pool,
+            email_client,
+            base_url,
+            issue_id,
+            &recipient,
+        )
+        .await
+        .map_err(e500)?
+        {
+            Ok(sent_email) => {
+                record_test_email(
+                    pool, issue_id, &recipient, user_id, sent_email, sent_at,
+                )
+                .await
+                .map_err(e500)?;
+                delivered.push(recipient.as_ref().to_owned());
+            }
+            Err(e) => {
+                tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a test email.",
+                );
+                FlashMessage::error(format!(
+                    "Failed to send a test email to {}: {}",
+                    htmlescape::encode_minimal(recipient.as_ref()),
+                    htmlescape::encode_minimal(&e.to_string()),
+                ))
+                .send();
+            }
+        }
+    }
+    if !delivered.is_empty() {
+        FlashMessage::info(format!(
+            "A test email has been sent to {}.",
+            htmlescape::encode_minimal(&delivered.join(", "))
+        ))
+        .send();
+    }
+    Ok(())
+}
+
+pub(super) fn parse_recipients(
+    s: &str,
+) -> Result<Vec<SubscriberEmail>, String> {
+    let mut recipients = Vec::new();
+    for recipient in s.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let recipient = SubscriberEmail::parse(recipient.to_owned())?;
+        if !recipients.iter().any(|r: &SubscriberEmail| {
+            r.as_ref().eq_ignore_ascii_case(recipient.as_ref())
+        }) {
+            recipients.push(recipient);
+        }
+    }
+    if recipients.is_empty() {
+        return Err("Enter at least one address to send the test to.".into());
+    }
+    if recipients.len() > MAX_TEST_RECIPIENTS {
+        return Err(format!(
+            "A test can be sent to at most {} addresses.",
+            MAX_TEST_RECIPIENTS
+        ));
+    }
+    Ok(recipients)
+}
+
+#[tracing::instrument(skip(pool, sent_email))]
+async fn record_test_email(
+    pool: &PgPool,
+    issue_id: Uuid,
+    recipient: &SubscriberEmail,
+    user_id: Uuid,
+    sent_email: SentEmail,
+    sent_at: chrono::DateTime<Utc>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_test_emails (
+            test_email_id,
+            newsletter_issue_id,
+            recipient,
+            sent_by,
+            provider_message_id,
+            sent_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        issue_id,
+        recipient.as_ref(),
+        user_id,
+        sent_email.message_id,
+        sent_at,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record a test email.")?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::email_client::Outbox;
+use crate::utils::e500;
+
+const OUTBOX_DISABLED: &str = "<p>The outbox is only available \
+    when the <code>outbox</code> email backend is in use.</p>";
+
+// The outbox is only registered as application data
+// when the `outbox` email backend is enabled.
+pub async fn admin_outbox(
+    outbox: Option<web::Data<Outbox>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = match outbox {
+        None => OUTBOX_DISABLED.to_owned(),
+        Some(outbox) => {
+            let messages = outbox.list().await.map_err(e500)?;
+            let mut rows = String::new();
+            for m in messages {
+                writeln!(
+                    rows,
+                    r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td><a href="/admin/outbox/{}">{}</a></td>
+        </tr>"#,
+                    m.sent_at.to_rfc3339(),
+                    htmlescape::encode_minimal(&m.to),
+                    m.id,
+                    htmlescape::encode_minimal(&m.subject),
+                )
+                .unwrap();
+            }
+            format!(
+                r#"<table>
+        <tr><th>Sent at</th><th>To</th><th>Subject</th></tr>
+        {rows}
+    </table>"#
+            )
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Outbox</title>
+</head>
+<body>
+    {body}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn admin_outbox_message(
+    message_id: web::Path<Uuid>,
+    outbox: Option<web::Data<Outbox>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = match outbox {
+        None => OUTBOX_DISABLED.to_owned(),
+        Some(outbox) => {
+            let message = outbox
+                .get(message_id.into_inner())
+                .await
+                .map_err(e500)?
+                .ok_or_else(|| {
+                    actix_web::error::ErrorNotFound("Unknown message")
+                })?;
+            let mut headers = String::new();
+            for (name, value) in &message.headers {
+                writeln!(
+                    headers,
+                    "<li>{}: {}</li>",
+                    htmlescape::encode_minimal(name),
+                    htmlescape::encode_minimal(value)
+                )
+                .unwrap();
+            }
+            format!(
+                r#"<p>From: {from}</p>
+    <p>To: {to}</p>
+    <p>Subject: {subject}</p>
+    <p>Sent at: {sent_at}</p>
+    <ul>{headers}</ul>
+    <h2>HTML content</h2>
+    <iframe sandbox="" srcdoc="{html_body}" width="100%" height="400"></iframe>
+    <h2>Plain text content</h2>
+    <pre>{text_body}</pre>"#,
+                from = htmlescape::encode_minimal(&message.from),
+                to = htmlescape::encode_minimal(&message.to),
+                subject = htmlescape::encode_minimal(&message.subject),
+                sent_at = message.sent_at.to_rfc3339(),
+                html_body = htmlescape::encode_attribute(&message.html_body),
+                text_body = htmlescape::encode_minimal(&message.text_body),
+            )
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Outbox</title>
+</head>
+<body>
+    {body}
+    <p><a href="/admin/outbox">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
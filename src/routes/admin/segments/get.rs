@@ -0,0 +1,260 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::domain::{AttributeDefinition, Segment};
+use crate::segments::{get_segment, segment_from_columns, AttributeColumns};
+use crate::subscriber_attributes::get_attribute_definitions;
+use crate::utils::e500;
+
+pub struct SegmentSummary {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub segment: Segment,
+}
+
+pub async fn admin_segments(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut segments = String::new();
+    for summary in get_segments(&pool).await.map_err(e500)? {
+        let conditions = summary
+            .segment
+            .conditions
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>();
+        writeln!(
+            segments,
+            r#"<li><a href="/admin/segments/{}">{}</a>: {}</li>"#,
+            summary.segment_id,
+            htmlescape::encode_minimal(&summary.name),
+            if conditions.is_empty() {
+                "everybody".to_string()
+            } else {
+                htmlescape::encode_minimal(&conditions.join(", "))
+            },
+        )
+        .unwrap();
+    }
+    if segments.is_empty() {
+        segments.push_str("<li>No segments</li>");
+    }
+    let definitions = get_attribute_definitions(pool.get_ref())
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Segments</title>
+</head>
+<body>
+    {msg_html}
+    <p>Issues sent to a segment only reach the subscribers
+    matching all of its criteria.</p>
+    <ul>
+        {segments}
+    </ul>
+    <h2>New segment</h2>
+    {form}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            form = segment_form(
+                "/admin/segments",
+                "",
+                &Segment::default(),
+                &definitions,
+            ),
+        )))
+}
+
+pub async fn admin_segment(
+    segment_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment_id = segment_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let name = sqlx::query!(
+        "SELECT name FROM segments WHERE segment_id = $1",
+        segment_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a segment.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown segment"))?
+    .name;
+    let segment = get_segment(pool.get_ref(), segment_id)
+        .await
+        .context("Failed to retrieve a segment.")
+        .map_err(e500)?
+        .unwrap_or_default();
+    let definitions = get_attribute_definitions(pool.get_ref())
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit segment</title>
+</head>
+<body>
+    {msg_html}
+    {form}
+    <p><a href="/admin/segments">&lt;- Back</a></p>
+</body>
+</html>"#,
+            form = segment_form(
+                &format!("/admin/segments/{}", segment_id),
+                &name,
+                &segment,
+                &definitions,
+            ),
+        )))
+}
+
+fn segment_form(
+    action: &str,
+    name: &str,
+    segment: &Segment,
+    definitions: &[AttributeDefinition],
+) -> String {
+    let attribute = segment.attribute_equals();
+    let mut attribute_options =
+        r#"<option value="">No attribute</option>"#.to_string();
+    for definition in definitions {
+        let selected =
+            attribute.is_some_and(|(id, _)| id == definition.attribute_id);
+        write!(
+            attribute_options,
+            r#"<option value="{}"{}>{}</option>"#,
+            definition.attribute_id,
+            if selected { " selected" } else { "" },
+            htmlescape::encode_minimal(&definition.label),
+        )
+        .unwrap();
+    }
+    format!(
+        r#"<form action="{action}" method="post">
+        <label>Name:<br>
+            <input
+                type="text"
+                placeholder="Enter the segment name"
+                name="name"
+                value="{name}"
+            >
+        </label>
+        <p>Leave a criterion empty to ignore it.</p>
+        <label>Subscribed after:
+            <input type="date" name="subscribed_after" value="{subscribed_after}">
+        </label>
+        <br>
+        <label>Email domain:
+            <input
+                type="text"
+                placeholder="example.com"
+                name="email_domain"
+                value="{email_domain}"
+            >
+        </label>
+        <br>
+        <label>Opened each of the last
+            <input
+                type="number"
+                min="1"
+                name="opened_last_issues"
+                value="{opened_last_issues}"
+            >
+            tracked issues
+        </label>
+        <br>
+        <label>Attribute:
+            <select name="attribute_id">
+                {attribute_options}
+            </select>
+        </label>
+        <label>equals:
+            <input type="text" name="attribute_value" value="{attribute_value}">
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>"#,
+        name = htmlescape::encode_attribute(name),
+        subscribed_after = segment
+            .subscribed_after()
+            .map(|date| date.to_string())
+            .unwrap_or_default(),
+        email_domain =
+            htmlescape::encode_attribute(segment.email_domain().unwrap_or("")),
+        opened_last_issues = segment
+            .opened_last_issues()
+            .map(|n| n.to_string())
+            .unwrap_or_default(),
+        attribute_value = htmlescape::encode_attribute(
+            attribute.map(|(_, value)| value).unwrap_or("")
+        ),
+    )
+}
+
+#[tracing::instrument(name = "Get segments", skip(pool))]
+pub async fn get_segments(
+    pool: &PgPool,
+) -> Result<Vec<SegmentSummary>, anyhow::Error> {
+    let segments = sqlx::query!(
+        r#"
+        SELECT
+            s.segment_id,
+            s.name,
+            s.subscribed_after,
+            s.email_domain,
+            s.opened_last_issues,
+            s.attribute_id,
+            a.name AS "attribute_name?",
+            s.attribute_value
+        FROM segments s
+        LEFT JOIN subscriber_attributes a ON a.attribute_id = s.attribute_id
+        ORDER BY s.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve segments.")?;
+    Ok(segments
+        .into_iter()
+        .map(|s| SegmentSummary {
+            segment_id: s.segment_id,
+            name: s.name,
+            segment: segment_from_columns(
+                s.subscribed_after,
+                s.email_domain,
+                s.opened_last_issues,
+                AttributeColumns {
+                    attribute_id: s.attribute_id,
+                    name: s.attribute_name,
+                    value: s.attribute_value,
+                },
+            ),
+        })
+        .collect())
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub use get::{admin_segment, admin_segments};
+pub(crate) use get::{get_segments, SegmentSummary};
+pub use post::{create_segment, update_segment};
@@ -0,0 +1,191 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{AttributeDefinition, Segment, SegmentCondition};
+use crate::routes::admin::parse_optional_id;
+use crate::subscriber_attributes::get_attribute_definitions;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    #[serde(default)]
+    subscribed_after: String,
+    #[serde(default)]
+    email_domain: String,
+    #[serde(default)]
+    opened_last_issues: String,
+    // Left empty to ignore attributes
+    #[serde(default)]
+    attribute_id: String,
+    #[serde(default)]
+    attribute_value: String,
+}
+
+#[tracing::instrument(name = "Create a segment", skip(form, pool))]
+pub async fn create_segment(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let definitions = get_attribute_definitions(pool.get_ref())
+        .await
+        .map_err(e500)?;
+    let (name, segment) = match validate(&form, &definitions) {
+        Ok(valid) => valid,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+    if is_name_taken(&pool, &name, None).await.map_err(e500)? {
+        FlashMessage::error(name_taken_message(&name)).send();
+        return Ok(see_other("/admin/segments"));
+    }
+    let segment_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO segments(
+            segment_id,
+            name,
+            subscribed_after,
+            email_domain,
+            opened_last_issues,
+            attribute_id,
+            attribute_value
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        segment_id,
+        name,
+        segment.subscribed_after(),
+        segment.email_domain(),
+        segment.opened_last_issues(),
+        segment.attribute_equals().map(|(id, _)| id),
+        segment.attribute_equals().map(|(_, value)| value),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store a new segment.")
+    .map_err(e500)?;
+    FlashMessage::info("The segment has been created.").send();
+    Ok(see_other(&format!("/admin/segments/{}", segment_id)))
+}
+
+#[tracing::instrument(name = "Update a segment", skip(form, pool))]
+pub async fn update_segment(
+    segment_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment_id = segment_id.into_inner();
+    let back = format!("/admin/segments/{}", segment_id);
+    let definitions = get_attribute_definitions(pool.get_ref())
+        .await
+        .map_err(e500)?;
+    let (name, segment) = match validate(&form, &definitions) {
+        Ok(valid) => valid,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&back));
+        }
+    };
+    if is_name_taken(&pool, &name, Some(segment_id))
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(name_taken_message(&name)).send();
+        return Ok(see_other(&back));
+    }
+    let updated = sqlx::query!(
+        r#"
+        UPDATE segments
+        SET
+            name = $2,
+            subscribed_after = $3,
+            email_domain = $4,
+            opened_last_issues = $5,
+            attribute_id = $6,
+            attribute_value = $7
+        WHERE segment_id = $1
+        "#,
+        segment_id,
+        name,
+        segment.subscribed_after(),
+        segment.email_domain(),
+        segment.opened_last_issues(),
+        segment.attribute_equals().map(|(id, _)| id),
+        segment.attribute_equals().map(|(_, value)| value),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update a segment.")
+    .map_err(e500)?;
+    if updated.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorNotFound("Unknown segment"));
+    }
+    FlashMessage::info("The segment has been saved.").send();
+    Ok(see_other(&back))
+}
+
+fn validate(
+    form: &FormData,
+    definitions: &[AttributeDefinition],
+) -> Result<(String, Segment), String> {
+    let name = form.name.trim().to_owned();
+    if name.is_empty() {
+        return Err("The segment needs a name.".into());
+    }
+    let mut segment = Segment::parse(
+        &form.subscribed_after,
+        &form.email_domain,
+        &form.opened_last_issues,
+    )
+    .map_err(|e| htmlescape::encode_minimal(&e))?;
+    if let Some(attribute_id) =
+        parse_optional_id(&form.attribute_id, "attribute")
+            .map_err(|e| htmlescape::encode_minimal(&e))?
+    {
+        let definition = definitions
+            .iter()
+            .find(|d| d.attribute_id == attribute_id)
+            .ok_or("The attribute doesn't exist.")?;
+        segment.conditions.push(
+            SegmentCondition::attribute_equals(
+                definition,
+                &form.attribute_value,
+            )
+            .map_err(|e| htmlescape::encode_minimal(&e))?,
+        );
+    }
+    Ok((name, segment))
+}
+
+fn name_taken_message(name: &str) -> String {
+    format!(
+        "There is a segment named {} already.",
+        htmlescape::encode_minimal(name)
+    )
+}
+
+#[tracing::instrument(skip(pool))]
+async fn is_name_taken(
+    pool: &PgPool,
+    name: &str,
+    segment_id: Option<Uuid>,
+) -> Result<bool, anyhow::Error> {
+    let segment = sqlx::query!(
+        r#"
+        SELECT segment_id FROM segments
+        WHERE name = $1 AND segment_id IS DISTINCT FROM $2
+        "#,
+        name,
+        segment_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up segments by name.")?;
+    Ok(segment.is_some())
+}
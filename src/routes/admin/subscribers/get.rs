@@ -0,0 +1,146 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::routes::admin::attribute_inputs;
+use crate::subscriber_attributes::{
+    get_attribute_definitions, get_template_attributes,
+};
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    email: String,
+}
+
+// Enough for a search box
+const MAX_RESULTS: i64 = 50;
+
+pub async fn admin_subscribers(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = query.into_inner().email;
+    let mut results = String::new();
+    if !email.trim().is_empty() {
+        let subscribers = sqlx::query!(
+            r#"
+            SELECT id, email, name, status
+            FROM subscriptions
+            WHERE email ILIKE '%' || $1 || '%'
+            ORDER BY email
+            LIMIT $2
+            "#,
+            email.trim(),
+            MAX_RESULTS,
+        )
+        .fetch_all(pool.get_ref())
+        .await
+        .context("Failed to search subscribers.")
+        .map_err(e500)?;
+        for s in &subscribers {
+            writeln!(
+                results,
+                r#"<li><a href="/admin/subscribers/{}">{}</a> ({}, {})</li>"#,
+                s.id,
+                htmlescape::encode_minimal(&s.email),
+                htmlescape::encode_minimal(&s.name),
+                s.status,
+            )
+            .unwrap();
+        }
+        if subscribers.is_empty() {
+            results.push_str("<li>No subscribers</li>");
+        }
+        results = format!("<ul>\n{}</ul>", results);
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    <form action="/admin/subscribers" method="get">
+        <label>Email:
+            <input type="text" name="email" value="{email}">
+        </label>
+        <button type="submit">Search</button>
+    </form>
+    {results}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            email = htmlescape::encode_attribute(&email),
+        )))
+}
+
+pub async fn admin_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let subscriber = sqlx::query!(
+        "SELECT email, name, status FROM subscriptions WHERE id = $1",
+        subscriber_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a subscriber.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown subscriber"))?;
+    let definitions = get_attribute_definitions(pool.get_ref())
+        .await
+        .map_err(e500)?;
+    let values = get_template_attributes(pool.get_ref(), subscriber_id)
+        .await
+        .context("Failed to retrieve the attributes of a subscriber.")
+        .map_err(e500)?;
+    let attributes = if definitions.is_empty() {
+        r#"<p>There are no <a href="/admin/attributes">attributes</a>
+        yet.</p>"#
+            .to_string()
+    } else {
+        format!(
+            r#"<form action="/admin/subscribers/{}" method="post">
+        {}
+        <button type="submit">Save</button>
+    </form>"#,
+            subscriber_id,
+            attribute_inputs(&definitions, &values),
+        )
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber</title>
+</head>
+<body>
+    {msg_html}
+    <p>{name} &lt;{email}&gt;, {status}</p>
+    {attributes}
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+            name = htmlescape::encode_minimal(&subscriber.name),
+            email = htmlescape::encode_minimal(&subscriber.email),
+            status = subscriber.status,
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{admin_subscriber, admin_subscribers};
+pub use post::update_subscriber;
@@ -0,0 +1,63 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::AttributeValues;
+use crate::subscriber_attributes::{
+    get_attribute_definitions, store_attribute_values,
+};
+use crate::utils::{e500, see_other};
+
+#[tracing::instrument(
+    name = "Update the attributes of a subscriber",
+    skip(form, pool)
+)]
+pub async fn update_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    // The fields are named after the attributes
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let back = format!("/admin/subscribers/{}", subscriber_id);
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let definitions = get_attribute_definitions(&mut transaction)
+        .await
+        .map_err(e500)?;
+    let values = match AttributeValues::parse(&definitions, &form) {
+        Ok(values) => values,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other(&back));
+        }
+    };
+    let exists = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve a subscriber.")
+    .map_err(e500)?
+    .is_some();
+    if !exists {
+        return Err(actix_web::error::ErrorNotFound("Unknown subscriber"));
+    }
+    store_attribute_values(&mut transaction, subscriber_id, &values)
+        .await
+        .context("Failed to store the attributes of a subscriber.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store attributes.")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber has been saved.").send();
+    Ok(see_other(&back))
+}
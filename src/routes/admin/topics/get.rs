@@ -0,0 +1,162 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::e500;
+
+pub struct Topic {
+    pub topic_id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub n_subscribers: i64,
+}
+
+pub async fn admin_topics(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut topics = String::new();
+    for topic in get_topics(&pool).await.map_err(e500)? {
+        writeln!(
+            topics,
+            r#"<li><a href="/admin/topics/{}">{}</a> ({} subscribers)</li>"#,
+            topic.topic_id,
+            htmlescape::encode_minimal(&topic.name),
+            topic.n_subscribers,
+        )
+        .unwrap();
+    }
+    if topics.is_empty() {
+        topics.push_str("<li>No topics</li>");
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Topics</title>
+</head>
+<body>
+    {msg_html}
+    <p>Subscribers pick the topics they want to hear about.
+    Issues sent to a topic only reach its subscribers.</p>
+    <ul>
+        {topics}
+    </ul>
+    <h2>New topic</h2>
+    {form}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            form = topic_form("/admin/topics", "", ""),
+        )))
+}
+
+pub async fn admin_topic(
+    topic_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let topic_id = topic_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let topic = sqlx::query!(
+        r#"
+        SELECT name, description
+        FROM topics
+        WHERE topic_id = $1
+        "#,
+        topic_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a topic.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown topic"))?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit topic</title>
+</head>
+<body>
+    {msg_html}
+    {form}
+    <p><a href="/admin/topics">&lt;- Back</a></p>
+</body>
+</html>"#,
+            form = topic_form(
+                &format!("/admin/topics/{}", topic_id),
+                &topic.name,
+                &topic.description,
+            ),
+        )))
+}
+
+fn topic_form(action: &str, name: &str, description: &str) -> String {
+    format!(
+        r#"<form action="{action}" method="post">
+        <label>Name:<br>
+            <input
+                type="text"
+                placeholder="Enter the topic name"
+                name="name"
+                value="{name}"
+            >
+        </label>
+        <br>
+        <label>Description (shown to subscribers):<br>
+            <textarea
+                placeholder="What is the topic about?"
+                name="description"
+                rows="5"
+                cols="50"
+            >{description}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>"#,
+        name = htmlescape::encode_attribute(name),
+        description = htmlescape::encode_minimal(description),
+    )
+}
+
+/// Every topic, with how many confirmed subscribers it has.
+#[tracing::instrument(name = "Get topics", skip(pool))]
+pub async fn get_topics(pool: &PgPool) -> Result<Vec<Topic>, anyhow::Error> {
+    let topics = sqlx::query_as!(
+        Topic,
+        r#"
+        SELECT
+            t.topic_id,
+            t.name,
+            t.description,
+            COUNT(s.id) AS "n_subscribers!"
+        FROM topics t
+        LEFT JOIN subscriber_topics st ON st.topic_id = t.topic_id
+        LEFT JOIN subscriptions s
+            ON s.id = st.subscriber_id AND s.status = 'confirmed'
+        GROUP BY t.topic_id
+        ORDER BY t.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve topics.")?;
+    Ok(topics)
+}
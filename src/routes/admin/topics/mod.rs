@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub use get::{admin_topic, admin_topics};
+pub(crate) use get::{get_topics, Topic};
+pub use post::{create_topic, update_topic};
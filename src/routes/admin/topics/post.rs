@@ -0,0 +1,126 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[tracing::instrument(name = "Create a topic", skip(form, pool))]
+pub async fn create_topic(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (name, description) = match validate(form.0) {
+        Ok(valid) => valid,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/topics"));
+        }
+    };
+    if is_name_taken(&pool, &name, None).await.map_err(e500)? {
+        FlashMessage::error(name_taken_message(&name)).send();
+        return Ok(see_other("/admin/topics"));
+    }
+    let topic_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO topics(topic_id, name, description)
+        VALUES ($1, $2, $3)
+        "#,
+        topic_id,
+        name,
+        description,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store a new topic.")
+    .map_err(e500)?;
+    FlashMessage::info("The topic has been created.").send();
+    Ok(see_other(&format!("/admin/topics/{}", topic_id)))
+}
+
+#[tracing::instrument(name = "Update a topic", skip(form, pool))]
+pub async fn update_topic(
+    topic_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let topic_id = topic_id.into_inner();
+    let back = format!("/admin/topics/{}", topic_id);
+    let (name, description) = match validate(form.0) {
+        Ok(valid) => valid,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&back));
+        }
+    };
+    if is_name_taken(&pool, &name, Some(topic_id))
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(name_taken_message(&name)).send();
+        return Ok(see_other(&back));
+    }
+    let updated = sqlx::query!(
+        r#"
+        UPDATE topics
+        SET name = $2, description = $3
+        WHERE topic_id = $1
+        "#,
+        topic_id,
+        name,
+        description,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update a topic.")
+    .map_err(e500)?;
+    if updated.rows_affected() == 0 {
+        return Err(actix_web::error::ErrorNotFound("Unknown topic"));
+    }
+    FlashMessage::info("The topic has been saved.").send();
+    Ok(see_other(&back))
+}
+
+fn validate(form: FormData) -> Result<(String, String), String> {
+    let name = form.name.trim().to_owned();
+    if name.is_empty() {
+        return Err("The topic needs a name.".into());
+    }
+    Ok((name, form.description.trim().to_owned()))
+}
+
+fn name_taken_message(name: &str) -> String {
+    format!(
+        "There is a topic named {} already.",
+        htmlescape::encode_minimal(name)
+    )
+}
+
+#[tracing::instrument(skip(pool))]
+async fn is_name_taken(
+    pool: &PgPool,
+    name: &str,
+    topic_id: Option<Uuid>,
+) -> Result<bool, anyhow::Error> {
+    let topic = sqlx::query!(
+        r#"
+        SELECT topic_id FROM topics
+        WHERE name = $1 AND topic_id IS DISTINCT FROM $2
+        "#,
+        name,
+        topic_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up topics by name.")?;
+    Ok(topic.is_some())
+}
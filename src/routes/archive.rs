@@ -0,0 +1,198 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::email_template::{EmailTemplate, IssueVariables};
+use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_attributes::{
+    anonymous_attributes, get_attribute_definitions,
+};
+use crate::utils::e500;
+
+const ISSUES_PER_PAGE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveParameters {
+    // Starting from 1
+    page: Option<u32>,
+}
+
+struct ArchivedIssue {
+    slug: String,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+/// The published issues everybody can read, newest first.
+#[tracing::instrument(name = "Show the archive", skip(parameters, pool))]
+pub async fn issues_archive(
+    parameters: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = parameters.page.unwrap_or(1).max(1);
+    let mut issues = get_archived_issues(&pool, page).await.map_err(e500)?;
+    // We asked for one more issue than we show
+    let has_older = issues.len() as i64 > ISSUES_PER_PAGE;
+    issues.truncate(ISSUES_PER_PAGE as usize);
+
+    let mut items = String::new();
+    for issue in &issues {
+        writeln!(
+            items,
+            r#"<li><a href="/issues/{slug}">{title}</a> ({published_at})</li>"#,
+            slug = issue.slug,
+            title = htmlescape::encode_minimal(&issue.title),
+            published_at = issue.published_at.format("%B %-d, %Y"),
+        )
+        .unwrap();
+    }
+    if items.is_empty() {
+        items.push_str("<li>No issues yet</li>");
+    }
+    let mut navigation = String::new();
+    if page > 1 {
+        writeln!(
+            navigation,
+            r#"<a href="/issues?page={}">Newer issues</a>"#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_older {
+        writeln!(
+            navigation,
+            r#"<a href="/issues?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Past issues</title>
+    <link rel="alternate" type="application/rss+xml" href="/feed.xml">
+    <link rel="alternate" type="application/atom+xml" href="/atom.xml">
+</head>
+<body>
+    <h1>Past issues</h1>
+    <ul>
+        {items}
+    </ul>
+    <p>{navigation}</p>
+    <p><a href="/">Subscribe to our newsletter</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// The public page of a published issue.
+#[tracing::instrument(name = "Show an archived issue", skip(pool, base_url))]
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let slug = slug.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT
+            title,
+            html_content,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE
+        slug = $1 AND
+        NOT subscribers_only AND
+        status IN ('sending', 'paused', 'completed')
+        "#,
+        slug,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve an archived issue.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown issue"))?;
+    let attributes = anonymous_attributes(
+        &get_attribute_definitions(pool.get_ref())
+            .await
+            .map_err(e500)?,
+    );
+    let content =
+        archived_content(&issue.html_content, &attributes).map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+    <link rel="canonical" href="{base_url}/issues/{slug}">
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>Published on {published_at}</p>
+    <article>
+    {content}
+    </article>
+    <p><a href="/issues">&lt;- Past issues</a></p>
+    <p><a href="/">Subscribe to our newsletter</a></p>
+</body>
+</html>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            base_url = base_url.0,
+            published_at = issue.published_at.format("%B %-d, %Y"),
+        )))
+}
+
+/// The HTML content of an issue, as anonymous readers see it.
+/// `attributes` are the (empty) custom attributes of subscribers.
+pub(crate) fn archived_content(
+    html_content: &str,
+    attributes: &BTreeMap<String, String>,
+) -> Result<String, String> {
+    // Only the content: layouts and footers are for emails
+    let template = EmailTemplate::parse(html_content, "", None)?;
+    let variables = IssueVariables {
+        attributes,
+        ..IssueVariables::anonymous()
+    };
+    Ok(template.render(&variables)?.html)
+}
+
+#[tracing::instrument(name = "Get archived issues", skip(pool))]
+async fn get_archived_issues(
+    pool: &PgPool,
+    page: u32,
+) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT
+            slug AS "slug!",
+            title,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE
+        slug IS NOT NULL AND
+        NOT subscribers_only AND
+        status IN ('sending', 'paused', 'completed')
+        ORDER BY published_at DESC, slug
+        LIMIT $1 OFFSET $2
+        "#,
+        ISSUES_PER_PAGE + 1,
+        (i64::from(page) - 1) * ISSUES_PER_PAGE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve archived issues.")?;
+    Ok(issues)
+}
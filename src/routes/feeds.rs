@@ -0,0 +1,159 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::archive::archived_content;
+use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_attributes::{
+    anonymous_attributes, get_attribute_definitions,
+};
+use crate::utils::e500;
+
+const FEED_TITLE: &str = "Our newsletter";
+// Feed readers only care about what is new
+const ISSUES_PER_FEED: i64 = 20;
+
+struct FeedIssue {
+    newsletter_issue_id: Uuid,
+    slug: String,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+/// The issues of the public archive, as an RSS 2.0 feed.
+#[tracing::instrument(name = "Show the RSS feed", skip(pool, base_url))]
+pub async fn rss_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let mut items = String::new();
+    let attributes = anonymous_attributes(
+        &get_attribute_definitions(pool.get_ref())
+            .await
+            .map_err(e500)?,
+    );
+    for issue in get_feed_issues(&pool).await.map_err(e500)? {
+        let content =
+            archived_content(&issue.html_content, &attributes).map_err(e500)?;
+        writeln!(
+            items,
+            r#"    <item>
+      <title>{title}</title>
+      <link>{base_url}/issues/{slug}</link>
+      <guid isPermaLink="false">urn:uuid:{issue_id}</guid>
+      <pubDate>{published_at}</pubDate>
+      <description>{content}</description>
+    </item>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            slug = issue.slug,
+            issue_id = issue.newsletter_issue_id,
+            published_at = issue.published_at.to_rfc2822(),
+            content = htmlescape::encode_minimal(&content),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+  <channel>
+    <title>{FEED_TITLE}</title>
+    <link>{base_url}/issues</link>
+    <description>Past issues of {FEED_TITLE}</description>
+{items}  </channel>
+</rss>
+"#,
+        )))
+}
+
+/// The issues of the public archive, as an Atom feed.
+#[tracing::instrument(name = "Show the Atom feed", skip(pool, base_url))]
+pub async fn atom_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let issues = get_feed_issues(&pool).await.map_err(e500)?;
+    // Issues are never edited once published
+    let updated = issues
+        .first()
+        .map(|issue| issue.published_at)
+        .unwrap_or_else(Utc::now);
+    let attributes = anonymous_attributes(
+        &get_attribute_definitions(pool.get_ref())
+            .await
+            .map_err(e500)?,
+    );
+    let mut entries = String::new();
+    for issue in issues {
+        let content =
+            archived_content(&issue.html_content, &attributes).map_err(e500)?;
+        writeln!(
+            entries,
+            r#"  <entry>
+    <title>{title}</title>
+    <link href="{base_url}/issues/{slug}"/>
+    <id>urn:uuid:{issue_id}</id>
+    <published>{published_at}</published>
+    <updated>{published_at}</updated>
+    <content type="html">{content}</content>
+  </entry>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            slug = issue.slug,
+            issue_id = issue.newsletter_issue_id,
+            published_at = issue.published_at.to_rfc3339(),
+            content = htmlescape::encode_minimal(&content),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>{FEED_TITLE}</title>
+  <link href="{base_url}/issues"/>
+  <link rel="self" href="{base_url}/atom.xml"/>
+  <id>{base_url}/atom.xml</id>
+  <updated>{updated}</updated>
+  <author><name>{FEED_TITLE}</name></author>
+{entries}</feed>
+"#,
+            updated = updated.to_rfc3339(),
+        )))
+}
+
+#[tracing::instrument(name = "Get the issues of feeds", skip(pool))]
+async fn get_feed_issues(
+    pool: &PgPool,
+) -> Result<Vec<FeedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        FeedIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            slug AS "slug!",
+            title,
+            html_content,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE
+        slug IS NOT NULL AND
+        NOT subscribers_only AND
+        status IN ('sending', 'paused', 'completed')
+        ORDER BY published_at DESC, slug
+        LIMIT $1
+        "#,
+        ISSUES_PER_FEED,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the issues of feeds.")?;
+    Ok(issues)
+}
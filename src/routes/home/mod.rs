@@ -1,7 +1,64 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
+use crate::routes::admin::{attribute_inputs, get_topics};
+use crate::subscriber_attributes::get_attribute_definitions;
+use crate::utils::e500;
+
+pub async fn home(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut topics = String::new();
+    for topic in get_topics(&pool).await.map_err(e500)? {
+        writeln!(
+            topics,
+            r#"<label>
+            <input type="checkbox" name="topic_id" value="{}">
+            {} <small>{}</small>
+        </label>
+        <br>"#,
+            topic.topic_id,
+            htmlescape::encode_minimal(&topic.name),
+            htmlescape::encode_minimal(&topic.description),
+        )
+        .unwrap();
+    }
+    if !topics.is_empty() {
+        topics.insert_str(0, "<p>Which topics are you interested in?</p>\n");
+    }
+    let definitions = get_attribute_definitions(pool.get_ref())
+        .await
+        .map_err(e500)?;
+    let attributes = attribute_inputs(&definitions, &Default::default());
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <!-- This is equivalent to a HTTP header -->
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Home</title>
+</head>
+<body>
+    <p>Welcome to our newsletter!</p>
+    <form action="/subscriptions" method="post">
+        <label>Name:
+            <input type="text" placeholder="Enter your name" name="name">
+        </label>
+        <br>
+        <label>Email:
+            <input type="email" placeholder="Enter your email" name="email">
+        </label>
+        <br>
+        {attributes}
+        {topics}
+        <button type="submit">Subscribe</button>
+    </form>
+    <p><a href="/issues">Read past issues</a></p>
+</body>
+</html>"#,
+        )))
 }
@@ -45,11 +45,11 @@ pub async fn login(
 
     // Let's validate the creds
     tracing::Span::current()
-        .record("username", &tracing::field::display(&credentials.username));
+        .record("username", tracing::field::display(&credentials.username));
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
             tracing::Span::current()
-                .record("user_id", &tracing::field::display(&user_id));
+                .record("user_id", tracing::field::display(&user_id));
             // renew the session after login is called
             // to prevent session fixation attacks
             session.renew();
@@ -1,21 +1,92 @@
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailSender, SendEmailError};
+use crate::email_template::EmailTemplate;
 use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_attributes::{
+    get_attribute_definitions, store_attribute_values,
+};
+use crate::suppressions::is_suppressed;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::domain::{
+    AttributeDefinition, AttributeValues, NewSubscriber, SubscriberEmail,
+    SubscriberName,
+};
+
+/// How long a confirmation link stays valid after it has been issued.
+pub const SUBSCRIPTION_TOKEN_TTL_HOURS: i64 = 48;
+
+// We build the link ourselves, it is safe to leave it unescaped
+const CONFIRMATION_HTML_TEMPLATE: &str = "Welcome to our newsletter!<br />\
+Click <a href=\"{{{ confirmation_url }}}\">here</a> to confirm your subscription.";
+const CONFIRMATION_TEXT_TEMPLATE: &str = "Welcome to our newsletter!\n\
+Visit {{ confirmation_url }} to confirm your subscription.";
+
+#[derive(serde::Serialize)]
+struct ConfirmationVariables<'a> {
+    confirmation_url: &'a str,
+}
+
+const PREFERENCES_HTML_TEMPLATE: &str =
+    "You are on our newsletter already!<br />\
+Click <a href=\"{{{ preferences_url }}}\">here</a> to pick the topics you get.";
+const PREFERENCES_TEXT_TEMPLATE: &str = "You are on our newsletter already!\n\
+Visit {{ preferences_url }} to pick the topics you get.";
+
+#[derive(serde::Serialize)]
+struct PreferencesVariables<'a> {
+    preferences_url: &'a str,
+}
 
 #[derive(serde::Deserialize)]
+#[serde(try_from = "Vec<(String, String)>")]
 pub struct FormData {
     email: String,
     name: String,
+    topic_ids: Vec<Uuid>,
+    // Custom attributes, named after them
+    attributes: Vec<(String, String)>,
+}
+
+// There is one `topic_id` field per ticked topic, and the
+// attributes are only known at runtime: a struct can't be
+// deserialized from that.
+impl TryFrom<Vec<(String, String)>> for FormData {
+    type Error = String;
+
+    fn try_from(fields: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut email = None;
+        let mut name = None;
+        let mut topic_ids = Vec::new();
+        let mut attributes = Vec::new();
+        for (key, value) in fields {
+            match key.as_str() {
+                "email" => email = Some(value),
+                "name" => name = Some(value),
+                "topic_id" => topic_ids.push(parse_topic_id(&value)?),
+                _ => attributes.push((key, value)),
+            }
+        }
+        Ok(Self {
+            email: email.ok_or("missing field `email`")?,
+            name: name.ok_or("missing field `name`")?,
+            topic_ids,
+            attributes,
+        })
+    }
+}
+
+pub(crate) fn parse_topic_id(topic_id: &str) -> Result<Uuid, String> {
+    topic_id
+        .parse()
+        .map_err(|_| format!("{} is not a valid topic.", topic_id))
 }
 
 #[tracing::instrument(
@@ -31,14 +102,35 @@ pub async fn subscribe(
     // Retrieving a pool from the application state
     pool: web::Data<PgPool>,
     // Get the email client from the app context
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailSender>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
     // Get the subscriber details from the incoming request
     // `web::Form` is a wrapper around `FormData`
     // `form.0` gives us access to the underlying `FormData`
-    let new_subscriber =
-        form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let topic_ids = form.topic_ids.clone();
+    let definitions = get_attribute_definitions(pool.get_ref())
+        .await
+        .context("Failed to retrieve the subscriber attributes.")?;
+    let new_subscriber = form
+        .0
+        .parse(&definitions)
+        .map_err(SubscribeError::ValidationError)?;
+    if !topics_exist(pool.get_ref(), &topic_ids)
+        .await
+        .context("Failed to look up topics.")?
+    {
+        return Err(SubscribeError::ValidationError(
+            "Some of the topics do not exist.".into(),
+        ));
+    }
+    // The address bounced or its owner complained about us
+    if is_suppressed(pool.get_ref(), new_subscriber.email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        return Err(SubscribeError::SuppressedEmail);
+    }
 
     // Start the transaction for db operations
     let mut transaction = pool
@@ -46,17 +138,103 @@ pub async fn subscribe(
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    // Insert the subscriber in the db
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    // Submitting the form again must not fail: what we do
+    // depends on where the subscriber is in their lifecycle.
+    // Concurrent submissions for a new address are settled by the
+    // insert: the others wait for it and find the subscriber there.
+    let inserted =
+        insert_subscriber(&mut transaction, &new_subscriber)
+            .await
+            .context("Failed to insert new subscriber in the database.")?;
+    let subscriber_id = match inserted {
+        Some(subscriber_id) => {
+            // Generate and store the token used to leave the newsletter
+            let unsubscribe_token = generate_subscription_token();
+            store_unsubscribe_token(
+                &mut transaction,
+                subscriber_id,
+                &unsubscribe_token,
+            )
+            .await
+            .context(
+                "Failed to store the unsubscribe token for a new subscriber.",
+            )?;
+            subscriber_id
+        }
+        None => {
+            let subscriber = get_subscriber_by_email(
+                &mut transaction,
+                &new_subscriber.email,
+            )
+            .await
+            .context("Failed to look up the subscriber in the database.")?
+            .context("The subscriber is gone from the database.")?;
+            match subscriber.status.as_str() {
+                // Already on the list: they might be after more topics.
+                // Anybody could know their address, so we email them
+                // the preference centre rather than change anything.
+                "confirmed" => {
+                    let unsubscribe_token =
+                        get_unsubscribe_token(&mut transaction, subscriber.id)
+                            .await
+                            .context(
+                                "Failed to retrieve an unsubscribe token.",
+                            )?
+                            .context(
+                                "The subscriber has no unsubscribe token.",
+                            )?;
+                    transaction.commit().await.context(
+                        "Failed to commit SQL transaction to look up \
+                        a subscriber.",
+                    )?;
+                    send_preferences_email(
+                        email_client.as_ref(),
+                        &new_subscriber.email,
+                        &base_url.0,
+                        &unsubscribe_token,
+                    )
+                    .await
+                    .map_err(|e| {
+                        SubscribeError::from_send_error(
+                            e,
+                            "Failed to send a preferences email.",
+                        )
+                    })?;
+                    return Ok(HttpResponse::Ok().finish());
+                }
+                // They left the list: they must go through
+                // double opt-in again
+                "unsubscribed" => {
+                    mark_subscriber_as_pending(&mut transaction, subscriber.id)
+                        .await
+                        .context("Failed to mark the subscriber as pending.")?;
+                    subscriber.id
+                }
+                // Still pending: send them a fresh confirmation link
+                _ => subscriber.id,
+            }
+        }
+    };
+
+    add_subscriber_topics(&mut transaction, subscriber_id, &topic_ids)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to store the topics of a subscriber.")?;
+    // Confirmed subscribers returned earlier: their attributes can't
+    // be overwritten by anybody who knows their address.
+    store_attribute_values(
+        &mut transaction,
+        subscriber_id,
+        &new_subscriber.attributes,
+    )
+    .await
+    .context("Failed to store the attributes of a subscriber.")?;
 
     // Generate and store token in db
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
         .context(
-            "Failed to store the confirmation token for a new \
+            "Failed to store the confirmation token for a \
             subscriber.",
         )?;
 
@@ -66,15 +244,19 @@ pub async fn subscribe(
     )?;
 
     // Send a (useless) email to the new subscriber.
-    // We are ignoring email delivery errors for now.
     send_confirmation_email(
-        &email_client,
-        new_subscriber,
+        email_client.as_ref(),
+        &new_subscriber.email,
         &base_url.0,
         &subscription_token,
     )
     .await
-    .context("Failed to send a confirmation email.")?;
+    .map_err(|e| {
+        SubscribeError::from_send_error(
+            e,
+            "Failed to send a confirmation email.",
+        )
+    })?;
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -83,10 +265,25 @@ pub async fn subscribe(
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("We could not deliver a confirmation email to this address.")]
+    UndeliverableEmail(#[source] SendEmailError),
+    #[error("We could not deliver a confirmation email to this address.")]
+    SuppressedEmail,
     #[error("transparent")]
     UnexpectedError(#[from] anyhow::Error),
 }
 
+impl SubscribeError {
+    fn from_send_error(e: SendEmailError, context: &'static str) -> Self {
+        // There is no point in asking them to try again later
+        if e.is_permanent() {
+            Self::UndeliverableEmail(e)
+        } else {
+            anyhow::Error::new(e).context(context).into()
+        }
+    }
+}
+
 impl std::fmt::Debug for SubscribeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -96,7 +293,9 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_)
+            | SubscribeError::UndeliverableEmail(_)
+            | SubscribeError::SuppressedEmail => StatusCode::BAD_REQUEST,
             SubscribeError::UnexpectedError(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
@@ -113,11 +312,23 @@ pub async fn store_token(
     subscriber_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
+    let issued_at = Utc::now();
+    let expires_at =
+        issued_at + chrono::Duration::hours(SUBSCRIPTION_TOKEN_TTL_HOURS);
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        r#"
+        INSERT INTO subscription_tokens (
+            subscription_token,
+            subscriber_id,
+            issued_at,
+            expires_at
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
         subscription_token,
         subscriber_id,
+        issued_at,
+        expires_at,
     )
     .execute(transaction)
     .await
@@ -125,6 +336,26 @@ pub async fn store_token(
     Ok(())
 }
 
+#[tracing::instrument(
+    name = "Store unsubscribe token in the database",
+    skip(unsubscribe_token, transaction)
+)]
+pub async fn store_unsubscribe_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    unsubscribe_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO unsubscribe_tokens (unsubscribe_token, subscriber_id)
+        VALUES ($1, $2)"#,
+        unsubscribe_token,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 /// A new error type, wrapping a `sqlx::Error`
 /// We are following the newType pattern in
 /// order to get around the Orphan rule
@@ -174,44 +405,90 @@ pub fn error_chain_fmt(
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(email_client, recipient, base_url, subscription_token)
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+    email_client: &dyn EmailSender,
+    recipient: &SubscriberEmail,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), SendEmailError> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token,
     );
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link,
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
+    let rendered = EmailTemplate::parse(
+        CONFIRMATION_HTML_TEMPLATE,
+        CONFIRMATION_TEXT_TEMPLATE,
+        None,
+    )
+    .and_then(|template| {
+        template.render(&ConfirmationVariables {
+            confirmation_url: &confirmation_link,
+        })
+    })
+    .map_err(|e| SendEmailError::UnexpectedError(anyhow::anyhow!(e)))?;
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
+        .send_email(recipient, "Welcome!", &rendered.html, &rendered.text)
+        .await?;
+    Ok(())
 }
 
-// Takes care of the conversion from our
-// wire_format to our domain_model(NewSubscriber)
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+#[tracing::instrument(
+    name = "Send the preference centre to a subscriber",
+    skip(email_client, recipient, base_url, unsubscribe_token)
+)]
+pub async fn send_preferences_email(
+    email_client: &dyn EmailSender,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    unsubscribe_token: &str,
+) -> Result<(), SendEmailError> {
+    let preferences_link = format!(
+        "{}/subscriptions/preferences?unsubscribe_token={}",
+        base_url, unsubscribe_token,
+    );
+    let rendered = EmailTemplate::parse(
+        PREFERENCES_HTML_TEMPLATE,
+        PREFERENCES_TEXT_TEMPLATE,
+        None,
+    )
+    .and_then(|template| {
+        template.render(&PreferencesVariables {
+            preferences_url: &preferences_link,
+        })
+    })
+    .map_err(|e| SendEmailError::UnexpectedError(anyhow::anyhow!(e)))?;
+    email_client
+        .send_email(
+            recipient,
+            "Your subscription",
+            &rendered.html,
+            &rendered.text,
+        )
+        .await?;
+    Ok(())
+}
 
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+impl FormData {
+    // Takes care of the conversion from our
+    // wire_format to our domain_model(NewSubscriber)
+    fn parse(
+        self,
+        definitions: &[AttributeDefinition],
+    ) -> Result<NewSubscriber, String> {
+        let name = SubscriberName::parse(self.name)?;
+        let email = SubscriberEmail::parse(self.email)?;
+        let attributes = AttributeValues::parse(definitions, &self.attributes)?;
+        Ok(NewSubscriber {
+            email,
+            name,
+            attributes,
+        })
     }
 }
 
+// Returns `None` if somebody subscribed with this address already
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -219,12 +496,13 @@ impl TryFrom<FormData> for NewSubscriber {
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    let inserted = sqlx::query!(
         r#"
         INSERT INTO subscriptions (id, email, name, subscribed_at, status)
         VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        ON CONFLICT (email) DO NOTHING
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
@@ -233,11 +511,126 @@ pub async fn insert_subscriber(
     )
     .execute(transaction)
     .await?;
-    Ok(subscriber_id)
+    Ok((inserted.rows_affected() == 1).then_some(subscriber_id))
+}
+
+struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+}
+
+#[tracing::instrument(
+    name = "Get an existing subscriber by email",
+    skip(email, transaction)
+)]
+async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    // Lock the row until the transaction ends to serialise
+    // concurrent submissions for the same address
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"
+        SELECT id, status FROM subscriptions
+        WHERE email = $1
+        FOR UPDATE
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(transaction)
+    .await
+}
+
+#[tracing::instrument(
+    name = "Get the unsubscribe token of a subscriber",
+    skip(transaction)
+)]
+async fn get_unsubscribe_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let token = sqlx::query!(
+        r#"
+        SELECT unsubscribe_token FROM unsubscribe_tokens
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(token.map(|t| t.unsubscribe_token))
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as pending confirmation",
+    skip(transaction)
+)]
+async fn mark_subscriber_as_pending(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'pending_confirmation'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Whether every topic in `topic_ids` exists.
+#[tracing::instrument(name = "Check that topics exist", skip(executor))]
+pub(crate) async fn topics_exist(
+    executor: impl PgExecutor<'_>,
+    topic_ids: &[Uuid],
+) -> Result<bool, sqlx::Error> {
+    if topic_ids.is_empty() {
+        return Ok(true);
+    }
+    let unknown = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "n!"
+        FROM UNNEST($1::uuid[]) AS requested(topic_id)
+        WHERE NOT EXISTS (
+            SELECT 1 FROM topics t WHERE t.topic_id = requested.topic_id
+        )
+        "#,
+        topic_ids,
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(unknown.n == 0)
+}
+
+#[tracing::instrument(
+    name = "Add topics to a subscriber",
+    skip(transaction, topic_ids)
+)]
+pub(crate) async fn add_subscriber_topics(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    topic_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_topics (subscriber_id, topic_id)
+        SELECT $1, UNNEST($2::uuid[])
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        topic_ids,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
 }
 
 /// Generate a random 25-characters-long case-sensitive subscription token
-fn generate_subscription_token() -> String {
+pub(crate) fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
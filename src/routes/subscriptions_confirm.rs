@@ -1,6 +1,8 @@
 use crate::routes::error_chain_fmt;
+use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
@@ -40,21 +42,23 @@ pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
 ) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(
-        &pool,
-        &parameters.subscription_token,
-    )
-    .await
-    {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+    let token =
+        match get_subscription_token(&pool, &parameters.subscription_token)
+            .await
+        {
+            Ok(token) => token,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
 
-    match id {
+    match token {
         // Non-existing token!
         None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
+        Some(token) if token.expires_at < Utc::now() => expired_token_page(),
+        Some(token) => {
+            if confirm_subscriber(&pool, token.subscriber_id)
+                .await
+                .is_err()
+            {
                 return HttpResponse::InternalServerError().finish();
             }
 
@@ -63,6 +67,33 @@ pub async fn confirm(
     }
 }
 
+// Explain what happened and let the subscriber ask for a new link
+fn expired_token_page() -> HttpResponse {
+    HttpResponse::Gone().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Confirmation link expired</title>
+</head>
+<body>
+    <p>This confirmation link has expired.</p>
+    <p>Enter your email address and we will send you a new one.</p>
+    <form action="/subscriptions/resend-confirmation" method="post">
+        <label>Email
+            <input
+                type="email"
+                placeholder="Enter your email address"
+                name="email"
+            >
+        </label>
+        <button type="submit">Send a new confirmation link</button>
+    </form>
+</body>
+</html>"#,
+    )
+}
+
 #[tracing::instrument(
     name = "Mark subscriber as confirmed",
     skip(subscriber_id, pool)
@@ -71,8 +102,13 @@ pub async fn confirm_subscriber(
     pool: &PgPool,
     subscriber_id: Uuid,
 ) -> Result<(), sqlx::Error> {
+    // Only pending subscribers can be confirmed: an old confirmation
+    // link must not resubscribe somebody who has left the list.
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
         subscriber_id,
     )
     .execute(pool)
@@ -80,20 +116,25 @@ pub async fn confirm_subscriber(
     Ok(())
 }
 
+pub struct SubscriptionToken {
+    pub subscriber_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
 #[tracing::instrument(
-    name = "Get subscriber_id from token",
+    name = "Get subscription token details",
     skip(subscription_token, pool)
 )]
-pub async fn get_subscriber_id_from_token(
+pub async fn get_subscription_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens \
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriptionToken,
+        "SELECT subscriber_id, expires_at FROM subscription_tokens \
         WHERE subscription_token = $1",
         subscription_token,
     )
     .fetch_optional(pool)
-    .await?;
-    Ok(result.map(|r| r.subscriber_id))
+    .await
 }
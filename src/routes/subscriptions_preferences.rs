@@ -0,0 +1,212 @@
+use crate::routes::admin::get_topics;
+use crate::routes::{
+    add_subscriber_topics, error_chain_fmt,
+    get_subscriber_id_from_unsubscribe_token, parse_topic_id, topics_exist,
+};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    unsubscribe_token: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(try_from = "Vec<(String, String)>")]
+pub struct PreferencesFormData {
+    unsubscribe_token: String,
+    topic_ids: Vec<Uuid>,
+}
+
+// One `topic_id` field per ticked topic, none if they are all unticked
+impl TryFrom<Vec<(String, String)>> for PreferencesFormData {
+    type Error = String;
+
+    fn try_from(fields: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut unsubscribe_token = None;
+        let mut topic_ids = Vec::new();
+        for (key, value) in fields {
+            match key.as_str() {
+                "unsubscribe_token" => unsubscribe_token = Some(value),
+                "topic_id" => topic_ids.push(parse_topic_id(&value)?),
+                _ => {}
+            }
+        }
+        Ok(Self {
+            unsubscribe_token: unsubscribe_token
+                .ok_or("missing field `unsubscribe_token`")?,
+            topic_ids,
+        })
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("{0}")]
+    ValidationError(String),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownToken => StatusCode::UNAUTHORIZED,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The preference centre: subscribers pick the topics they
+/// want to hear about. The unsubscribe token of their emails
+/// proves who they are.
+#[tracing::instrument(
+    name = "Show the preference centre",
+    skip(parameters, pool)
+)]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id =
+        subscriber_id(&pool, &parameters.unsubscribe_token).await?;
+    preferences_page(&pool, subscriber_id, &parameters.unsubscribe_token, "")
+        .await
+}
+
+#[tracing::instrument(name = "Save subscriber preferences", skip(form, pool))]
+pub async fn update_preferences(
+    form: web::Form<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = subscriber_id(&pool, &form.unsubscribe_token).await?;
+    if !topics_exist(pool.get_ref(), &form.topic_ids)
+        .await
+        .context("Failed to look up topics.")?
+    {
+        return Err(PreferencesError::ValidationError(
+            "Some of the topics do not exist.".into(),
+        ));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"DELETE FROM subscriber_topics WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the topics of a subscriber.")?;
+    add_subscriber_topics(&mut transaction, subscriber_id, &form.topic_ids)
+        .await
+        .context("Failed to store the topics of a subscriber.")?;
+    transaction.commit().await.context(
+        "Failed to commit SQL transaction to store subscriber preferences.",
+    )?;
+    preferences_page(
+        &pool,
+        subscriber_id,
+        &form.unsubscribe_token,
+        "<p><i>Your preferences have been saved.</i></p>",
+    )
+    .await
+}
+
+async fn subscriber_id(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<Uuid, PreferencesError> {
+    get_subscriber_id_from_unsubscribe_token(pool, unsubscribe_token)
+        .await
+        .context(
+            "Failed to retrieve the subscriber id associated \
+            with the provided token.",
+        )?
+        .ok_or(PreferencesError::UnknownToken)
+}
+
+async fn preferences_page(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    unsubscribe_token: &str,
+    msg_html: &str,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscribed = get_subscriber_topic_ids(pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the topics of a subscriber.")?;
+    let mut topics = String::new();
+    for topic in get_topics(pool).await? {
+        writeln!(
+            topics,
+            r#"<label>
+            <input type="checkbox" name="topic_id" value="{}"{}>
+            {} <small>{}</small>
+        </label>
+        <br>"#,
+            topic.topic_id,
+            if subscribed.contains(&topic.topic_id) {
+                " checked"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&topic.name),
+            htmlescape::encode_minimal(&topic.description),
+        )
+        .unwrap();
+    }
+    if topics.is_empty() {
+        topics.push_str("<p>There are no topics to choose from.</p>");
+    }
+    let unsubscribe_token = htmlescape::encode_attribute(unsubscribe_token);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preferences</title>
+</head>
+<body>
+    {msg_html}
+    <p>Which topics do you want to hear about?</p>
+    <form action="/subscriptions/preferences" method="post">
+        <input hidden type="text" name="unsubscribe_token" value="{unsubscribe_token}">
+        {topics}
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/subscriptions/unsubscribe?unsubscribe_token={unsubscribe_token}">Unsubscribe from everything</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get the topics of a subscriber", skip(pool))]
+async fn get_subscriber_topic_ids(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let topics = sqlx::query!(
+        r#"SELECT topic_id FROM subscriber_topics WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(topics.into_iter().map(|t| t.topic_id).collect())
+}
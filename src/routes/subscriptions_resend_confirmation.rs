@@ -0,0 +1,115 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailSender;
+use crate::routes::{
+    generate_subscription_token, send_confirmation_email, store_token,
+    SubscribeError,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppressions::is_suppressed;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ResendConfirmationFormData {
+    email: String,
+}
+
+// The response is the same whether or not the address belongs to a
+// pending subscriber: we must not leak who is on our list.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(form, pool, email_client, base_url),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendConfirmationFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, SubscribeError> {
+    let email = SubscriberEmail::parse(form.0.email)
+        .map_err(SubscribeError::ValidationError)?;
+
+    let mut subscriber_id = get_pending_subscriber_id(&pool, &email)
+        .await
+        .context("Failed to look up a pending subscriber.")?;
+    if is_suppressed(pool.get_ref(), email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        subscriber_id = None;
+    }
+
+    if let Some(subscriber_id) = subscriber_id {
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let subscription_token = generate_subscription_token();
+        store_token(&mut transaction, subscriber_id, &subscription_token)
+            .await
+            .context(
+                "Failed to store a new confirmation token for a pending \
+                subscriber.",
+            )?;
+        transaction.commit().await.context(
+            "Failed to commit SQL transaction to store a new confirmation \
+            token.",
+        )?;
+
+        // A delivery failure must not be distinguishable from
+        // an unknown address, we just log it.
+        if let Err(e) = send_confirmation_email(
+            email_client.as_ref(),
+            &email,
+            &base_url.0,
+            &subscription_token,
+        )
+        .await
+        {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to resend a confirmation email.",
+            );
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Confirmation email sent</title>
+</head>
+<body>
+    <p>If this address is waiting to be confirmed, a new confirmation link is on its way.</p>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(
+    name = "Get the id of a pending subscriber",
+    skip(email, pool)
+)]
+async fn get_pending_subscriber_id(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT id FROM subscriptions
+        WHERE email = $1 AND status = 'pending_confirmation'
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(result.map(|r| r.id))
+}
@@ -0,0 +1,170 @@
+use crate::routes::error_chain_fmt;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    unsubscribe_token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownToken => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// Following the link in an email must not unsubscribe anybody:
+// mail scanners and link previews fetch it too.
+// We ask the subscriber to confirm with a POST instead.
+#[tracing::instrument(
+    name = "Show the unsubscribe confirmation page",
+    skip(parameters, pool)
+)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    get_subscriber_id_from_unsubscribe_token(
+        &pool,
+        &parameters.unsubscribe_token,
+    )
+    .await
+    .context(
+        "Failed to retrieve the subscriber id associated \
+        with the provided token.",
+    )?
+    .ok_or(UnsubscribeError::UnknownToken)?;
+
+    let unsubscribe_token =
+        htmlescape::encode_attribute(&parameters.unsubscribe_token);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you really want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe" method="post">
+        <input hidden type="text" name="unsubscribe_token" value="{unsubscribe_token}">
+        <button type="submit">Unsubscribe</button>
+    </form>
+    <p>Or <a href="/subscriptions/preferences?unsubscribe_token={unsubscribe_token}">pick the topics</a> you want to hear about.</p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(form, pool))]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    unsubscribe_with_token(&pool, &form.unsubscribe_token).await?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>You have been unsubscribed. You will not receive any further issues.</p>
+</body>
+</html>"#,
+    ))
+}
+
+// RFC 8058 one-click unsubscribe: mail clients POST
+// `List-Unsubscribe=One-Click` to the URL advertised in the
+// `List-Unsubscribe` header, without any user interaction.
+// The token travels in the query string of that URL.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber with one click",
+    skip(parameters, pool)
+)]
+pub async fn unsubscribe_one_click(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    unsubscribe_with_token(&pool, &parameters.unsubscribe_token).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn unsubscribe_with_token(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<(), UnsubscribeError> {
+    let subscriber_id =
+        get_subscriber_id_from_unsubscribe_token(pool, unsubscribe_token)
+            .await
+            .context(
+                "Failed to retrieve the subscriber id associated \
+                with the provided token.",
+            )?
+            .ok_or(UnsubscribeError::UnknownToken)?;
+    mark_subscriber_as_unsubscribed(pool, subscriber_id)
+        .await
+        .context("Failed to mark the subscriber as unsubscribed.")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed",
+    skip(subscriber_id, pool)
+)]
+pub async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        subscriber_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Get subscriber_id from unsubscribe token",
+    skip(unsubscribe_token, pool)
+)]
+pub async fn get_subscriber_id_from_unsubscribe_token(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        "SELECT subscriber_id FROM unsubscribe_tokens \
+        WHERE unsubscribe_token = $1",
+        unsubscribe_token,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(result.map(|r| r.subscriber_id))
+}
@@ -0,0 +1,98 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::startup::HmacSecret;
+use crate::tracking::{TrackedDelivery, TrackingEvent, TRACKING_PIXEL};
+use crate::utils::see_other;
+
+#[derive(serde::Deserialize)]
+pub struct ClickParameters {
+    url: String,
+}
+
+/// The pixel of tracked issues, fetched when they are opened.
+#[tracing::instrument(name = "Track an open", skip_all)]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let event = TrackingEvent::Open;
+    let delivery = TrackedDelivery::parse(&token, event, &hmac_secret)
+        .map_err(actix_web::error::ErrorNotFound)?;
+    record_event(&pool, &delivery, event).await;
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        // Every open goes through us
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(TRACKING_PIXEL))
+}
+
+/// Where the links of tracked issues lead to, before
+/// sending readers on their way.
+#[tracing::instrument(name = "Track a click", skip_all)]
+pub async fn track_click(
+    token: web::Path<String>,
+    parameters: web::Query<ClickParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // The URL is signed: we are not an open redirect
+    let event = TrackingEvent::Click(&parameters.url);
+    let delivery = TrackedDelivery::parse(&token, event, &hmac_secret)
+        .map_err(actix_web::error::ErrorNotFound)?;
+    record_event(&pool, &delivery, event).await;
+    Ok(see_other(&parameters.url))
+}
+
+// Readers get their pixel or their page even if we fail to record it
+async fn record_event(
+    pool: &PgPool,
+    delivery: &TrackedDelivery,
+    event: TrackingEvent<'_>,
+) {
+    if let Err(e) = insert_event(pool, delivery, event).await {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to record a tracking event.",
+        );
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+async fn insert_event(
+    pool: &PgPool,
+    delivery: &TrackedDelivery,
+    event: TrackingEvent<'_>,
+) -> Result<(), anyhow::Error> {
+    let url = match event {
+        TrackingEvent::Click(url) => Some(url),
+        TrackingEvent::Open => None,
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (
+            event_id,
+            newsletter_issue_id,
+            subscriber_id,
+            kind,
+            url,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        delivery.newsletter_issue_id,
+        delivery.subscriber_id,
+        event.kind(),
+        url,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a tracking event.")?;
+    Ok(())
+}
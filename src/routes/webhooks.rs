@@ -0,0 +1,195 @@
+use crate::configuration::WebhookSettings;
+use crate::domain::EmailEvent;
+use crate::routes::error_chain_fmt;
+use crate::suppressions::suppress;
+use actix_web::http::header::HeaderMap;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("There is no webhook for the {0} provider.")]
+    UnknownProvider(String),
+    #[error("{0}")]
+    InvalidPayload(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let WebhookError::AuthError(_) = self {
+            response.insert_header((
+                header::WWW_AUTHENTICATE,
+                r#"Basic realm="webhooks""#,
+            ));
+        }
+        response.body(self.to_string())
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            WebhookError::UnknownProvider(_) => StatusCode::NOT_FOUND,
+            WebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            WebhookError::UnexpectedError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+/// Bounces and spam complaints, posted by the email provider.
+///
+/// Addresses which hard bounced, or whose owner complained, are
+/// put on the suppression list: we never email them again.
+#[tracing::instrument(
+    name = "Receive an email webhook",
+    skip(request, body, pool, settings),
+    fields(kind=tracing::field::Empty, email=tracing::field::Empty)
+)]
+pub async fn email_webhook(
+    provider: web::Path<String>,
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    settings: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    check_credentials(request.headers(), &settings)
+        .map_err(WebhookError::AuthError)?;
+    let provider = provider.into_inner();
+    let event = match provider.as_str() {
+        "postmark" => EmailEvent::from_postmark(&body)
+            .map_err(WebhookError::InvalidPayload)?,
+        _ => return Err(WebhookError::UnknownProvider(provider)),
+    };
+    // Acknowledge what we don't care about, or the provider retries
+    let Some(event) = event else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+    let span = tracing::Span::current();
+    span.record("kind", event.kind.as_str());
+    span.record("email", event.email.as_str());
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let is_new = store_event(&mut transaction, &provider, &event)
+        .await
+        .context("Failed to store an email event.")?;
+    if is_new && event.kind.suppresses() {
+        suppress(&mut transaction, &event.email, event.kind.as_str())
+            .await
+            .context("Failed to suppress an email address.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an email event.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+struct Credentials {
+    username: String,
+    password: Secret<String>,
+}
+
+fn check_credentials(
+    headers: &HeaderMap,
+    settings: &WebhookSettings,
+) -> Result<(), anyhow::Error> {
+    let credentials = basic_authentication(headers)?;
+    // Both are checked, in constant time, not to leak
+    // how much of the credentials is right
+    let username_matches = credentials
+        .username
+        .as_bytes()
+        .ct_eq(settings.username.as_bytes());
+    let password_matches = credentials
+        .password
+        .expose_secret()
+        .as_bytes()
+        .ct_eq(settings.password.expose_secret().as_bytes());
+    if !bool::from(username_matches & password_matches) {
+        anyhow::bail!("Invalid username or password.");
+    }
+    Ok(())
+}
+
+fn basic_authentication(
+    headers: &HeaderMap,
+) -> Result<Credentials, anyhow::Error> {
+    // The header value, if present, must be a valid UTF8 string
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes =
+        base64::decode_config(base64encoded_segment, base64::STANDARD)
+            .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    // Split into two segments, using ':' as delimiter
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: Secret::new(password.to_string()),
+    })
+}
+
+// Returns `false` if the provider had told us about the event already
+#[tracing::instrument(skip_all)]
+async fn store_event(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    provider: &str,
+    event: &EmailEvent,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            event_id,
+            provider,
+            provider_event_id,
+            kind,
+            provider_type,
+            email,
+            description,
+            received_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        ON CONFLICT (provider, provider_event_id) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        provider,
+        event.provider_event_id,
+        event.kind.as_str(),
+        event.provider_type,
+        event.email,
+        event.description,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(inserted.rows_affected() == 1)
+}
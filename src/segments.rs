@@ -0,0 +1,236 @@
+use crate::domain::{Segment, SegmentCondition};
+use chrono::NaiveDate;
+use sqlx::{PgExecutor, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+/// Who an issue goes out to: the confirmed subscribers of its topic
+/// (everybody, without a topic) who are in its segment.
+/// Suppressed addresses are always left out.
+#[derive(Debug, Default)]
+pub struct Audience {
+    pub topic_id: Option<Uuid>,
+    pub segment: Option<Segment>,
+}
+
+impl Audience {
+    /// Append the conditions picking the recipients to the
+    /// `WHERE` clause of a query selecting `FROM subscriptions`.
+    pub fn push_filter(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        query.push(
+            "subscriptions.status = 'confirmed' AND NOT EXISTS ( \
+                SELECT 1 FROM suppressed_emails s \
+                WHERE s.email = subscriptions.email \
+            )",
+        );
+        if let Some(topic_id) = self.topic_id {
+            query
+                .push(
+                    " AND EXISTS ( \
+                        SELECT 1 FROM subscriber_topics t \
+                        WHERE t.subscriber_id = subscriptions.id \
+                        AND t.topic_id = ",
+                )
+                .push_bind(topic_id)
+                .push(")");
+        }
+        let conditions = self.segment.iter().flat_map(|s| &s.conditions);
+        for condition in conditions {
+            query.push(" AND ");
+            push_condition(query, condition);
+        }
+    }
+
+    /// How many subscribers the issue would go out to right now.
+    #[tracing::instrument(name = "Count recipients", skip(executor))]
+    pub async fn count_recipients(
+        &self,
+        executor: impl PgExecutor<'_>,
+    ) -> Result<i64, sqlx::Error> {
+        let mut query =
+            QueryBuilder::new("SELECT COUNT(*) FROM subscriptions WHERE ");
+        self.push_filter(&mut query);
+        let (n,): (i64,) = query.build_query_as().fetch_one(executor).await?;
+        Ok(n)
+    }
+}
+
+fn push_condition(
+    query: &mut QueryBuilder<'_, Postgres>,
+    condition: &SegmentCondition,
+) {
+    match condition {
+        SegmentCondition::SubscribedAfter(date) => {
+            query
+                .push("subscriptions.subscribed_at >= ((")
+                .push_bind(*date)
+                .push("::date + 1)::timestamp AT TIME ZONE 'UTC')");
+        }
+        SegmentCondition::EmailDomain(domain) => {
+            query
+                .push("lower(split_part(subscriptions.email, '@', 2)) = ")
+                .push_bind(domain.clone());
+        }
+        // Issues published in the same transaction (i.e. the one
+        // being sent) are not among the last ones yet
+        SegmentCondition::OpenedLastIssues(n) => {
+            query
+                .push(
+                    "NOT EXISTS ( \
+                        SELECT 1 FROM ( \
+                            SELECT newsletter_issue_id \
+                            FROM newsletter_issues \
+                            WHERE tracking AND published_at < now() \
+                            ORDER BY published_at DESC \
+                            LIMIT ",
+                )
+                .push_bind(*n)
+                .push(
+                    ") recent \
+                    WHERE NOT EXISTS ( \
+                        SELECT 1 FROM tracking_events e \
+                        WHERE \
+                        e.newsletter_issue_id = recent.newsletter_issue_id \
+                        AND e.subscriber_id = subscriptions.id \
+                        AND e.kind = 'open' \
+                    ) \
+                )",
+                );
+        }
+        SegmentCondition::AttributeEquals {
+            attribute_id,
+            value,
+            ..
+        } => {
+            query
+                .push(
+                    "EXISTS ( \
+                        SELECT 1 FROM subscriber_attribute_values v \
+                        WHERE v.subscriber_id = subscriptions.id \
+                        AND v.attribute_id = ",
+                )
+                .push_bind(*attribute_id)
+                .push(" AND v.value = ")
+                .push_bind(value.clone())
+                .push(")");
+        }
+    }
+}
+
+/// The attribute condition of a segment, as stored: the id and
+/// value, along with the name of the attribute for display.
+pub struct AttributeColumns {
+    pub attribute_id: Option<Uuid>,
+    pub name: Option<String>,
+    pub value: Option<String>,
+}
+
+/// Put a segment back together out of its columns.
+pub fn segment_from_columns(
+    subscribed_after: Option<NaiveDate>,
+    email_domain: Option<String>,
+    opened_last_issues: Option<i32>,
+    attribute: AttributeColumns,
+) -> Segment {
+    let mut conditions = Vec::new();
+    if let Some(date) = subscribed_after {
+        conditions.push(SegmentCondition::SubscribedAfter(date));
+    }
+    if let Some(domain) = email_domain {
+        conditions.push(SegmentCondition::EmailDomain(domain));
+    }
+    if let Some(n) = opened_last_issues {
+        conditions.push(SegmentCondition::OpenedLastIssues(n));
+    }
+    if let AttributeColumns {
+        attribute_id: Some(attribute_id),
+        name: Some(name),
+        value: Some(value),
+    } = attribute
+    {
+        conditions.push(SegmentCondition::AttributeEquals {
+            attribute_id,
+            name,
+            value,
+        });
+    }
+    Segment { conditions }
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_segment(
+    executor: impl PgExecutor<'_>,
+    segment_id: Uuid,
+) -> Result<Option<Segment>, sqlx::Error> {
+    let segment = sqlx::query!(
+        r#"
+        SELECT
+            s.subscribed_after,
+            s.email_domain,
+            s.opened_last_issues,
+            s.attribute_id,
+            a.name AS "attribute_name?",
+            s.attribute_value
+        FROM segments s
+        LEFT JOIN subscriber_attributes a ON a.attribute_id = s.attribute_id
+        WHERE s.segment_id = $1
+        "#,
+        segment_id,
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(segment.map(|s| {
+        segment_from_columns(
+            s.subscribed_after,
+            s.email_domain,
+            s.opened_last_issues,
+            AttributeColumns {
+                attribute_id: s.attribute_id,
+                name: s.attribute_name,
+                value: s.attribute_value,
+            },
+        )
+    }))
+}
+
+/// The audience picked when the issue was written.
+#[tracing::instrument(skip(executor))]
+pub async fn get_issue_audience(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Audience, sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT
+            i.topic_id,
+            i.segment_id AS "segment_id?",
+            s.subscribed_after AS "subscribed_after?",
+            s.email_domain AS "email_domain?",
+            s.opened_last_issues AS "opened_last_issues?",
+            s.attribute_id AS "attribute_id?",
+            a.name AS "attribute_name?",
+            s.attribute_value AS "attribute_value?"
+        FROM newsletter_issues i
+        LEFT JOIN segments s ON s.segment_id = i.segment_id
+        LEFT JOIN subscriber_attributes a ON a.attribute_id = s.attribute_id
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(Audience {
+        topic_id: issue.topic_id,
+        segment: issue.segment_id.map(|_| {
+            segment_from_columns(
+                issue.subscribed_after,
+                issue.email_domain,
+                issue.opened_last_issues,
+                AttributeColumns {
+                    attribute_id: issue.attribute_id,
+                    name: issue.attribute_name,
+                    value: issue.attribute_value,
+                },
+            )
+        }),
+    })
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::configuration::SendRateLimitSettings;
+use crate::domain::SubscriberEmail;
+
+// Per-domain buckets are dropped once they are full again,
+// we only bother doing it when there are many of them.
+const MAX_IDLE_DOMAIN_BUCKETS: usize = 1000;
+
+/// A token bucket: it holds up to `capacity` tokens and
+/// gets `refill_rate` new tokens every second.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    refill_rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, refill_rate: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            refill_rate,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// How long until a token is available.
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1. - self.tokens) / self.refill_rate)
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    global: TokenBucket,
+    domains: HashMap<String, TokenBucket>,
+    paused_until: Option<Instant>,
+}
+
+/// Throttle outgoing emails, across all delivery workers.
+///
+/// Every send takes a token from the global bucket and one from
+/// the bucket of the recipient's domain. Delivery workers only
+/// try to take them: they don't wait while holding a task.
+/// Delivery can also be paused altogether, e.g. when the email
+/// provider tells us we are going too fast.
+#[derive(Debug)]
+pub struct SendRateLimiter {
+    settings: SendRateLimitSettings,
+    state: Mutex<State>,
+}
+
+impl SendRateLimiter {
+    pub fn new(settings: SendRateLimitSettings) -> Self {
+        let global = TokenBucket::new(
+            settings.burst,
+            settings.emails_per_second,
+            Instant::now(),
+        );
+        Self {
+            settings,
+            state: Mutex::new(State {
+                global,
+                domains: HashMap::new(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Wait until we are allowed to send an email to `recipient`.
+    pub async fn acquire(&self, recipient: &SubscriberEmail) {
+        while let Err(wait_time) = self.try_acquire_global() {
+            tokio::time::sleep(wait_time).await;
+        }
+        while let Err(wait_time) = self.try_acquire_domain(recipient) {
+            tokio::time::sleep(wait_time).await;
+        }
+    }
+
+    /// Take a token from the global bucket, or tell how long until
+    /// one is available (or the pause is over).
+    pub fn try_acquire_global(&self) -> Result<(), Duration> {
+        let wait_time = self.try_acquire_global_at(Instant::now());
+        if wait_time.is_zero() {
+            Ok(())
+        } else {
+            Err(wait_time)
+        }
+    }
+
+    /// Take a token for the domain of `recipient`, or tell
+    /// how long until one is available.
+    pub fn try_acquire_domain(
+        &self,
+        recipient: &SubscriberEmail,
+    ) -> Result<(), Duration> {
+        let domain = recipient
+            .as_ref()
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_lowercase())
+            .unwrap_or_default();
+        let wait_time = self.try_acquire_domain_at(&domain, Instant::now());
+        if wait_time.is_zero() {
+            Ok(())
+        } else {
+            Err(wait_time)
+        }
+    }
+
+    // Take a token if we are not paused, otherwise
+    // return how long to wait before trying again.
+    fn try_acquire_global_at(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        if let Some(paused_until) = state.paused_until {
+            if paused_until > now {
+                return paused_until - now;
+            }
+            state.paused_until = None;
+        }
+        state.global.refill(now);
+        let wait_time = state.global.wait_time();
+        if wait_time.is_zero() {
+            state.global.tokens -= 1.;
+        }
+        wait_time
+    }
+
+    fn try_acquire_domain_at(&self, domain: &str, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        if state.domains.len() > MAX_IDLE_DOMAIN_BUCKETS {
+            state.domains.retain(|_, bucket| {
+                bucket.refill(now);
+                !bucket.is_full()
+            });
+        }
+        let bucket =
+            state.domains.entry(domain.to_owned()).or_insert_with(|| {
+                TokenBucket::new(
+                    self.settings.per_domain_burst,
+                    self.settings.per_domain_emails_per_second,
+                    now,
+                )
+            });
+        bucket.refill(now);
+        let wait_time = bucket.wait_time();
+        if wait_time.is_zero() {
+            bucket.tokens -= 1.;
+        }
+        wait_time
+    }
+
+    /// Stop sending emails for a while.
+    pub fn pause(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut state = self.state.lock().unwrap();
+        if state.paused_until.is_none_or(|current| current < until) {
+            state.paused_until = Some(until);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::SendRateLimitSettings;
+    use crate::domain::SubscriberEmail;
+    use crate::send_rate_limiter::SendRateLimiter;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    fn limiter() -> SendRateLimiter {
+        SendRateLimiter::new(SendRateLimitSettings {
+            emails_per_second: 10.,
+            burst: 4.,
+            per_domain_emails_per_second: 1.,
+            per_domain_burst: 2.,
+        })
+    }
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.into()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_burst_goes_through_right_away() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        limiter.acquire(&email("a@a.com")).await;
+        limiter.acquire(&email("b@b.com")).await;
+        limiter.acquire(&email("c@c.com")).await;
+        limiter.acquire(&email("d@d.com")).await;
+
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_are_throttled_once_the_burst_is_exhausted() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        for domain in ["a", "b", "c", "d", "e"] {
+            limiter.acquire(&email(&format!("x@{}.com", domain))).await;
+        }
+
+        // One token every 100ms
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_millis(150));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn each_recipient_domain_has_its_own_limit() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        limiter.acquire(&email("a@gmail.com")).await;
+        limiter.acquire(&email("b@GMAIL.com")).await;
+        limiter.acquire(&email("c@outlook.com")).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        limiter.acquire(&email("d@gmail.com")).await;
+
+        // One token every second
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert!(start.elapsed() < Duration::from_millis(1050));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_busy_domain_tells_how_long_to_wait() {
+        let limiter = limiter();
+
+        for recipient in ["a@gmail.com", "b@gmail.com"] {
+            assert_eq!(limiter.try_acquire_domain(&email(recipient)), Ok(()));
+        }
+
+        assert_eq!(
+            limiter.try_acquire_domain(&email("c@gmail.com")),
+            Err(Duration::from_secs(1))
+        );
+        assert_eq!(limiter.try_acquire_domain(&email("d@outlook.com")), Ok(()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_wait_for_a_pause_to_be_over() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        limiter.pause(Duration::from_secs(30));
+        limiter.acquire(&email("a@a.com")).await;
+
+        assert!(start.elapsed() >= Duration::from_secs(30));
+        assert!(start.elapsed() < Duration::from_millis(30050));
+    }
+}
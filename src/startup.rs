@@ -1,11 +1,41 @@
 use crate::authentication::reject_anonymous_users;
 use crate::configuration::DatabaseSettings;
-use crate::configuration::Settings;
-use crate::email_client::EmailClient;
+use crate::configuration::{EmailClientSettings, Settings};
+use crate::email_client::EmailSender;
+use crate::routes::email_webhook;
 use crate::routes::publish_newsletter_form;
-use crate::routes::{admin_dashboard, home, log_out, login, login_form};
+use crate::routes::unsubscribe_one_click;
+use crate::routes::{
+    admin_analytics, admin_dashboard, home, log_out, login, login_form,
+};
+use crate::routes::{
+    admin_attribute, admin_attributes, create_attribute, update_attribute,
+};
+use crate::routes::{
+    admin_issue, admin_issues, cancel_issue, pause_issue, resume_issue,
+    schedule_issue, set_issue_visibility, unschedule_issue,
+};
+use crate::routes::{
+    admin_layout, admin_layouts, create_layout, update_layout,
+};
+use crate::routes::{admin_outbox, admin_outbox_message};
+use crate::routes::{
+    admin_segment, admin_segments, create_segment, update_segment,
+};
+use crate::routes::{admin_subscriber, admin_subscribers, update_subscriber};
+use crate::routes::{admin_topic, admin_topics, create_topic, update_topic};
+use crate::routes::{archived_issue, issues_archive};
+use crate::routes::{atom_feed, rss_feed};
+use crate::routes::{
+    autosave_draft, count_recipients, edit_draft, edit_draft_form,
+    preview_newsletter, publish_draft, send_test_email,
+};
 use crate::routes::{change_password, change_password_form};
 use crate::routes::{confirm, health_check, publish_newsletter, subscribe};
+use crate::routes::{dead_letters, requeue_dead_letter};
+use crate::routes::{preferences_form, update_preferences};
+use crate::routes::{resend_confirmation, unsubscribe, unsubscribe_form};
+use crate::routes::{track_click, track_open};
 
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
@@ -21,6 +51,7 @@ use secrecy::Secret;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 // A new type to hold the newly
@@ -38,7 +69,6 @@ impl Application {
     // `Application`.
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
-        let email_client = configuration.email_client.client();
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
@@ -48,7 +78,7 @@ impl Application {
         let server = run(
             listener,
             connection_pool,
-            email_client,
+            configuration.email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
             configuration.redis_uri,
@@ -83,7 +113,7 @@ pub struct ApplicationBaseUrl(pub String);
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_settings: EmailClientSettings,
     base_url: String,
     hmac_secret: Secret<String>,
     redis_uri: Secret<String>,
@@ -94,7 +124,10 @@ async fn run(
     // instances of App thread (one for each core)
     // Data - internally uses an Arc
     let db_pool = Data::new(db_pool);
-    let email_client = Data::new(email_client);
+    let outbox = email_settings.outbox();
+    let webhook = Data::new(email_settings.webhook.clone());
+    let email_client: Arc<dyn EmailSender> = email_settings.client();
+    let email_client: Data<dyn EmailSender> = Data::from(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
     let message_store = CookieMessageStore::builder(Key::from(
         hmac_secret.expose_secret().as_bytes(),
@@ -110,7 +143,7 @@ async fn run(
     // capture the `connection` in the closure
     // from the surrounding environment
     let server = HttpServer::new(move || {
-        App::new()
+        let mut app = App::new()
             // Middlewares are added using the `wrap` method on `App`
             .wrap(message_framework.clone())
             .wrap(SessionMiddleware::new(
@@ -122,18 +155,151 @@ async fn run(
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
+            .route("/issues", web::get().to(issues_archive))
+            .route("/issues/{slug}", web::get().to(archived_issue))
+            .route("/feed.xml", web::get().to(rss_feed))
+            .route("/atom.xml", web::get().to(atom_feed))
             .route("/newsletters", web::post().to(publish_newsletter))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/resend-confirmation",
+                web::post().to(resend_confirmation),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/subscriptions/unsubscribe/one-click",
+                web::post().to(unsubscribe_one_click),
+            )
+            .route("/t/o/{token}", web::get().to(track_open))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/webhooks/email/{provider}", web::post().to(email_webhook))
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
+                    .route("/analytics", web::get().to(admin_analytics))
                     .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/dead-letters", web::get().to(dead_letters))
+                    .route(
+                        "/dead-letters/requeue",
+                        web::post().to(requeue_dead_letter),
+                    )
+                    .route("/issues", web::get().to(admin_issues))
+                    .route("/issues/{issue_id}", web::get().to(admin_issue))
+                    .route(
+                        "/issues/{issue_id}/pause",
+                        web::post().to(pause_issue),
+                    )
+                    .route(
+                        "/issues/{issue_id}/resume",
+                        web::post().to(resume_issue),
+                    )
+                    .route(
+                        "/issues/{issue_id}/cancel",
+                        web::post().to(cancel_issue),
+                    )
+                    .route(
+                        "/issues/{issue_id}/schedule",
+                        web::post().to(schedule_issue),
+                    )
+                    .route(
+                        "/issues/{issue_id}/unschedule",
+                        web::post().to(unschedule_issue),
+                    )
+                    .route(
+                        "/issues/{issue_id}/visibility",
+                        web::post().to(set_issue_visibility),
+                    )
+                    .route("/layouts", web::get().to(admin_layouts))
+                    .route("/layouts", web::post().to(create_layout))
+                    .route("/layouts/{layout_id}", web::get().to(admin_layout))
+                    .route(
+                        "/layouts/{layout_id}",
+                        web::post().to(update_layout),
+                    )
                     .route(
                         "/newsletters",
                         web::get().to(publish_newsletter_form),
                     )
                     .route("/newsletters", web::post().to(publish_newsletter))
+                    .route(
+                        "/newsletters/recipients",
+                        web::post().to(count_recipients),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/edit",
+                        web::get().to(edit_draft_form),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/edit",
+                        web::post().to(edit_draft),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/autosave",
+                        web::post().to(autosave_draft),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/preview",
+                        web::get().to(preview_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/publish",
+                        web::post().to(publish_draft),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/test",
+                        web::post().to(send_test_email),
+                    )
+                    .route("/outbox", web::get().to(admin_outbox))
+                    .route(
+                        "/outbox/{message_id}",
+                        web::get().to(admin_outbox_message),
+                    )
+                    .route("/topics", web::get().to(admin_topics))
+                    .route("/topics", web::post().to(create_topic))
+                    .route("/topics/{topic_id}", web::get().to(admin_topic))
+                    .route("/topics/{topic_id}", web::post().to(update_topic))
+                    .route("/segments", web::get().to(admin_segments))
+                    .route("/segments", web::post().to(create_segment))
+                    .route(
+                        "/segments/{segment_id}",
+                        web::get().to(admin_segment),
+                    )
+                    .route(
+                        "/segments/{segment_id}",
+                        web::post().to(update_segment),
+                    )
+                    .route("/attributes", web::get().to(admin_attributes))
+                    .route("/attributes", web::post().to(create_attribute))
+                    .route(
+                        "/attributes/{attribute_id}",
+                        web::get().to(admin_attribute),
+                    )
+                    .route(
+                        "/attributes/{attribute_id}",
+                        web::post().to(update_attribute),
+                    )
+                    .route("/subscribers", web::get().to(admin_subscribers))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(admin_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::post().to(update_subscriber),
+                    )
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(log_out)),
@@ -142,7 +308,13 @@ async fn run(
             // Register the email client
             .app_data(email_client.clone())
             .app_data(base_url.clone())
-            .app_data(Data::new(HmacSecret(hmac_secret.clone())))
+            .app_data(webhook.clone())
+            .app_data(Data::new(HmacSecret(hmac_secret.clone())));
+        // Only expose the outbox if the `outbox` backend is in use
+        if let Some(outbox) = &outbox {
+            app = app.app_data(Data::new(outbox.clone()));
+        }
+        app
     })
     .listen(listener)?
     .run();
@@ -0,0 +1,106 @@
+use crate::domain::{AttributeDefinition, AttributeKind, AttributeValues};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// The attributes of subscribers, in the order forms show them.
+#[tracing::instrument(skip(executor))]
+pub async fn get_attribute_definitions(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<AttributeDefinition>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT attribute_id, name, label, kind, required
+        FROM subscriber_attributes
+        ORDER BY created_at, name
+        "#,
+    )
+    .fetch_all(executor)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(AttributeDefinition {
+                attribute_id: r.attribute_id,
+                name: r.name,
+                label: r.label,
+                kind: AttributeKind::parse(&r.kind)
+                    .map_err(anyhow::Error::msg)?,
+                required: r.required,
+            })
+        })
+        .collect()
+}
+
+/// Replace the attributes of a subscriber.
+#[tracing::instrument(skip(transaction, values))]
+pub async fn store_attribute_values(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    values: &AttributeValues,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscriber_attribute_values WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attribute_values (
+            subscriber_id,
+            attribute_id,
+            value
+        )
+        SELECT $1, *
+        FROM UNNEST($2::uuid[], $3::text[])
+        "#,
+        subscriber_id,
+        &values.attribute_ids()[..],
+        &values.values()[..],
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Every attribute of a subscriber, by name, as templates use them.
+/// Templates fail on missing variables: attributes without
+/// a value are empty.
+#[tracing::instrument(skip(executor))]
+pub async fn get_template_attributes(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<BTreeMap<String, String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT a.name, COALESCE(v.value, '') AS "value!"
+        FROM subscriber_attributes a
+        LEFT JOIN subscriber_attribute_values v
+            ON v.attribute_id = a.attribute_id AND v.subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.name, r.value)).collect())
+}
+
+/// Made-up values for every attribute, for previews.
+pub fn sample_attributes(
+    definitions: &[AttributeDefinition],
+) -> BTreeMap<String, String> {
+    definitions
+        .iter()
+        .map(|d| (d.name.clone(), d.kind.sample().to_string()))
+        .collect()
+}
+
+/// Every attribute left empty, for readers we don't know.
+pub fn anonymous_attributes(
+    definitions: &[AttributeDefinition],
+) -> BTreeMap<String, String> {
+    definitions
+        .iter()
+        .map(|d| (d.name.clone(), String::new()))
+        .collect()
+}
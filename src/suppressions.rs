@@ -0,0 +1,45 @@
+use sqlx::PgExecutor;
+
+/// Whether bounces or complaints got the address on the suppression list.
+///
+/// Nothing must be sent to suppressed addresses: not even
+/// a confirmation email.
+#[tracing::instrument(skip(executor))]
+pub async fn is_suppressed(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let suppressed = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM suppressed_emails WHERE email = $1
+        ) AS "suppressed!"
+        "#,
+        email,
+    )
+    .fetch_one(executor)
+    .await?
+    .suppressed;
+    Ok(suppressed)
+}
+
+/// Put an address on the suppression list, if it's not there already.
+#[tracing::instrument(skip(executor))]
+pub async fn suppress(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email, reason, suppressed_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        email,
+        reason,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
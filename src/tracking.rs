@@ -0,0 +1,288 @@
+use crate::startup::HmacSecret;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use uuid::Uuid;
+
+// A transparent 1x1 GIF
+pub const TRACKING_PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00,
+    0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00,
+    0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// What a tracking token is used for: an open token can't be used to
+/// record clicks and a click token only ever redirects to its own URL.
+#[derive(Debug, Clone, Copy)]
+pub enum TrackingEvent<'a> {
+    Open,
+    Click(&'a str),
+}
+
+impl TrackingEvent<'_> {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TrackingEvent::Open => "open",
+            TrackingEvent::Click(_) => "click",
+        }
+    }
+}
+
+/// The delivery of an issue to a subscriber, as identified
+/// in tracking pixels and links.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackedDelivery {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Uuid,
+}
+
+impl TrackedDelivery {
+    /// The ids of the delivery followed by their signature,
+    /// encoded to be used in URLs.
+    pub fn token(&self, event: TrackingEvent, secret: &HmacSecret) -> String {
+        let mut token = self.payload();
+        token.extend(signature(&token, event, secret));
+        base64::encode_config(token, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Get the delivery back out of a token, if it has not been
+    /// tampered with.
+    pub fn parse(
+        token: &str,
+        event: TrackingEvent,
+        secret: &HmacSecret,
+    ) -> Result<Self, anyhow::Error> {
+        let token = base64::decode_config(token, base64::URL_SAFE_NO_PAD)?;
+        if token.len() <= 32 {
+            anyhow::bail!("The tracking token is too short.");
+        }
+        let (payload, tag) = token.split_at(32);
+        mac(payload, event, secret).verify_slice(tag).map_err(|_| {
+            anyhow::anyhow!("Invalid tracking token signature.")
+        })?;
+        Ok(Self {
+            newsletter_issue_id: Uuid::from_slice(&payload[..16])?,
+            subscriber_id: Uuid::from_slice(&payload[16..])?,
+        })
+    }
+
+    /// Route the web links of an HTML email through us, and add
+    /// a pixel to find out when it's opened.
+    /// Links to one of the `untracked` URLs are left alone.
+    pub fn track(
+        &self,
+        html: &str,
+        base_url: &str,
+        secret: &HmacSecret,
+        untracked: &[&str],
+    ) -> String {
+        let html = rewrite_links(html, |url| {
+            if untracked.contains(&url.trim()) {
+                return None;
+            }
+            Some(format!(
+                "{}/t/c/{}?url={}",
+                base_url,
+                self.token(TrackingEvent::Click(url), secret),
+                urlencoding::encode(url)
+            ))
+        });
+        format!(
+            r#"{}<img src="{}/t/o/{}" width="1" height="1" alt="">"#,
+            html,
+            base_url,
+            self.token(TrackingEvent::Open, secret)
+        )
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        let mut payload = self.newsletter_issue_id.as_bytes().to_vec();
+        payload.extend(self.subscriber_id.as_bytes());
+        payload
+    }
+}
+
+fn mac(
+    payload: &[u8],
+    event: TrackingEvent,
+    secret: &HmacSecret,
+) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.0.expose_secret().as_bytes())
+            .unwrap();
+    mac.update(payload);
+    mac.update(event.kind().as_bytes());
+    if let TrackingEvent::Click(url) = event {
+        mac.update(url.as_bytes());
+    }
+    mac
+}
+
+fn signature(
+    payload: &[u8],
+    event: TrackingEvent,
+    secret: &HmacSecret,
+) -> Vec<u8> {
+    mac(payload, event, secret).finalize().into_bytes().to_vec()
+}
+
+/// Replace the target of every web link of an HTML document,
+/// unless `rewrite` returns `None` for it.
+///
+/// Other links (`mailto:`, anchors...) are left alone, so are links
+/// we can't make sense of.
+pub fn rewrite_links(
+    html: &str,
+    rewrite: impl Fn(&str) -> Option<String>,
+) -> String {
+    // Same byte offsets as `html`
+    let lowercase = html.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(html.len());
+    // Where we are in `html`, and how much of it is in `rewritten` already
+    let mut offset = 0;
+    let mut copied = 0;
+    while let Some(i) = lowercase[offset..].find("href=") {
+        let start = offset + i;
+        offset = start + 5;
+        if !is_attribute(&lowercase[..start]) {
+            continue;
+        }
+        let Some((quote, value)) = quoted_value(&html[offset..]) else {
+            continue;
+        };
+        let Ok(url) = htmlescape::decode_html(value) else {
+            continue;
+        };
+        if !is_web_link(&url) {
+            continue;
+        }
+        let Some(url) = rewrite(&url) else {
+            continue;
+        };
+        rewritten.push_str(&html[copied..offset]);
+        rewritten.push(quote);
+        rewritten.push_str(&htmlescape::encode_minimal(&url));
+        rewritten.push(quote);
+        // Past the closing quote
+        offset += value.len() + 2;
+        copied = offset;
+    }
+    rewritten.push_str(&html[copied..]);
+    rewritten
+}
+
+// Within a tag, after another attribute or the tag name
+fn is_attribute(before: &str) -> bool {
+    before.ends_with(|c: char| c.is_ascii_whitespace())
+        && before.rfind('<') > before.rfind('>')
+}
+
+fn quoted_value(attribute: &str) -> Option<(char, &str)> {
+    let quote = attribute
+        .chars()
+        .next()
+        .filter(|c| *c == '"' || *c == '\'')?;
+    let end = attribute[1..].find(quote)?;
+    Some((quote, &attribute[1..end + 1]))
+}
+
+fn is_web_link(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rewrite_links, TrackedDelivery, TrackingEvent};
+    use crate::startup::HmacSecret;
+    use claim::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret(secret: &str) -> HmacSecret {
+        HmacSecret(Secret::new(secret.into()))
+    }
+
+    fn delivery() -> TrackedDelivery {
+        TrackedDelivery {
+            newsletter_issue_id: Uuid::new_v4(),
+            subscriber_id: Uuid::new_v4(),
+        }
+    }
+
+    #[test]
+    fn a_token_identifies_its_delivery() {
+        let delivery = delivery();
+        let secret = secret("secret");
+        let token = delivery.token(TrackingEvent::Open, &secret);
+        assert_ok_eq!(
+            TrackedDelivery::parse(&token, TrackingEvent::Open, &secret),
+            delivery
+        );
+    }
+
+    #[test]
+    fn tokens_only_work_for_their_event() {
+        let secret = secret("secret");
+        let event = TrackingEvent::Click("https://example.com");
+        let token = delivery().token(event, &secret);
+        for other_event in [
+            TrackingEvent::Open,
+            TrackingEvent::Click("https://example.com/phishing"),
+        ] {
+            assert_err!(TrackedDelivery::parse(&token, other_event, &secret));
+        }
+    }
+
+    #[test]
+    fn tokens_must_be_signed_with_our_secret() {
+        let token = delivery().token(TrackingEvent::Open, &secret("theirs"));
+        assert_err!(TrackedDelivery::parse(
+            &token,
+            TrackingEvent::Open,
+            &secret("ours")
+        ));
+        assert_err!(TrackedDelivery::parse(
+            "garbage",
+            TrackingEvent::Open,
+            &secret("ours")
+        ));
+    }
+
+    #[test]
+    fn only_web_links_are_rewritten() {
+        let html = r##"<a href="https://example.com/?a=1&amp;b=2">Web</a>
+            <a class="x" HREF='http://example.com'>Upper case</a>
+            <a href="mailto:editor@example.com">Mail</a>
+            <a href="#top">Top</a>
+            <p>Write href="https://example.com" in the text</p>"##;
+        let rewritten = rewrite_links(html, |url| {
+            Some(format!("https://t.example/?u={}", url))
+        });
+        assert_eq!(
+            rewritten,
+            r##"<a href="https://t.example/?u=https://example.com/?a=1&amp;b=2">Web</a>
+            <a class="x" HREF='https://t.example/?u=http://example.com'>Upper case</a>
+            <a href="mailto:editor@example.com">Mail</a>
+            <a href="#top">Top</a>
+            <p>Write href="https://example.com" in the text</p>"##
+        );
+    }
+
+    #[test]
+    fn untracked_links_are_left_alone() {
+        let html = r#"<a href="https://example.com/news">News</a>
+            <a href="https://example.com/unsubscribe?token=a&amp;b">Leave</a>"#;
+        let tracked = delivery().track(
+            html,
+            "https://t.example",
+            &secret("secret"),
+            &["https://example.com/unsubscribe?token=a&b"],
+        );
+        assert!(!tracked.contains(r#"href="https://example.com/news""#));
+        assert!(tracked.contains(
+            r#"<a href="https://example.com/unsubscribe?token=a&amp;b">Leave</a>"#
+        ));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber,
+    publish_newsletter_issue, spawn_app, when_sending_an_email, TestApp,
+};
+use wiremock::ResponseTemplate;
+
+// Publish an issue that can't be delivered to a single subscriber
+async fn create_dead_letter(app: &TestApp) -> String {
+    let email = create_confirmed_subscriber(app).await;
+    let _guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .named("Failing delivery")
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter_issue(app).await;
+    app.dispatch_all_pending_emails().await;
+    email
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_dead_letters() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_dead_letters().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_requeue_a_dead_letter() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_requeue_dead_letter(&serde_json::json!({
+            "newsletter_issue_id": uuid::Uuid::new_v4().to_string(),
+            "subscriber_email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn dead_letters_are_listed_and_can_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = create_dead_letter(&app).await;
+
+    // Act - Part 1 - Inspect the dead letters
+    let html_page = app.get_dead_letters_html().await;
+    assert!(html_page.contains(&email));
+    assert!(html_page.contains("Newsletter title"));
+    let issue_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM issue_delivery_dead_letters"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+
+    // Act - Part 2 - Requeue
+    let response = app
+        .post_requeue_dead_letter(&serde_json::json!({
+            "newsletter_issue_id": issue_id.to_string(),
+            "subscriber_email": email,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dead-letters");
+    let html_page = app.get_dead_letters_html().await;
+    assert!(html_page.contains("<p><i>The delivery has been requeued.</i></p>"));
+    assert!(!html_page.contains(&email));
+
+    // Act - Part 3 - Deliver
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the issue has been delivered
+}
+
+#[tokio::test]
+async fn requeuing_an_unknown_dead_letter_is_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_requeue_dead_letter(&serde_json::json!({
+            "newsletter_issue_id": uuid::Uuid::new_v4().to_string(),
+            "subscriber_email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dead-letters");
+    let html_page = app.get_dead_letters_html().await;
+    assert!(html_page
+        .contains("<p><i>The delivery is no longer dead-lettered.</i></p>"));
+}
+
+#[tokio::test]
+async fn cancelling_an_issue_drops_its_dead_letters() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = create_dead_letter(&app).await;
+    // Other deliveries of the issue are still going
+    let issue_id = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues SET status = 'sending'
+        RETURNING newsletter_issue_id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+
+    // Act
+    app.post_issue_action(issue_id, "cancel").await;
+
+    // Assert
+    let html_page = app.get_dead_letters_html().await;
+    assert!(!html_page.contains(&email));
+}
+
+#[tokio::test]
+async fn dead_letters_of_a_cancelled_issue_cannot_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let email = create_dead_letter(&app).await;
+    // Cancelled before its dead letters were dropped with it
+    let issue_id = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues SET status = 'cancelled'
+        RETURNING newsletter_issue_id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+
+    // Act
+    let response = app
+        .post_requeue_dead_letter(&serde_json::json!({
+            "newsletter_issue_id": issue_id.to_string(),
+            "subscriber_email": email,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dead-letters");
+    let html_page = app.get_dead_letters_html().await;
+    assert!(html_page.contains(
+        "<p><i>The issue has been cancelled, its deliveries can't be \
+        requeued.</i></p>"
+    ));
+    let n_tasks =
+        sqlx::query!("SELECT COUNT(*) AS n FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+    assert_eq!(n_tasks, Some(0));
+}
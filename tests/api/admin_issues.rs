@@ -0,0 +1,438 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber,
+    publish_newsletter_issue, spawn_app, spawn_app_with, when_sending_an_email,
+    TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::body_string_contains;
+use wiremock::ResponseTemplate;
+
+async fn issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_issues() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_issues("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_progress_of_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_issues(&format!("/{}", Uuid::new_v4())).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn every_delivery_is_logged() {
+    // Arrange
+    let app = spawn_app().await;
+    let delivered = create_confirmed_subscriber(&app).await;
+    let rejected = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .and(body_string_contains(&delivered))
+        .respond_with(ResponseTemplate::new(200).set_body_json(
+            serde_json::json!({
+                "To": delivered,
+                "SubmittedAt": "2023-01-16T07:15:02.0000000-05:00",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK"
+            }),
+        ))
+        .mount(&app.email_server)
+        .await;
+    when_sending_an_email()
+        .and(body_string_contains(&rejected))
+        .respond_with(ResponseTemplate::new(422).set_body_json(
+            serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "Address is inactive."
+            }),
+        ))
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    publish_newsletter_issue(&app).await;
+    let issue_id = issue_id(&app).await;
+    let pending = sqlx::query!(
+        "SELECT status FROM newsletter_deliveries \
+        WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(pending.len(), 2);
+    assert!(pending.iter().all(|d| d.status == "pending"));
+
+    // Act - Part 2 - Deliver
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let sent = sqlx::query!(
+        "SELECT status, n_attempts, provider_message_id, sent_at \
+        FROM newsletter_deliveries WHERE subscriber_email = $1",
+        delivered
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(sent.status, "sent");
+    assert_eq!(sent.n_attempts, 1);
+    assert_eq!(
+        sent.provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+    assert!(sent.sent_at.is_some());
+    let failed = sqlx::query!(
+        "SELECT status, last_error \
+        FROM newsletter_deliveries WHERE subscriber_email = $1",
+        rejected
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(failed.status, "failed");
+    assert!(failed.last_error.unwrap().contains("Address is inactive."));
+}
+
+#[tokio::test]
+async fn failed_attempts_are_logged_until_delivery_succeeds() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!(
+        "SELECT status, n_attempts, last_error \
+        FROM newsletter_deliveries WHERE subscriber_email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.n_attempts, 2);
+    assert!(delivery.last_error.is_none());
+}
+
+#[tokio::test]
+async fn retried_deliveries_are_logged_with_their_error() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.initial_backoff_milliseconds = 60 * 60 * 1000;
+    })
+    .await;
+    let email = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!(
+        "SELECT status, n_attempts, last_error \
+        FROM newsletter_deliveries WHERE subscriber_email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "retrying");
+    assert_eq!(delivery.n_attempts, 1);
+    assert!(delivery.last_error.unwrap().contains("503"));
+}
+
+#[tokio::test]
+async fn the_issue_page_shows_delivery_counts_and_recipients() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.initial_backoff_milliseconds = 60 * 60 * 1000;
+    })
+    .await;
+    let delivered = create_confirmed_subscriber(&app).await;
+    let failing = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .and(body_string_contains(&delivered))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    when_sending_an_email()
+        .and(body_string_contains(&failing))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+    let issue_id = issue_id(&app).await;
+
+    // Act - Part 1 - List issues
+    let html_page = app.get_admin_issues_html("").await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/issues/{}">Newsletter title</a>"#,
+        issue_id
+    )));
+
+    // Act - Part 2 - Inspect the issue
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<li>Sent: 1</li>"));
+    assert!(html_page.contains("<li>Failed: 0</li>"));
+    assert!(html_page.contains("<li>Pending: 1</li>"));
+    assert!(html_page.contains("<li>Skipped: 0</li>"));
+    assert!(html_page.contains(&delivered));
+    assert!(html_page.contains(&failing));
+}
+
+#[tokio::test]
+async fn recipients_can_be_searched() {
+    // Arrange
+    let app = spawn_app().await;
+    let wanted = create_confirmed_subscriber(&app).await;
+    let other = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter_issue(&app).await;
+    let issue_id = issue_id(&app).await;
+
+    // Act
+    let html_page = app
+        .get_admin_issues_html(&format!(
+            "/{}?search={}",
+            issue_id,
+            urlencoding::encode(&wanted.to_uppercase())
+        ))
+        .await;
+
+    // Assert
+    assert!(html_page.contains(&wanted));
+    assert!(!html_page.contains(&other));
+}
+
+#[tokio::test]
+async fn an_unknown_issue_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_admin_issues(&format!("/{}", Uuid::new_v4())).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_pause_resume_or_cancel_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for action in ["pause", "resume", "cancel"] {
+        // Act
+        let response = app.post_issue_action(Uuid::new_v4(), action).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn an_issue_is_completed_once_every_delivery_is_done() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    publish_newsletter_issue(&app).await;
+    let issue_id = issue_id(&app).await;
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p>Status: sending</p>"));
+
+    // Act - Part 2 - Deliver
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p>Status: completed</p>"));
+    for action in ["pause", "resume", "cancel"] {
+        assert!(!html_page
+            .contains(&format!("/admin/issues/{}/{}", issue_id, action)));
+    }
+}
+
+#[tokio::test]
+async fn an_issue_without_recipients_is_completed_right_away() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+
+    // Assert
+    let issue_id = issue_id(&app).await;
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p>Status: completed</p>"));
+}
+
+#[tokio::test]
+async fn a_paused_issue_is_not_delivered_until_it_is_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter_issue(&app).await;
+    let issue_id = issue_id(&app).await;
+
+    // Act - Part 1 - Pause
+    let response = app.post_issue_action(issue_id, "pause").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p><i>The issue has been paused.</i></p>"));
+    assert!(html_page.contains("<p>Status: paused</p>"));
+
+    // Act - Part 2 - Nothing goes out
+    let guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    drop(guard);
+    assert_eq!(app.n_pending_deliveries().await, 1);
+
+    // Act - Part 3 - Resume
+    let response = app.post_issue_action(issue_id, "resume").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p><i>The issue has been resumed.</i></p>"));
+
+    // Act - Part 4 - Deliver
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the issue has been delivered
+}
+
+#[tokio::test]
+async fn no_emails_go_out_after_an_issue_is_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter_issue(&app).await;
+    let issue_id = issue_id(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_issue_action(issue_id, "cancel").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    assert_eq!(app.n_pending_deliveries().await, 0);
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p><i>The issue has been cancelled.</i></p>"));
+    assert!(html_page.contains("<p>Status: cancelled</p>"));
+    assert!(html_page.contains("<li>Pending: 0</li>"));
+    assert!(html_page.contains("<li>Cancelled: 2</li>"));
+    // Mock verifies on Drop that no email has been sent
+}
+
+#[tokio::test]
+async fn a_background_worker_stops_delivering_a_cancelled_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter_issue(&app).await;
+    let issue_id = issue_id(&app).await;
+    app.post_issue_action(issue_id, "pause").await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let worker = app.spawn_worker();
+
+    // Act
+    app.post_issue_action(issue_id, "cancel").await;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    // Assert
+    worker.stop().await;
+    assert_eq!(app.n_pending_deliveries().await, 0);
+    // Mock verifies on Drop that no email has been sent
+}
+
+#[tokio::test]
+async fn a_cancelled_issue_cannot_be_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter_issue(&app).await;
+    let issue_id = issue_id(&app).await;
+    app.post_issue_action(issue_id, "cancel").await;
+
+    // Act
+    let response = app.post_issue_action(issue_id, "resume").await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(
+        html_page.contains("<p><i>Only a paused issue can be resumed.</i></p>")
+    );
+    assert!(html_page.contains("<p>Status: cancelled</p>"));
+}
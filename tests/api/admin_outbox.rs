@@ -0,0 +1,90 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use zero2prod::configuration::EmailBackend;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_browse_the_outbox() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_outbox("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_outbox_is_unavailable_with_other_email_backends() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app.get_admin_outbox("").await.text().await.unwrap();
+
+    // Assert
+    assert!(html_page.contains("The outbox is only available"));
+}
+
+#[tokio::test]
+async fn confirmation_emails_can_be_read_from_the_outbox() {
+    // Arrange
+    let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    let app = spawn_app_with(|c| {
+        c.email_client.backend = EmailBackend::Outbox;
+        c.email_client.outbox_directory =
+            Some(directory.to_string_lossy().into_owned());
+    })
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Subscribe
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 2 - List the outbox
+    let html_page = app.get_admin_outbox("").await.text().await.unwrap();
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(html_page.contains("Welcome!"));
+
+    // Act - Part 3 - Open the email
+    let message_path = html_page
+        .split(r#"href="/admin/outbox"#)
+        .nth(1)
+        .and_then(|s| s.split('"').next())
+        .unwrap()
+        .to_owned();
+    let html_page = app
+        .get_admin_outbox(&message_path)
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("/subscriptions/confirm?subscription_token="));
+    // Scripts in captured emails must not run with our session
+    assert!(html_page.contains(r#"<iframe sandbox="" srcdoc="#));
+}
+
+#[tokio::test]
+async fn unknown_outbox_messages_are_a_404() {
+    // Arrange
+    let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    let app = spawn_app_with(|c| {
+        c.email_client.backend = EmailBackend::Outbox;
+        c.email_client.outbox_directory =
+            Some(directory.to_string_lossy().into_owned());
+    })
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .get_admin_outbox(&format!("/{}", uuid::Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,272 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app,
+    when_sending_an_email, TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+fn layout_body(name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "html_template": "<h1>The Newsletter</h1>{{ content }}\
+            <footer>Sent to {{ email }}</footer>",
+        "text_template": "THE NEWSLETTER\n\n{{ content }}\n\n\
+            Sent to {{ email }}",
+    })
+}
+
+/// Create a layout through the admin pages, returns its id.
+async fn create_layout(app: &TestApp, name: &str) -> Uuid {
+    let response = app.post_layout("", &layout_body(name)).await;
+    let location = response.headers().get("Location").unwrap();
+    location
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/layouts/")
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_layouts() {
+    // Arrange
+    let app = spawn_app().await;
+    let layout_id = Uuid::new_v4();
+
+    for path in ["".to_string(), format!("/{}", layout_id)] {
+        // Act
+        let get_response = app.get_layouts(&path).await;
+        let post_response =
+            app.post_layout(&path, &layout_body("Branded")).await;
+
+        // Assert
+        assert_is_redirect_to(&get_response, "/login");
+        assert_is_redirect_to(&post_response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn a_layout_can_be_created_and_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create
+    let layout_id = create_layout(&app, "Branded").await;
+    let html_page = app.get_layouts_html("").await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/layouts/{}">Branded</a>"#,
+        layout_id
+    )));
+
+    // Act - Part 2 - Edit
+    let response = app
+        .post_layout(&format!("/{}", layout_id), &layout_body("Rebranded"))
+        .await;
+
+    // Assert
+    let path = format!("/{}", layout_id);
+    assert_is_redirect_to(&response, &format!("/admin/layouts{}", path));
+    let html_page = app.get_layouts_html(&path).await;
+    assert!(html_page.contains("<p><i>The layout has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Rebranded""#));
+}
+
+#[tokio::test]
+async fn invalid_layouts_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "name": "No content",
+                "html_template": "<h1>The Newsletter</h1>",
+                "text_template": "{{ content }}",
+            }),
+            "The HTML layout must include {{ content }}.",
+        ),
+        (
+            serde_json::json!({
+                "name": "Typo",
+                "html_template": "{{ content }}",
+                "text_template": "{{ content }} {{ unsubscribe_link }}",
+            }),
+            "unsubscribe_link",
+        ),
+        (
+            serde_json::json!({
+                "name": "Unclosed",
+                "html_template": "{{#if name}}{{ content }}",
+                "text_template": "{{ content }}",
+            }),
+            "The HTML layout is invalid",
+        ),
+        (
+            serde_json::json!({
+                "name": " ",
+                "html_template": "{{ content }}",
+                "text_template": "{{ content }}",
+            }),
+            "The layout needs a name.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        // Act
+        let response = app.post_layout("", &body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/layouts");
+        let html_page = app.get_layouts_html("").await;
+        assert!(
+            html_page.contains(error_message),
+            "The error for {} was not shown.",
+            body
+        );
+    }
+    assert!(app
+        .get_layouts_html("")
+        .await
+        .contains("<li>No layouts</li>"));
+}
+
+#[tokio::test]
+async fn layout_names_are_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_layout(&app, "Branded").await;
+
+    // Act
+    let response = app.post_layout("", &layout_body("Branded")).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/layouts");
+    let html_page = app.get_layouts_html("").await;
+    assert!(html_page.contains("There is a layout named Branded already."));
+}
+
+#[tokio::test]
+async fn issues_are_wrapped_in_their_layout_and_personalized() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let layout_id = create_layout(&app, "Branded").await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ name }}!",
+            "html_content": "<p>Hi {{ name }}!</p>",
+            "layout_id": layout_id.to_string(),
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let name = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .name;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with(&format!(
+        "THE NEWSLETTER\n\nHi {}!\n\nSent to {}",
+        name, email
+    )));
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<h1>The Newsletter</h1><p>Hi "));
+    // The unsubscribe footer is always there
+    let unsubscribe_link = "/subscriptions/unsubscribe?unsubscribe_token=";
+    assert!(text_body.contains(unsubscribe_link));
+}
+
+#[tokio::test]
+async fn issues_with_broken_templates_cannot_be_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ nmae }}!",
+            "html_content": "<p>Hi {{ name }}!</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("nmae"));
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS n FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, Some(0));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_preview_is_rendered_with_a_sample_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let layout_id = create_layout(&app, "Branded").await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Hi {{ name }}!",
+            "html_content": "<p>Hi {{ name }}!</p>",
+            "layout_id": layout_id.to_string(),
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "action": "save_draft",
+        }))
+        .await;
+    let location = response.headers().get("Location").unwrap();
+    let issue_id: Uuid = location
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/newsletters/")
+        .trim_end_matches("/edit")
+        .parse()
+        .unwrap();
+
+    // Act
+    let html_page = app.get_draft_html(issue_id, "preview").await;
+
+    // Assert
+    assert!(html_page.contains("<pre>THE NEWSLETTER\n\nHi Ursula Le Guin!"));
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page.contains(&format!(
+        r#"<option value="{}" selected>Branded</option>"#,
+        layout_id
+    )));
+}
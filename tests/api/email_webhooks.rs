@@ -0,0 +1,223 @@
+use crate::helpers::{
+    create_confirmed_subscriber, publish_newsletter_issue, spawn_app,
+    when_sending_an_email, TestApp,
+};
+use wiremock::ResponseTemplate;
+
+fn bounce(email: &str, bounce_type: &str, id: u64) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": id,
+        "Type": bounce_type,
+        "Email": email,
+        "Description": "The server was unable to deliver your message",
+    })
+}
+
+async fn is_suppressed(app: &TestApp, email: &str) -> bool {
+    sqlx::query!(
+        "SELECT email FROM suppressed_emails WHERE email = $1",
+        email
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .is_some()
+}
+
+#[tokio::test]
+async fn webhooks_must_be_authenticated() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = bounce("ursula@example.com", "HardBounce", 1);
+
+    for password in [None, Some("wrong-password")] {
+        // Act
+        let response = app
+            .api_client
+            .post(format!("{}/webhooks/email/postmark", &app.address))
+            .basic_auth("webhook", password)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            r#"Basic realm="webhooks""#,
+            response.headers()["WWW-Authenticate"]
+        );
+    }
+    assert!(!is_suppressed(&app, "ursula@example.com").await);
+}
+
+#[tokio::test]
+async fn unknown_providers_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = bounce("ursula@example.com", "HardBounce", 1);
+
+    // Act
+    let response = app.post_email_webhook("mailchimp", &body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn invalid_payloads_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_email_webhook(
+            "postmark",
+            &serde_json::json!({ "RecordType": "Bounce" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn hard_bounces_and_complaints_suppress_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [
+        bounce("ursula@example.com", "HardBounce", 1),
+        serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "ID": 2,
+            "Type": "SpamComplaint",
+            "Email": "octavia@example.com",
+        }),
+    ];
+
+    for body in test_cases {
+        // Act
+        let response = app.post_email_webhook("postmark", &body).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        let email = body["Email"].as_str().unwrap();
+        assert!(is_suppressed(&app, email).await, "{}", email);
+    }
+}
+
+#[tokio::test]
+async fn soft_bounces_and_other_records_are_only_acknowledged() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [
+        bounce("ursula@example.com", "SoftBounce", 1),
+        serde_json::json!({
+            "RecordType": "Delivery",
+            "Recipient": "ursula@example.com",
+        }),
+    ];
+
+    for body in test_cases {
+        // Act
+        let response = app.post_email_webhook("postmark", &body).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    assert!(!is_suppressed(&app, "ursula@example.com").await);
+}
+
+#[tokio::test]
+async fn redelivered_webhooks_are_recorded_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = bounce("ursula@example.com", "HardBounce", 1);
+
+    // Act
+    for _ in 0..2 {
+        let response = app.post_email_webhook("postmark", &body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Assert
+    let n_events = sqlx::query!("SELECT COUNT(*) AS n FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_events, Some(1));
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_do_not_get_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_email_webhook("postmark", &bounce(&email, "HardBounce", 1))
+        .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn issues_are_not_sent_to_addresses_suppressed_after_publishing() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter_issue(&app).await;
+
+    // Act
+    app.post_email_webhook("postmark", &bounce(&email, "HardBounce", 1))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery =
+        sqlx::query!("SELECT status, last_error FROM newsletter_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(delivery.status, "skipped");
+    assert_eq!(delivery.last_error.as_deref(), Some("Suppressed address"));
+}
+
+#[tokio::test]
+async fn suppressed_addresses_cannot_subscribe() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_email_webhook(
+        "postmark",
+        &bounce("ursula_le_guin@gmail.com", "HardBounce", 1),
+    )
+    .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -1,14 +1,28 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
-use zero2prod::email_client::EmailClient;
-use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+use zero2prod::configuration::{
+    get_configuration, DatabaseSettings, EmailBackend, Settings,
+};
+use zero2prod::email_client::EmailSender;
+use zero2prod::issue_delivery_worker::{
+    publish_due_issues, run_worker_until_stopped, try_execute_task,
+    ExecutionOutcome, LinkSettings,
+};
+use zero2prod::send_rate_limiter::SendRateLimiter;
 use zero2prod::startup::get_connection_pool;
-use zero2prod::startup::Application;
+use zero2prod::startup::{Application, HmacSecret};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 pub struct TestUser {
@@ -91,20 +105,35 @@ pub struct ConfirmationLinks {
     pub plain_text: reqwest::Url,
 }
 
+/// Unsubscribe links embedded in a newsletter issue
+pub struct UnsubscribeLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+/// Preference centre links emailed to confirmed subscribers
+pub struct PreferencesLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
 pub struct TestApp {
     pub address: String,
     pub db_pool: PgPool,
     pub email_server: MockServer,
     pub port: u16,
+    pub base_url: String,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient,
+    pub email_client: Arc<dyn EmailSender>,
+    pub configuration: Settings,
+    pub rate_limiter: SendRateLimiter,
 }
 
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -112,12 +141,96 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_resend_confirmation(
+        &self,
+        email: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/subscriptions/resend-confirmation",
+                &self.address
+            ))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(
+        &self,
+        unsubscribe_token: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(&serde_json::json!({
+                "unsubscribe_token": unsubscribe_token
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     /// Extract the confirmation links embedded in
     /// the request to the email API
     pub fn get_confirmation_links(
         &self,
         email_request: &wiremock::Request,
     ) -> ConfirmationLinks {
+        let (html, plain_text) = self.get_links(email_request);
+        ConfirmationLinks { html, plain_text }
+    }
+
+    /// Extract the unsubscribe links embedded in
+    /// a newsletter issue sent to the email API
+    pub fn get_unsubscribe_links(
+        &self,
+        email_request: &wiremock::Request,
+    ) -> UnsubscribeLinks {
+        let (html, plain_text) = self.get_links(email_request);
+        UnsubscribeLinks { html, plain_text }
+    }
+
+    /// Extract the preference centre links emailed
+    /// to subscribers who subscribe again
+    pub fn get_preferences_links(
+        &self,
+        email_request: &wiremock::Request,
+    ) -> PreferencesLinks {
+        let (html, plain_text) = self.get_links(email_request);
+        PreferencesLinks { html, plain_text }
+    }
+
+    /// Extract the URL advertised in the `List-Unsubscribe`
+    /// header of a newsletter issue sent to the email API
+    pub fn get_list_unsubscribe_link(
+        &self,
+        email_request: &wiremock::Request,
+    ) -> reqwest::Url {
+        let body: serde_json::Value =
+            serde_json::from_slice(&email_request.body).unwrap();
+        let header = body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .unwrap();
+        let raw_link = header["Value"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        let mut link = reqwest::Url::parse(raw_link).unwrap();
+        assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+        link.set_port(Some(self.port)).unwrap();
+        link
+    }
+
+    // Extract the only link in the HTML body and
+    // the only link in the plain text body
+    fn get_links(
+        &self,
+        email_request: &wiremock::Request,
+    ) -> (reqwest::Url, reqwest::Url) {
         let body: serde_json::Value =
             serde_json::from_slice(&email_request.body).unwrap();
         // Extract the link from one of the request fields.
@@ -134,14 +247,14 @@ impl TestApp {
             confirmation_link.set_port(Some(self.port)).unwrap();
             confirmation_link
         };
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
-        ConfirmationLinks { html, plain_text }
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
+        (html, plain_text)
     }
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -151,6 +264,26 @@ impl TestApp {
         self.get_publish_newsletter().await.text().await.unwrap()
     }
 
+    /// Post to the webhook of `provider` the way it would,
+    /// with the credentials from the configuration.
+    pub async fn post_email_webhook(
+        &self,
+        provider: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        let webhook = &self.configuration.email_client.webhook;
+        self.api_client
+            .post(format!("{}/webhooks/email/{}", &self.address, provider))
+            .basic_auth(
+                &webhook.username,
+                Some(webhook.password.expose_secret()),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_publish_newsletter<Body>(
         &self,
         body: &Body,
@@ -159,7 +292,52 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `page` is either `edit` or `preview`.
+    pub async fn get_draft(
+        &self,
+        issue_id: uuid::Uuid,
+        page: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}/{}",
+                &self.address, issue_id, page
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft_html(
+        &self,
+        issue_id: uuid::Uuid,
+        page: &str,
+    ) -> String {
+        self.get_draft(issue_id, page).await.text().await.unwrap()
+    }
+
+    /// `action` is one of `edit`, `autosave` or `publish`.
+    pub async fn post_draft<Body>(
+        &self,
+        issue_id: uuid::Uuid,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/{}",
+                &self.address, issue_id, action
+            ))
             .form(body)
             .send()
             .await
@@ -171,7 +349,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             // This reqwest method makes sure that the body
             // is URL-encoded and the `Content-Type` header is
             // set accordingly
@@ -185,7 +363,7 @@ impl TestApp {
     // therefore we do not expose the underlying reqwest::Response
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -196,7 +374,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -207,9 +385,309 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    pub async fn get_admin_outbox(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/outbox{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_issues(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_issues_html(&self, path: &str) -> String {
+        self.get_admin_issues(path).await.text().await.unwrap()
+    }
+
+    /// `action` is one of `pause`, `resume` or `cancel`.
+    pub async fn post_issue_action(
+        &self,
+        issue_id: uuid::Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/{}",
+                &self.address, issue_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_issue_action_form<Body>(
+        &self,
+        issue_id: uuid::Uuid,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/{}",
+                &self.address, issue_id, action
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `path` is either empty, for the list, or `/{layout_id}`.
+    pub async fn get_layouts(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/layouts{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_layouts_html(&self, path: &str) -> String {
+        self.get_layouts(path).await.text().await.unwrap()
+    }
+
+    pub async fn post_layout<Body>(
+        &self,
+        path: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/layouts{}", &self.address, path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `path` is either empty, for the list, or `/{topic_id}`.
+    pub async fn get_topics(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/topics{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_topics_html(&self, path: &str) -> String {
+        self.get_topics(path).await.text().await.unwrap()
+    }
+
+    pub async fn post_topic<Body>(
+        &self,
+        path: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/topics{}", &self.address, path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preferences(
+        &self,
+        unsubscribe_token: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("unsubscribe_token", unsubscribe_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// The body is a list of pairs: there is one `topic_id` per topic.
+    pub async fn post_preferences(
+        &self,
+        body: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `path` is either empty, for the list, or `/{segment_id}`.
+    pub async fn get_segments(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/segments{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_segments_html(&self, path: &str) -> String {
+        self.get_segments(path).await.text().await.unwrap()
+    }
+
+    pub async fn post_segment<Body>(
+        &self,
+        path: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/segments{}", &self.address, path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_count_recipients<Body>(
+        &self,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/recipients", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `path` is either empty, for the list, or `/{attribute_id}`.
+    pub async fn get_attributes(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/attributes{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_attributes_html(&self, path: &str) -> String {
+        self.get_attributes(path).await.text().await.unwrap()
+    }
+
+    pub async fn post_attribute<Body>(
+        &self,
+        path: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/attributes{}", &self.address, path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `path` is either a search, e.g. `?email=ursula`,
+    /// or `/{subscriber_id}`.
+    pub async fn get_subscribers(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self, path: &str) -> String {
+        self.get_subscribers(path).await.text().await.unwrap()
+    }
+
+    pub async fn post_subscriber(
+        &self,
+        path: &str,
+        fields: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/subscribers{}", &self.address, path))
+            .form(fields)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `path` is either empty, for the list, or `/{slug}`.
+    pub async fn get_analytics(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/analytics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_analytics_html(&self) -> String {
+        self.get_analytics().await.text().await.unwrap()
+    }
+
+    pub async fn get_archive(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive_html(&self, path: &str) -> String {
+        self.get_archive(path).await.text().await.unwrap()
+    }
+
+    /// `feed` is either `feed.xml` or `atom.xml`.
+    pub async fn get_feed(&self, feed: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/{}", &self.address, feed))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_dead_letters(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dead-letters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_dead_letters_html(&self) -> String {
+        self.get_dead_letters().await.text().await.unwrap()
+    }
+
+    pub async fn post_requeue_dead_letter<Body>(
+        &self,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/dead-letters/requeue", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -223,7 +701,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -236,28 +714,106 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    /// Run the delivery workers in the background, as in production.
+    pub fn spawn_worker(&self) -> TestWorker {
+        let (stop, worker_stop) = watch::channel(false);
+        let handle = tokio::spawn(run_worker_until_stopped(
+            self.configuration.clone(),
+            worker_stop,
+        ));
+        TestWorker { stop, handle }
+    }
+
+    /// Wait until the email server has received `n` requests in total.
+    pub async fn wait_for_email_requests(&self, n: usize) {
+        for _ in 0..50 {
+            let received = self.email_server.received_requests().await;
+            if received.unwrap().len() >= n {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        panic!("The email server did not receive {} requests.", n);
+    }
+
+    pub async fn n_pending_deliveries(&self) -> i64 {
+        sqlx::query!("SELECT COUNT(*) AS n FROM issue_delivery_queue")
+            .fetch_one(&self.db_pool)
+            .await
+            .unwrap()
+            .n
+            .unwrap()
+    }
+
+    /// Returns how many scheduled issues have been published.
+    pub async fn publish_due_issues(&self) -> usize {
+        publish_due_issues(&self.db_pool).await.unwrap()
+    }
+
+    /// Process one batch of tasks, as a worker would.
+    pub async fn try_execute_tasks(
+        &self,
+    ) -> Result<ExecutionOutcome, anyhow::Error> {
+        let links = LinkSettings {
+            base_url: self.base_url.clone(),
+            hmac_secret: HmacSecret(
+                self.configuration.application.hmac_secret.clone(),
+            ),
+        };
+        try_execute_task(
+            &self.db_pool,
+            self.email_client.as_ref(),
+            &links,
+            &self.configuration.issue_delivery,
+            &self.rate_limiter,
+        )
+        .await
+    }
+
+    /// Deliver every task that is due, waiting for the rate limiter
+    /// like the workers do.
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
-            {
-                break;
+            match self.try_execute_tasks().await.unwrap() {
+                ExecutionOutcome::TaskCompleted => {}
+                ExecutionOutcome::Throttled(wait_time) => {
+                    tokio::time::sleep(wait_time).await
+                }
+                ExecutionOutcome::EmptyQueue => break,
             }
         }
     }
 }
 
+/// Delivery workers running in the background.
+pub struct TestWorker {
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<Result<(), anyhow::Error>>,
+}
+
+impl TestWorker {
+    /// Ask the workers to stop and wait for them to exit.
+    pub async fn stop(self) {
+        self.stop.send(true).unwrap();
+        self.handle.await.unwrap().unwrap();
+    }
+}
+
 /// Spin up an instance of our application
 /// an returns its address (i.e. http://localhost:XXXX)
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Same as `spawn_app`, with a chance to tweak the configuration
+/// before the application is built.
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
     // The first time `initialize` is invoked the code
     // in `TRACING` is executed.
     // All other invocations will instead skip execution.
@@ -272,7 +828,15 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         // Use a random OS port
         c.application.port = 0;
+        // Talk to the mock server, whatever the local backend is
+        c.email_client.backend = EmailBackend::Postmark;
         c.email_client.base_url = email_server.uri();
+        // Retry failed deliveries straight away
+        c.issue_delivery.initial_backoff_milliseconds = 0;
+        // The webhook password is only ever set by the environment
+        c.email_client.webhook.password =
+            Secret::new(Uuid::new_v4().to_string());
+        customise(&mut c);
         c
     };
     // Create and migrate the database
@@ -283,7 +847,7 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
     // Create the `reqwest` client
     let api_client = reqwest::Client::builder()
@@ -295,11 +859,16 @@ pub async fn spawn_app() -> TestApp {
     let test_app = TestApp {
         address: format!("http://localhost:{}", port),
         port,
+        base_url: configuration.application.base_url.clone(),
         db_pool: get_connection_pool(&configuration.database),
         email_server,
         test_user: TestUser::generate(),
         api_client,
-        email_client: configuration.email_client.client(),
+        email_client: configuration.email_client.clone().client(),
+        rate_limiter: SendRateLimiter::new(
+            configuration.issue_delivery.rate_limit.clone(),
+        ),
+        configuration,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -329,6 +898,104 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
     connection_pool
 }
 
+// Short-hand for a common mocking setup
+/// Publish a newsletter issue, without delivering it.
+pub async fn publish_newsletter_issue(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+}
+
+pub fn when_sending_an_email() -> MockBuilder {
+    Mock::given(path("/email")).and(method("POST"))
+}
+
+/// Create a subscriber who has not confirmed their subscription yet.
+/// Returns their email address and the confirmation links they received.
+pub async fn create_unconfirmed_subscriber(
+    app: &TestApp,
+) -> (String, ConfirmationLinks) {
+    // We are working with multiple subscribers now,
+    // their details must be randomised to avoid conflicts!
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email
+    }))
+    .unwrap();
+
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    (email, app.get_confirmation_links(email_request))
+}
+
+/// Create a subscriber and confirm their subscription.
+/// Returns their email address.
+pub async fn create_confirmed_subscriber(app: &TestApp) -> String {
+    let (email, confirmation_links) = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    email
+}
+
+/// Subscribe `email` with more fields than the usual name
+/// (topics...) and confirm the subscription.
+pub async fn create_confirmed_subscriber_with(
+    app: &TestApp,
+    email: &str,
+    fields: &[(&str, String)],
+) {
+    let mut body =
+        vec![("name", "le guin".to_string()), ("email", email.into())];
+    body.extend_from_slice(fields);
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(serde_urlencoded::to_string(&body).unwrap())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(&email_request);
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
 // Little helper function - we will be doing this check several times
 // throughout this chapter and the next one
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
@@ -0,0 +1,315 @@
+use crate::helpers::{
+    create_confirmed_subscriber, publish_newsletter_issue, spawn_app,
+    spawn_app_with, when_sending_an_email,
+};
+use sqlx::Executor;
+use std::collections::HashMap;
+use std::time::Duration;
+use wiremock::ResponseTemplate;
+use zero2prod::issue_delivery_worker::ExecutionOutcome;
+
+#[tokio::test]
+async fn the_worker_is_woken_up_as_soon_as_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let worker = app.spawn_worker();
+    // Give the workers the time to find an empty queue and go idle
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+
+    // Assert - well before the workers would have polled the queue again
+    app.wait_for_email_requests(2).await;
+    worker.stop().await;
+    assert_eq!(app.n_pending_deliveries().await, 0);
+}
+
+#[tokio::test]
+async fn issues_are_delivered_by_a_pool_of_workers() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.n_workers = 3;
+        c.issue_delivery.batch_size = 2;
+    })
+    .await;
+    for _ in 0..5 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(5)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let worker = app.spawn_worker();
+    publish_newsletter_issue(&app).await;
+
+    // Assert
+    app.wait_for_email_requests(5 + 5).await;
+    worker.stop().await;
+    assert_eq!(app.n_pending_deliveries().await, 0);
+    // Mock verifies on Drop that every subscriber got the issue exactly once
+}
+
+#[tokio::test]
+async fn emails_sent_before_a_failure_are_not_sent_again() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.batch_size = 10;
+    })
+    .await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    let n_confirmation_emails = 3;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter_issue(&app).await;
+    // The database fails to record the second delivery,
+    // once its email has gone out
+    app.db_pool
+        .execute(
+            r#"
+            CREATE FUNCTION fail_second_delivery() RETURNS trigger AS $$
+            BEGIN
+                IF EXISTS (
+                    SELECT 1 FROM newsletter_deliveries
+                    WHERE status = 'sent'
+                ) THEN
+                    RAISE EXCEPTION 'The database is having a hiccup';
+                END IF;
+                RETURN NEW;
+            END;
+            $$ LANGUAGE plpgsql;
+            CREATE TRIGGER fail_second_delivery
+                BEFORE INSERT ON newsletter_deliveries
+                FOR EACH ROW EXECUTE FUNCTION fail_second_delivery();
+            "#,
+        )
+        .await
+        .unwrap();
+
+    // Act
+    assert!(app.try_execute_tasks().await.is_err());
+    app.db_pool
+        .execute("DROP TRIGGER fail_second_delivery ON newsletter_deliveries")
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let requests = app.email_server.received_requests().await.unwrap();
+    let recipients: Vec<String> = requests[n_confirmation_emails..]
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value =
+                serde_json::from_slice(&r.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    let mut n_emails = HashMap::new();
+    for recipient in &recipients {
+        *n_emails.entry(recipient).or_insert(0) += 1;
+    }
+    assert_eq!(n_emails.len(), 3, "Every subscriber got the issue");
+    // The first delivery was committed before the failure
+    assert_eq!(n_emails[&recipients[0]], 1);
+    // Only the email in flight when the database failed went out twice
+    assert_eq!(recipients.len(), 4);
+    assert_eq!(app.n_pending_deliveries().await, 0);
+}
+
+#[tokio::test]
+async fn in_flight_deliveries_complete_on_shutdown() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(
+            ResponseTemplate::new(200).set_delay(Duration::from_millis(500)),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let worker = app.spawn_worker();
+    publish_newsletter_issue(&app).await;
+    app.wait_for_email_requests(2).await;
+
+    // Act
+    worker.stop().await;
+
+    // Assert
+    assert_eq!(app.n_pending_deliveries().await, 0);
+}
+
+#[tokio::test]
+async fn deliveries_still_in_flight_after_the_deadline_are_left_in_the_queue() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.shutdown_timeout_milliseconds = 100;
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(
+            ResponseTemplate::new(200).set_delay(Duration::from_millis(800)),
+        )
+        .mount(&app.email_server)
+        .await;
+    let worker = app.spawn_worker();
+    publish_newsletter_issue(&app).await;
+    app.wait_for_email_requests(2).await;
+
+    // Act
+    worker.stop().await;
+
+    // Assert - the task will be picked up again on the next start
+    assert_eq!(app.n_pending_deliveries().await, 1);
+}
+
+#[tokio::test]
+async fn delivery_is_paused_when_the_provider_rate_limits_us() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.initial_backoff_milliseconds = 60 * 1000;
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(
+            ResponseTemplate::new(429).insert_header("Retry-After", "2"),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // The rate limited delivery might be due again by the time
+    // the second one goes out
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1..=2)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter_issue(&app).await;
+
+    // Act
+    let start = std::time::Instant::now();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - the second subscriber had to wait as well
+    assert!(start.elapsed() >= Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn rate_limited_deliveries_do_not_count_as_attempts() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.max_attempts = 1;
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(
+            ResponseTemplate::new(429).insert_header("Retry-After", "1"),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter_issue(&app).await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery =
+        sqlx::query!("SELECT status, n_attempts FROM newsletter_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.n_attempts, 1);
+}
+
+#[tokio::test]
+async fn delivery_is_paused_when_the_provider_refuses_our_account() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.max_attempts = 1;
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(401).set_body_json(
+            serde_json::json!({
+                "ErrorCode": 10,
+                "Message": "Bad or missing Server API token"
+            }),
+        ))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter_issue(&app).await;
+
+    // Act
+    let outcome = app.try_execute_tasks().await.unwrap();
+
+    // Assert - the second subscriber is held off as well
+    assert!(matches!(outcome, ExecutionOutcome::Throttled(_)));
+    // Neither the attempt nor the subscribers are given up on
+    assert_eq!(app.n_pending_deliveries().await, 2);
+    let n_dead_letters =
+        sqlx::query!("SELECT COUNT(*) AS n FROM issue_delivery_dead_letters")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n
+            .unwrap();
+    assert_eq!(n_dead_letters, 0);
+    let delivery = sqlx::query!(
+        "SELECT n_attempts, last_error FROM newsletter_deliveries \
+        WHERE status = 'retrying'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.n_attempts, 0);
+    assert!(delivery
+        .last_error
+        .unwrap()
+        .contains("Bad or missing Server API token"));
+}
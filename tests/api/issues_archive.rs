@@ -0,0 +1,210 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Publish an issue, returns its id.
+async fn publish_issue(
+    app: &TestApp,
+    title: &str,
+    subscribers_only: bool,
+) -> Uuid {
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": "Hi {{ name }}, here is our news.",
+        "html_content": "<p>Hi {{ name }}, here is our news.</p>",
+        "subscribers_only": subscribers_only,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .pop()
+    .unwrap()
+    .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn published_issues_can_be_read_by_anybody() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Our January update!", false).await;
+    app.post_logout().await;
+
+    // Act - Part 1 - The list
+    let html_page = app.get_archive_html("").await;
+    assert!(html_page.contains(
+        r#"<a href="/issues/our-january-update">Our January update!</a>"#
+    ));
+
+    // Act - Part 2 - The issue
+    let response = app.get_archive("/our-january-update").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Our January update!</h1>"));
+    // Personalized for an anonymous reader
+    assert!(html_page.contains("<p>Hi reader, here is our news.</p>"));
+    // Emails only
+    assert!(!html_page.contains("Unsubscribe"));
+}
+
+#[tokio::test]
+async fn drafts_and_subscriber_only_issues_are_not_archived() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "For subscribers", true).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "A draft",
+        "text_content": "Work in progress",
+        "html_content": "<p>Work in progress</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "action": "save_draft",
+    }))
+    .await;
+
+    // Act
+    let html_page = app.get_archive_html("").await;
+
+    // Assert
+    assert!(html_page.contains("<li>No issues yet</li>"));
+    let response = app.get_archive("/for-subscribers").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_get_different_slugs() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, "Weekly digest", false).await;
+    publish_issue(&app, "Weekly digest", false).await;
+
+    // Assert
+    for slug in ["/weekly-digest", "/weekly-digest-2"] {
+        let response = app.get_archive(slug).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for n in 1..=21 {
+        publish_issue(&app, &format!("Issue {}", n), false).await;
+    }
+
+    // Act - Part 1 - The newest issues
+    let html_page = app.get_archive_html("").await;
+    assert_eq!(html_page.matches("<li>").count(), 20);
+    assert!(html_page.contains(r#"<a href="/issues?page=2">Older issues</a>"#));
+
+    // Act - Part 2 - The oldest one
+    let html_page = app.get_archive_html("?page=2").await;
+
+    // Assert
+    assert_eq!(html_page.matches("<li>").count(), 1);
+    assert!(html_page.contains(r#"<a href="/issues/issue-1">Issue 1</a>"#));
+    assert!(html_page.contains(r#"<a href="/issues?page=1">Newer issues</a>"#));
+    assert!(!html_page.contains("Older issues"));
+}
+
+#[tokio::test]
+async fn an_issue_can_be_removed_from_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue(&app, "Oops", false).await;
+
+    // Act - Part 1 - Remove it
+    let response = app
+        .post_issue_action_form(
+            issue_id,
+            "visibility",
+            &serde_json::json!({ "subscribers_only": true }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains(
+        "<p><i>The issue has been removed from the public archive.</i></p>"
+    ));
+    assert_eq!(app.get_archive("/oops").await.status().as_u16(), 404);
+
+    // Act - Part 2 - Put it back
+    app.post_issue_action_form(
+        issue_id,
+        "visibility",
+        &serde_json::json!({ "subscribers_only": false }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(app.get_archive("/oops").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn published_issues_are_in_the_feeds() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue(&app, "News & views", false).await;
+    publish_issue(&app, "For subscribers", true).await;
+    app.post_logout().await;
+    let published_at = sqlx::query!(
+        "SELECT published_at FROM newsletter_issues WHERE slug = 'news-views'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .published_at
+    .unwrap();
+
+    let test_cases = [
+        (
+            "feed.xml",
+            "application/rss+xml",
+            format!(
+                r#"<guid isPermaLink="false">urn:uuid:{}</guid>"#,
+                issue_id
+            ),
+            format!("<pubDate>{}</pubDate>", published_at.to_rfc2822()),
+            "<description>&lt;p&gt;Hi reader",
+        ),
+        (
+            "atom.xml",
+            "application/atom+xml",
+            format!("<id>urn:uuid:{}</id>", issue_id),
+            format!("<published>{}</published>", published_at.to_rfc3339()),
+            r#"<content type="html">&lt;p&gt;Hi reader"#,
+        ),
+    ];
+    for (feed, content_type, id, date, content) in test_cases {
+        // Act
+        let response = app.get_feed(feed).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with(content_type));
+        let xml = response.text().await.unwrap();
+        assert!(xml.contains("<title>News &amp; views</title>"), "{}", feed);
+        assert!(xml.contains("/issues/news-views"), "{}", feed);
+        assert!(xml.contains(&id), "{}", feed);
+        assert!(xml.contains(&date), "{}", feed);
+        assert!(xml.contains(content), "{}", feed);
+        assert!(!xml.contains("For subscribers"), "{}", feed);
+    }
+}
@@ -1,8 +1,23 @@
 mod admin_dashboard;
+mod admin_dead_letters;
+mod admin_issues;
+mod admin_outbox;
 mod change_password;
+mod email_layouts;
+mod email_webhooks;
 mod health_check;
 mod helpers;
+mod issue_delivery_worker;
+mod issues_archive;
 mod login;
 mod newsletter;
+mod newsletter_drafts;
+mod scheduled_issues;
+mod segments;
+mod subscriber_attributes;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_resend_confirmation;
+mod subscriptions_unsubscribe;
+mod topics;
+mod tracking;
@@ -1,60 +1,12 @@
 use crate::helpers::{
-    assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp,
+    assert_is_redirect_to, create_confirmed_subscriber,
+    create_unconfirmed_subscriber, publish_newsletter_issue, spawn_app,
+    spawn_app_with, when_sending_an_email,
 };
 
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::name::en::Name;
-use fake::Fake;
 use std::time::Duration;
 use wiremock::matchers::{any, method, path};
-use wiremock::{Mock, MockBuilder, ResponseTemplate};
-
-// Short-hand for a common mocking setup
-fn when_sending_an_email() -> MockBuilder {
-    Mock::given(path("/email")).and(method("POST"))
-}
-
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    // We are working with multiple subscribers now,
-    // their details must be randomised to avoid conflicts!
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name": name,
-        "email": email
-    }))
-    .unwrap();
-
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(email_request)
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await.html;
-    reqwest::get(confirmation_link)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
+use wiremock::{Mock, ResponseTemplate};
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -208,9 +160,10 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     Mock::given(path("/email"))
         .and(method("POST"))
         // Setting a long delay to ensure that the second request
-        // arrives before the first one completes
+        // arrives before the first one completes - it must stay below
+        // the client timeout, or the delivery would be retried
         .respond_with(
-            ResponseTemplate::new(200).set_delay(Duration::from_secs(2)),
+            ResponseTemplate::new(200).set_delay(Duration::from_millis(500)),
         )
         .expect(1)
         .mount(&app.email_server)
@@ -233,3 +186,224 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we have sent the newsletter email **once**
 }
+
+#[tokio::test]
+async fn transient_delivery_failures_are_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_dead_letters =
+        sqlx::query!("SELECT COUNT(*) AS n FROM issue_delivery_dead_letters")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+    assert_eq!(n_dead_letters, Some(0));
+    // Mock verifies on Drop that the second attempt went through
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_after_a_backoff() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.issue_delivery.initial_backoff_milliseconds = 60 * 60 * 1000;
+    })
+    .await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS postponed \
+        FROM issue_delivery_queue",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.n_retries, 1);
+    assert_eq!(task.postponed, Some(true));
+}
+
+#[tokio::test]
+async fn deliveries_are_dead_lettered_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let max_attempts = app.configuration.issue_delivery.max_attempts;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(503))
+        .expect(u64::from(max_attempts))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let dead_letter = sqlx::query!(
+        "SELECT subscriber_email, n_attempts FROM issue_delivery_dead_letters",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(dead_letter.subscriber_email, email);
+    assert_eq!(dead_letter.n_attempts as u32, max_attempts);
+    let n_tasks =
+        sqlx::query!("SELECT COUNT(*) AS n FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+    assert_eq!(n_tasks, Some(0));
+}
+
+#[tokio::test]
+async fn rejected_deliveries_are_dead_lettered_right_away() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(422).set_body_json(
+            serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been \
+                    marked as inactive."
+            }),
+        ))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let dead_letter = sqlx::query!(
+        "SELECT n_attempts, last_error FROM issue_delivery_dead_letters",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(dead_letter.n_attempts, 1);
+    assert!(dead_letter.last_error.contains("marked as inactive"));
+}
+
+#[tokio::test]
+async fn rate_limited_deliveries_wait_as_long_as_requested() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    when_sending_an_email()
+        .respond_with(
+            ResponseTemplate::new(429).insert_header("Retry-After", "3600"),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!(
+        "SELECT n_retries, \
+        execute_after > now() + interval '59 minutes' AS postponed \
+        FROM issue_delivery_queue",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    // Going too fast is not the subscriber's fault
+    assert_eq!(task.n_retries, 0);
+    assert_eq!(task.postponed, Some(true));
+}
+
+#[tokio::test]
+async fn markdown_issues_are_delivered_as_html_and_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "markdown_content": "Read **this** [post](https://example.com)!\n\n\
+            <script>alert('pwned')</script>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // The first email is the confirmation one
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(html_body.contains(
+        r#"Read <strong>this</strong> <a href="https://example.com""#
+    ));
+    assert!(!html_body.contains("<script>"));
+    assert!(text_body.starts_with("Read this post (https://example.com)!"));
+
+    let saved = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        saved.markdown_content.as_deref(),
+        newsletter_request_body["markdown_content"].as_str()
+    );
+}
@@ -0,0 +1,447 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app,
+    when_sending_an_email, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::body_string_contains;
+use wiremock::ResponseTemplate;
+
+/// Save a draft through the publish form, returns its id.
+async fn create_draft(app: &TestApp) -> Uuid {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Draft body as plain text",
+            "html_content": "<p>Draft body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "action": "save_draft",
+        }))
+        .await;
+    let location = response.headers().get("Location").unwrap();
+    location
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/newsletters/")
+        .trim_end_matches("/edit")
+        .parse()
+        .unwrap()
+}
+
+// How input values show up in the edit page
+fn value_attribute(value: &str) -> String {
+    format!(r#"value="{}""#, htmlescape::encode_attribute(value))
+}
+
+fn draft_body(title: &str, revision: i32) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "revision": revision,
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_work_on_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = Uuid::new_v4();
+
+    for page in ["edit", "preview"] {
+        // Act
+        let response = app.get_draft(issue_id, page).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/login");
+    }
+    for action in ["edit", "autosave", "publish", "test"] {
+        // Act
+        let response = app
+            .post_draft(issue_id, action, &draft_body("Title", 0))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn saving_a_draft_does_not_send_it() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let issue_id = create_draft(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains(&value_attribute("Draft title")));
+    assert!(html_page.contains("&lt;p&gt;Draft body as HTML&lt;/p&gt;"));
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/newsletters/{}/edit">Draft title</a>"#,
+        issue_id
+    )));
+    assert_eq!(app.n_pending_deliveries().await, 0);
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn a_draft_can_be_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_draft(issue_id, "edit", &draft_body("New title", 0))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}/edit", issue_id),
+    );
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page.contains(&value_attribute("New title")));
+    assert!(html_page.contains(r#"name="revision" value="1""#));
+}
+
+#[tokio::test]
+async fn editors_do_not_overwrite_each_other_changes() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act - Both editors started from the first revision
+    app.post_draft(issue_id, "edit", &draft_body("First editor", 0))
+        .await;
+    let response = app
+        .post_draft(issue_id, "edit", &draft_body("Second editor", 0))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("changed by someone else"));
+    // The second editor gets their changes back, on top of the latest
+    // revision: saving again is a deliberate choice
+    assert!(html_page.contains(&value_attribute("Second editor")));
+    assert!(html_page.contains(r#"name="revision" value="1""#));
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page.contains(&value_attribute("First editor")));
+}
+
+#[tokio::test]
+async fn autosave_returns_the_new_revision() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act - Part 1 - Autosave
+    let response = app
+        .post_draft(issue_id, "autosave", &draft_body("Autosaved", 0))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), "1");
+
+    // Act - Part 2 - Autosave a stale revision
+    let response = app
+        .post_draft(issue_id, "autosave", &draft_body("Stale", 0))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    // Assert
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page.contains(&value_attribute("Autosaved")));
+}
+
+#[tokio::test]
+async fn a_markdown_draft_is_rendered_on_every_save() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    app.post_draft(
+        issue_id,
+        "edit",
+        &serde_json::json!({
+            "title": "Draft title",
+            "markdown_content": "# Hello\n\nSome *news*.",
+            "revision": 0,
+        }),
+    )
+    .await;
+
+    // Assert - The source is kept around for the next edit
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page.contains(&format!(
+        ">{}</textarea>",
+        htmlescape::encode_minimal("# Hello\n\nSome *news*.")
+    )));
+    let html_page = app.get_draft_html(issue_id, "preview").await;
+    assert!(html_page.contains(&htmlescape::encode_attribute(
+        "<h1>Hello</h1>\n<p>Some <em>news</em>.</p>"
+    )));
+    assert!(html_page.contains("<pre>Hello\n\nSome news."));
+}
+
+#[tokio::test]
+async fn a_draft_can_be_previewed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+
+    // Act
+    let html_page = app.get_draft_html(issue_id, "preview").await;
+
+    // Assert
+    assert!(html_page.contains("<h1>Draft title</h1>"));
+    // Followed by the unsubscribe footer
+    assert!(html_page.contains(&format!(
+        r#"<iframe srcdoc="{}"#,
+        htmlescape::encode_attribute("<p>Draft body as HTML</p>")
+    )));
+    assert!(html_page.contains("<pre>Draft body as plain text"));
+}
+
+#[tokio::test]
+async fn a_published_draft_is_delivered_and_can_no_longer_be_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    let response = app
+        .post_draft(issue_id, "publish", &draft_body("Final title", 0))
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Part 2 - Try to edit
+    let response = app.get_draft(issue_id, "edit").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+
+    // Assert
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p><i>Only drafts can be edited.</i></p>"));
+    assert!(html_page.contains("<h1>Final title</h1>"));
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(!html_page.contains(&issue_id.to_string()));
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn a_draft_can_be_scheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+    let scheduled_for = (chrono::Utc::now() + chrono::Duration::days(1))
+        .format("%Y-%m-%dT%H:%M")
+        .to_string();
+    let mut body = draft_body("Title", 0);
+    body["scheduled_for"] = scheduled_for.into();
+    body["timezone"] = "UTC".into();
+
+    // Act
+    app.post_draft(issue_id, "publish", &body).await;
+
+    // Assert
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p>Status: scheduled</p>"));
+}
+
+async fn n_rows(app: &TestApp, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_test_email_is_sent_to_the_given_addresses_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+    let n_idempotency_keys = n_rows(&app, "idempotency").await;
+    when_sending_an_email()
+        .and(body_string_contains("[Test] Test title"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let mut body = draft_body("Test title", 0);
+    body["test_recipients"] = "ed@example.com, ITOR@example.com".into();
+
+    // Act
+    let response = app.post_draft(issue_id, "test", &body).await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}/edit", issue_id),
+    );
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page.contains(
+        "<p><i>A test email has been sent to \
+        ed@example.com, ITOR@example.com.</i></p>"
+    ));
+    // The next test goes to the same addresses by default
+    assert!(html_page
+        .contains(&value_attribute("ITOR@example.com, ed@example.com")));
+    // Tests are recorded, but they are not deliveries
+    assert_eq!(n_rows(&app, "newsletter_test_emails").await, 2);
+    assert_eq!(n_rows(&app, "newsletter_deliveries").await, 0);
+    assert_eq!(app.n_pending_deliveries().await, 0);
+    assert_eq!(n_rows(&app, "idempotency").await, n_idempotency_keys);
+    // Mock verifies on Drop that the tests have been sent
+}
+
+#[tokio::test]
+async fn a_test_email_can_be_sent_from_the_newsletter_form() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let n_idempotency_keys = n_rows(&app, "idempotency").await;
+    when_sending_an_email()
+        .and(body_string_contains("[Test] New title"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "New title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "test_recipients": "ed@example.com",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "action": "send_test",
+        }))
+        .await;
+
+    // Assert - the issue is saved as a draft to keep working on
+    let issue = sqlx::query!(
+        "SELECT newsletter_issue_id, status FROM newsletter_issues"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(issue.status, "draft");
+    let issue_id = issue.newsletter_issue_id;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}/edit", issue_id),
+    );
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains(
+        "<p><i>A test email has been sent to ed@example.com.</i></p>"
+    ));
+    assert_eq!(n_rows(&app, "newsletter_test_emails").await, 1);
+    assert_eq!(app.n_pending_deliveries().await, 0);
+    assert_eq!(n_rows(&app, "idempotency").await, n_idempotency_keys);
+}
+
+#[tokio::test]
+async fn the_newsletter_form_rejects_invalid_test_recipients() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "New title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "test_recipients": "not-an-email",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "action": "send_test",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(n_rows(&app, "newsletter_issues").await, 0);
+}
+
+#[tokio::test]
+async fn a_test_email_needs_valid_recipients() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for recipients in ["", "not-an-email", "ed@example.com, ursula"] {
+        // Act
+        let mut body = draft_body("Title", 0);
+        body["test_recipients"] = recipients.into();
+        let response = app.post_draft(issue_id, "test", &body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject the test recipients {:?}.",
+            recipients
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_failed_test_email_is_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = create_draft(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let mut body = draft_body("Title", 0);
+    body["test_recipients"] = "ed@example.com".into();
+
+    // Act
+    app.post_draft(issue_id, "test", &body).await;
+
+    // Assert
+    let html_page = app.get_draft_html(issue_id, "edit").await;
+    assert!(html_page
+        .contains("<p><i>Failed to send a test email to ed@example.com: "));
+    assert_eq!(n_rows(&app, "newsletter_test_emails").await, 0);
+}
@@ -0,0 +1,267 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp,
+};
+use chrono::{Duration, Utc};
+use chrono_tz::Tz;
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+// `datetime-local` format, `days` from now in `timezone`
+fn local_time_in(days: i64, timezone: Tz) -> String {
+    (Utc::now() + Duration::days(days))
+        .with_timezone(&timezone)
+        .format("%Y-%m-%dT%H:%M")
+        .to_string()
+}
+
+async fn schedule_newsletter_issue(
+    app: &TestApp,
+    scheduled_for: &str,
+    timezone: &str,
+) -> reqwest::Response {
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "scheduled_for": scheduled_for,
+        "timezone": timezone,
+    }))
+    .await
+}
+
+async fn issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+// Travelling in time is hard, moving the schedule is easier
+async fn make_schedule_due(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_for = now() - interval '1s' \
+        WHERE scheduled_for IS NOT NULL"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn a_scheduled_issue_is_not_delivered_before_its_time() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let scheduled_for = local_time_in(1, chrono_tz::Europe::Rome);
+
+    // Act - Part 1 - Schedule
+    let response =
+        schedule_newsletter_issue(&app, &scheduled_for, "Europe/Rome").await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The newsletter issue has been scheduled for {} \
+        (Europe/Rome).</i></p>",
+        scheduled_for.replace('T', " ")
+    )));
+
+    // Act - Part 2 - Nothing is due
+    assert_eq!(app.publish_due_issues().await, 0);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(app.n_pending_deliveries().await, 0);
+    let html_page = app
+        .get_admin_issues_html(&format!("/{}", issue_id(&app).await))
+        .await;
+    assert!(html_page.contains("<p>Status: scheduled</p>"));
+    assert!(html_page.contains(&format!(
+        "<p>Scheduled for: {} (Europe/Rome)</p>",
+        scheduled_for.replace('T', " ")
+    )));
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn a_scheduled_issue_is_delivered_once_it_is_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    schedule_newsletter_issue(&app, &local_time_in(1, Tz::UTC), "UTC").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    make_schedule_due(&app).await;
+    assert_eq!(app.publish_due_issues().await, 1);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue =
+        sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(issue.status, "completed");
+    assert!(issue.published_at.is_some());
+    // A scheduled issue is only published once
+    assert_eq!(app.publish_due_issues().await, 0);
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn background_workers_publish_scheduled_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    schedule_newsletter_issue(&app, &local_time_in(1, Tz::UTC), "UTC").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let worker = app.spawn_worker();
+
+    // Act
+    make_schedule_due(&app).await;
+    // Schedules changed through the admin pages wake the scheduler up
+    sqlx::query!("SELECT pg_notify('issue_delivery_queue', '')")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Assert
+    app.wait_for_email_requests(2).await;
+    worker.stop().await;
+}
+
+#[tokio::test]
+async fn invalid_schedules_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (
+            local_time_in(1, Tz::UTC),
+            "Mars/Olympus",
+            "unknown timezone",
+        ),
+        ("tomorrow".into(), "UTC", "malformed time"),
+        (local_time_in(-1, Tz::UTC), "UTC", "time in the past"),
+    ];
+
+    for (scheduled_for, timezone, error_message) in test_cases {
+        // Act
+        let response =
+            schedule_newsletter_issue(&app, &scheduled_for, timezone).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the schedule \
+            was a {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_scheduled_issue_can_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    schedule_newsletter_issue(&app, &local_time_in(1, Tz::UTC), "UTC").await;
+    let issue_id = issue_id(&app).await;
+    let scheduled_for = local_time_in(2, chrono_tz::America::New_York);
+
+    // Act
+    let response = app
+        .post_issue_action_form(
+            issue_id,
+            "schedule",
+            &serde_json::json!({
+                "scheduled_for": scheduled_for,
+                "timezone": "America/New_York",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    let scheduled_for = scheduled_for.replace('T', " ");
+    assert!(html_page.contains(&format!(
+        "<p><i>The issue has been scheduled for {} \
+        (America/New_York).</i></p>",
+        scheduled_for
+    )));
+    assert!(html_page.contains(&format!(
+        "<p>Scheduled for: {} (America/New_York)</p>",
+        scheduled_for
+    )));
+}
+
+#[tokio::test]
+async fn an_unscheduled_issue_is_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    schedule_newsletter_issue(&app, &local_time_in(1, Tz::UTC), "UTC").await;
+    let issue_id = issue_id(&app).await;
+
+    // Act
+    let response = app.post_issue_action(issue_id, "unschedule").await;
+    make_schedule_due(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    assert_eq!(app.publish_due_issues().await, 0);
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page.contains("<p><i>The issue has been unscheduled.</i></p>"));
+    assert!(html_page.contains("<p>Status: unscheduled</p>"));
+}
+
+#[tokio::test]
+async fn a_published_issue_cannot_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    crate::helpers::publish_newsletter_issue(&app).await;
+    let issue_id = issue_id(&app).await;
+
+    // Act
+    let response = app
+        .post_issue_action_form(
+            issue_id,
+            "schedule",
+            &serde_json::json!({
+                "scheduled_for": local_time_in(1, Tz::UTC),
+                "timezone": "UTC",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html_page = app.get_admin_issues_html(&format!("/{}", issue_id)).await;
+    assert!(html_page
+        .contains("<p><i>The issue has been published already.</i></p>"));
+    assert!(html_page.contains("<p>Status: sending</p>"));
+}
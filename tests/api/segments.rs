@@ -0,0 +1,294 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with, spawn_app,
+    when_sending_an_email, TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+fn segment_body(name: &str, email_domain: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "subscribed_after": "",
+        "email_domain": email_domain,
+        "opened_last_issues": "",
+    })
+}
+
+/// Create a segment through the admin pages, returns its id.
+async fn create_segment<Body>(app: &TestApp, body: &Body) -> Uuid
+where
+    Body: serde::Serialize,
+{
+    let response = app.post_segment("", body).await;
+    let location = response.headers().get("Location").unwrap();
+    location
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/segments/")
+        .parse()
+        .unwrap()
+}
+
+async fn count_recipients(app: &TestApp, segment_id: Uuid) -> String {
+    app.post_count_recipients(&serde_json::json!({
+        "title": "The rest of the form is ignored",
+        "topic_id": "",
+        "segment_id": segment_id.to_string(),
+    }))
+    .await
+    .text()
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_segments() {
+    // Arrange
+    let app = spawn_app().await;
+    let segment_id = Uuid::new_v4();
+
+    for path in ["".to_string(), format!("/{}", segment_id)] {
+        // Act
+        let get_response = app.get_segments(&path).await;
+        let post_response = app
+            .post_segment(&path, &segment_body("Corporate", "example.com"))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&get_response, "/login");
+        assert_is_redirect_to(&post_response, "/login");
+    }
+    let response = app.post_count_recipients(&serde_json::json!({})).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_segment_can_be_created_and_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create
+    let segment_id = create_segment(&app, &segment_body("Corporate", "")).await;
+    let html_page = app.get_segments_html("").await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/segments/{}">Corporate</a>: everybody"#,
+        segment_id
+    )));
+
+    // Act - Part 2 - Edit
+    let path = format!("/{}", segment_id);
+    let response = app
+        .post_segment(
+            &path,
+            &serde_json::json!({
+                "name": "Loyal",
+                "subscribed_after": "2023-01-31",
+                "email_domain": "@Example.com",
+                "opened_last_issues": "3",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/segments{}", path));
+    let html_page = app.get_segments_html(&path).await;
+    assert!(html_page.contains("<p><i>The segment has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Loyal""#));
+    assert!(html_page.contains(r#"value="2023-01-31""#));
+    let html_page = app.get_segments_html("").await;
+    assert!(html_page.contains(
+        "Loyal</a>: subscribed after 2023-01-31, email at example.com, \
+        opened the last 3 tracked issues"
+    ));
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (segment_body(" ", ""), "The segment needs a name."),
+        (
+            segment_body("Typo", "example"),
+            "example is not a valid email domain.",
+        ),
+        (
+            serde_json::json!({
+                "name": "Too many",
+                "opened_last_issues": "100",
+            }),
+            "The number of opened issues must be between 1 and 50.",
+        ),
+        (
+            serde_json::json!({
+                "name": "Gone",
+                "attribute_id": Uuid::new_v4().to_string(),
+                "attribute_value": "pro",
+            }),
+            "The attribute doesn't exist.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        // Act
+        let response = app.post_segment("", &body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/segments");
+        let html_page = app.get_segments_html("").await;
+        assert!(
+            html_page.contains(error_message),
+            "The page did not say '{}'",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_segment_only_reach_matching_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber_with(&app, "ursula@example.com", &[]).await;
+    create_confirmed_subscriber_with(&app, "octavia@example.com", &[]).await;
+    create_confirmed_subscriber_with(&app, "ursula@example.org", &[]).await;
+    let segment_id =
+        create_segment(&app, &segment_body("Corporate", "example.com")).await;
+
+    // Act - Part 1 - Preview
+    assert_eq!(count_recipients(&app, segment_id).await, "2 recipients");
+
+    // Act - Part 2 - Publish
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "segment_id": segment_id.to_string(),
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let recipients: Vec<_> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .rev()
+        .take(2)
+        .map(|r| {
+            let body: serde_json::Value =
+                serde_json::from_slice(&r.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert!(recipients.iter().all(|to| to.ends_with("@example.com")));
+}
+
+#[tokio::test]
+async fn segments_can_pick_subscribers_who_opened_the_last_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber_with(&app, "ursula@example.com", &[]).await;
+    create_confirmed_subscriber_with(&app, "octavia@example.com", &[]).await;
+    let segment_id = create_segment(
+        &app,
+        &serde_json::json!({ "name": "Readers", "opened_last_issues": "1" }),
+    )
+    .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "tracking": true,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(count_recipients(&app, segment_id).await, "0 recipients");
+
+    // Act
+    sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (
+            event_id, newsletter_issue_id, subscriber_id, kind, created_at
+        )
+        SELECT $1, i.newsletter_issue_id, s.id, 'open', now()
+        FROM newsletter_issues i, subscriptions s
+        WHERE s.email = 'ursula@example.com'
+        "#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(count_recipients(&app, segment_id).await, "1 recipient");
+}
+
+#[tokio::test]
+async fn segments_can_pick_subscribers_by_attribute_value() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber_with(&app, "ursula@example.com", &[]).await;
+    create_confirmed_subscriber_with(&app, "octavia@example.com", &[]).await;
+    let attribute_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attributes (attribute_id, name, label, kind)
+        VALUES ($1, 'plan', 'Plan', 'text')
+        "#,
+        attribute_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attribute_values (
+            subscriber_id, attribute_id, value
+        )
+        SELECT id, $1, 'pro'
+        FROM subscriptions
+        WHERE email = 'ursula@example.com'
+        "#,
+        attribute_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let segment_id = create_segment(
+        &app,
+        &serde_json::json!({
+            "name": "Paying",
+            "attribute_id": attribute_id.to_string(),
+            "attribute_value": " pro ",
+        }),
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_segments_html("").await;
+    assert!(html_page.contains("Paying</a>: plan is pro"));
+    assert_eq!(count_recipients(&app, segment_id).await, "1 recipient");
+}
@@ -0,0 +1,351 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with, spawn_app,
+    when_sending_an_email, TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+fn attribute_body(name: &str, kind: &str, required: bool) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "label": name.to_uppercase(),
+        "kind": kind,
+        "required": required,
+    })
+}
+
+/// Create an attribute through the admin pages, returns its id.
+async fn create_attribute(
+    app: &TestApp,
+    name: &str,
+    kind: &str,
+    required: bool,
+) -> Uuid {
+    let response = app
+        .post_attribute("", &attribute_body(name, kind, required))
+        .await;
+    let location = response.headers().get("Location").unwrap();
+    location
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/attributes/")
+        .parse()
+        .unwrap()
+}
+
+fn subscription_body(fields: &[(&str, &str)]) -> String {
+    let mut body = vec![("name", "le guin"), ("email", "ursula@example.com")];
+    body.extend_from_slice(fields);
+    serde_urlencoded::to_string(&body).unwrap()
+}
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_attributes() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = Uuid::new_v4();
+
+    for path in ["".to_string(), format!("/{}", id)] {
+        // Act
+        let get_attributes = app.get_attributes(&path).await;
+        let post_attribute = app
+            .post_attribute(&path, &attribute_body("company", "text", false))
+            .await;
+        let get_subscribers = app.get_subscribers(&path).await;
+
+        // Assert
+        assert_is_redirect_to(&get_attributes, "/login");
+        assert_is_redirect_to(&post_attribute, "/login");
+        assert_is_redirect_to(&get_subscribers, "/login");
+    }
+    let response = app.post_subscriber(&format!("/{}", id), &[]).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_attribute_can_be_created_and_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create
+    let attribute_id = create_attribute(&app, "company", "text", false).await;
+    let html_page = app.get_attributes_html("").await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/attributes/{}">COMPANY</a>: <code>company</code>, text</li>"#,
+        attribute_id
+    )));
+
+    // Act - Part 2 - Edit
+    let path = format!("/{}", attribute_id);
+    let response = app
+        .post_attribute(
+            &path,
+            &serde_json::json!({ "label": "Employer", "required": true }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/attributes{}", path));
+    let html_page = app.get_attributes_html(&path).await;
+    assert!(html_page.contains("<p><i>The attribute has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Employer""#));
+    let html_page = app.get_attributes_html("").await;
+    assert!(html_page
+        .contains("Employer</a>: <code>company</code>, text, required"));
+    // Subscribers are asked for it
+    let response = reqwest::get(&app.address).await.unwrap();
+    let home_page = response.text().await.unwrap();
+    assert!(home_page.contains(
+        r#"Employer: <input type="text" name="company" value="" required>"#
+    ));
+}
+
+#[tokio::test]
+async fn invalid_attributes_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attribute(&app, "company", "text", false).await;
+    let test_cases = vec![
+        (
+            attribute_body("Company Name", "text", false),
+            "Company Name is not a valid attribute name",
+        ),
+        (
+            attribute_body("email", "text", false),
+            "email is a reserved name.",
+        ),
+        (attribute_body("plan", "colour", false), "colour"),
+        (
+            serde_json::json!({ "name": "plan", "label": " ", "kind": "text" }),
+            "The attribute needs a label.",
+        ),
+        (
+            attribute_body("company", "number", false),
+            "There is an attribute named company already.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        // Act
+        let response = app.post_attribute("", &body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/attributes");
+        let html_page = app.get_attributes_html("").await;
+        assert!(
+            html_page.contains(error_message),
+            "The page did not say '{}'",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_stores_valid_attributes() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attribute(&app, "company", "text", true).await;
+    create_attribute(&app, "seats", "number", false).await;
+    create_attribute(&app, "beta", "boolean", false).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions(subscription_body(&[
+            ("company", " Acme "),
+            ("seats", "12"),
+            ("beta", "true"),
+            ("unknown", "ignored"),
+        ]))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let mut values = sqlx::query!(
+        r#"
+        SELECT a.name, v.value
+        FROM subscriber_attribute_values v
+        JOIN subscriber_attributes a ON a.attribute_id = v.attribute_id
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.name, r.value))
+    .collect::<Vec<_>>();
+    values.sort();
+    assert_eq!(
+        values,
+        vec![
+            ("beta".to_string(), "true".to_string()),
+            ("company".to_string(), "Acme".to_string()),
+            ("seats".to_string(), "12".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_missing_or_invalid_attributes() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attribute(&app, "company", "text", true).await;
+    create_attribute(&app, "seats", "number", false).await;
+    let test_cases = vec![
+        (vec![], "the required attribute is missing"),
+        (vec![("company", " ")], "the required attribute is empty"),
+        (
+            vec![("company", "Acme"), ("seats", "a few")],
+            "the number is invalid",
+        ),
+    ];
+
+    for (fields, description) in test_cases {
+        // Act
+        let response = app.post_subscriptions(subscription_body(&fields)).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not return a 400 when {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn admins_can_edit_the_attributes_of_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attribute(&app, "company", "text", false).await;
+    create_confirmed_subscriber_with(&app, "ursula@example.com", &[]).await;
+    let subscriber_id = subscriber_id(&app, "ursula@example.com").await;
+    let html_page = app.get_subscribers_html("?email=URSULA").await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/subscribers/{}">ursula@example.com</a>"#,
+        subscriber_id
+    )));
+    let path = format!("/{}", subscriber_id);
+
+    // Act
+    let response = app.post_subscriber(&path, &[("company", "Acme")]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers{}", path));
+    let html_page = app.get_subscribers_html(&path).await;
+    assert!(html_page.contains("<p><i>The subscriber has been saved.</i></p>"));
+    assert!(html_page.contains(r#"name="company" value="Acme""#));
+}
+
+#[tokio::test]
+async fn attributes_are_template_variables() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attribute(&app, "company", "text", false).await;
+    create_confirmed_subscriber_with(
+        &app,
+        "ursula@example.com",
+        &[("company", "Acme".into())],
+    )
+    .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ attributes.company }}!",
+            "html_content": "<p>Hi {{ attributes.company }}!</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Hi Acme!"));
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Hi Acme!</p>"));
+}
+
+#[tokio::test]
+async fn segments_can_pick_subscribers_by_attribute() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let attribute_id = create_attribute(&app, "plan", "text", false).await;
+    create_confirmed_subscriber_with(
+        &app,
+        "ursula@example.com",
+        &[("plan", "pro".into())],
+    )
+    .await;
+    create_confirmed_subscriber_with(
+        &app,
+        "octavia@example.com",
+        &[("plan", "free".into())],
+    )
+    .await;
+
+    // Act
+    let response = app
+        .post_segment(
+            "",
+            &serde_json::json!({
+                "name": "Paying",
+                "attribute_id": attribute_id.to_string(),
+                "attribute_value": "pro",
+            }),
+        )
+        .await;
+
+    // Assert
+    let location = response.headers().get("Location").unwrap();
+    let segment_id = location
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/segments/")
+        .to_owned();
+    let html_page = app.get_segments_html("").await;
+    assert!(html_page.contains("Paying</a>: plan is pro"));
+    let recipients = app
+        .post_count_recipients(&serde_json::json!({ "segment_id": segment_id }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(recipients, "1 recipient");
+}
@@ -1,7 +1,20 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    when_sending_an_email,
+};
+use uuid::Uuid;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
+// Build the body of a subscription request for the given address
+fn subscription_body(email: &str) -> String {
+    serde_urlencoded::to_string(serde_json::json!({
+        "name": "le guin",
+        "email": email
+    }))
+    .unwrap()
+}
+
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
     // Arrange
@@ -133,7 +146,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     // Assert
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     // The two links should be identical
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
@@ -154,3 +167,199 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_returns_a_400_if_the_provider_rejects_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(422).set_body_json(
+            serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been \
+                    marked as inactive."
+            }),
+        ))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_500_if_the_provider_fails_temporarily() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+}
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_sends_a_fresh_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let (email, first_links) = create_unconfirmed_subscriber(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(subscription_body(&email)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let second_links = app.get_confirmation_links(email_request);
+    assert_ne!(first_links.html, second_links.html);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_while_another_submission_is_in_flight_returns_a_200() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let email = "ursula_le_guin@gmail.com";
+    // Another submission for the same address, not committed yet
+    let mut in_flight = app.db_pool.begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'le guin', now(), 'pending_confirmation')
+        "#,
+        Uuid::new_v4(),
+        email,
+    )
+    .execute(&mut in_flight)
+    .await
+    .unwrap();
+
+    // Act - it commits while we are inserting the subscriber
+    let (response, _) =
+        tokio::join!(app.post_subscriptions(subscription_body(email)), async {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            in_flight.commit().await.unwrap();
+        });
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) AS n FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, Some(1));
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_returns_a_200_without_duplicates() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber(&app).await;
+    // They get a link to their preferences, nothing else
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(subscription_body(&email)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_requires_a_new_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber(&app).await;
+    let unsubscribe_token =
+        sqlx::query!("SELECT unsubscribe_token FROM unsubscribe_tokens")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .unsubscribe_token;
+    app.post_unsubscribe(&unsubscribe_token)
+        .await
+        .error_for_status()
+        .unwrap();
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Subscribe again
+    let response = app.post_subscriptions(subscription_body(&email)).await;
+
+    // Assert - Part 1 - Back to double opt-in
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+
+    // Act - Part 2 - Click on the new confirmation link
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert - Part 2
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
@@ -0,0 +1,276 @@
+use crate::helpers::{
+    create_confirmed_subscriber, publish_newsletter_issue, spawn_app,
+    when_sending_an_email, TestApp,
+};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+// Publish an issue and deliver it to every confirmed subscriber
+async fn publish_and_deliver_a_newsletter_issue(app: &TestApp) {
+    publish_newsletter_issue(app).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn unsubscribe_without_token_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response =
+        reqwest::get(&format!("{}/subscriptions/unsubscribe", app.address))
+            .await
+            .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribe_with_an_unknown_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token=not-a-real-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn newsletter_issues_contain_an_unsubscribe_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_and_deliver_a_newsletter_issue(&app).await;
+
+    // Assert
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_links = app.get_unsubscribe_links(email_request);
+    assert_eq!(unsubscribe_links.html, unsubscribe_links.plain_text);
+    assert_eq!(unsubscribe_links.html.path(), "/subscriptions/unsubscribe");
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_shows_a_confirmation_form() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_and_deliver_a_newsletter_issue(&app).await;
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_links = app.get_unsubscribe_links(email_request);
+
+    // Act
+    let response = reqwest::get(unsubscribe_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"action="/subscriptions/unsubscribe""#));
+    // Following the link must not unsubscribe anybody
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirming_the_unsubscribe_form_unsubscribes_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_and_deliver_a_newsletter_issue(&app).await;
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let unsubscribe_links = app.get_unsubscribe_links(email_request);
+    let unsubscribe_token = unsubscribe_links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "unsubscribe_token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act
+    let response = app.post_unsubscribe(&unsubscribe_token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let unsubscribe_token =
+        sqlx::query!("SELECT unsubscribe_token FROM unsubscribe_tokens",)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .unsubscribe_token;
+    app.post_unsubscribe(&unsubscribe_token)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_and_deliver_a_newsletter_issue(&app).await;
+
+    // Assert
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn newsletter_issues_carry_one_click_list_unsubscribe_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_and_deliver_a_newsletter_issue(&app).await;
+
+    // Assert
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let list_unsubscribe = app.get_list_unsubscribe_link(email_request);
+    assert_eq!(
+        list_unsubscribe.path(),
+        "/subscriptions/unsubscribe/one-click"
+    );
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.body).unwrap();
+    let list_unsubscribe_post = body["Headers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|h| h["Name"] == "List-Unsubscribe-Post")
+        .unwrap();
+    assert_eq!(list_unsubscribe_post["Value"], "List-Unsubscribe=One-Click");
+}
+
+#[tokio::test]
+async fn a_one_click_post_to_the_list_unsubscribe_url_unsubscribes_a_subscriber(
+) {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_and_deliver_a_newsletter_issue(&app).await;
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let list_unsubscribe = app.get_list_unsubscribe_link(email_request);
+
+    // Act - Mail clients send the request without any session cookie
+    let response = reqwest::Client::new()
+        .post(list_unsubscribe)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn a_one_click_post_with_an_unknown_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe/one-click\
+            ?unsubscribe_token=not-a-real-token",
+            app.address
+        ))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
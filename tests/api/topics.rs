@@ -0,0 +1,363 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber,
+    create_confirmed_subscriber_with, spawn_app, when_sending_an_email,
+    TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+fn topic_body(name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "description": "All about Rust",
+    })
+}
+
+/// Create a topic through the admin pages, returns its id.
+async fn create_topic(app: &TestApp, name: &str) -> Uuid {
+    let response = app.post_topic("", &topic_body(name)).await;
+    let location = response.headers().get("Location").unwrap();
+    location
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/topics/")
+        .parse()
+        .unwrap()
+}
+
+/// Subscribe and confirm, picking `topic_ids` on the way.
+async fn create_confirmed_subscriber_to(
+    app: &TestApp,
+    email: &str,
+    topic_ids: &[Uuid],
+) {
+    let fields: Vec<_> = topic_ids
+        .iter()
+        .map(|id| ("topic_id", id.to_string()))
+        .collect();
+    create_confirmed_subscriber_with(app, email, &fields).await;
+}
+
+async fn unsubscribe_token(app: &TestApp, email: &str) -> String {
+    sqlx::query!(
+        r#"
+        SELECT t.unsubscribe_token
+        FROM unsubscribe_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE s.email = $1
+        "#,
+        email,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .unsubscribe_token
+}
+
+async fn subscriber_topic_ids(app: &TestApp, email: &str) -> Vec<Uuid> {
+    sqlx::query!(
+        r#"
+        SELECT t.topic_id
+        FROM subscriber_topics t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE s.email = $1
+        ORDER BY t.topic_id
+        "#,
+        email,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.topic_id)
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_topics() {
+    // Arrange
+    let app = spawn_app().await;
+    let topic_id = Uuid::new_v4();
+
+    for path in ["".to_string(), format!("/{}", topic_id)] {
+        // Act
+        let get_response = app.get_topics(&path).await;
+        let post_response = app.post_topic(&path, &topic_body("Rust")).await;
+
+        // Assert
+        assert_is_redirect_to(&get_response, "/login");
+        assert_is_redirect_to(&post_response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn a_topic_can_be_created_and_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create
+    let topic_id = create_topic(&app, "Rust").await;
+    let html_page = app.get_topics_html("").await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/topics/{}">Rust</a> (0 subscribers)"#,
+        topic_id
+    )));
+
+    // Act - Part 2 - Edit
+    let path = format!("/{}", topic_id);
+    let response = app.post_topic(&path, &topic_body("Rustaceans")).await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/topics{}", path));
+    let html_page = app.get_topics_html(&path).await;
+    assert!(html_page.contains("<p><i>The topic has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Rustaceans""#));
+}
+
+#[tokio::test]
+async fn topic_names_must_be_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_topic(&app, "Rust").await;
+
+    // Act
+    let response = app.post_topic("", &topic_body("Rust")).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/topics");
+    let html_page = app.get_topics_html("").await;
+    assert!(html_page.contains("There is a topic named Rust already."));
+}
+
+#[tokio::test]
+async fn the_subscription_form_lists_the_topics() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let topic_id = create_topic(&app, "Rust").await;
+
+    // Act
+    let html_page = app
+        .api_client
+        .get(&app.address)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(&format!(
+        r#"<input type="checkbox" name="topic_id" value="{}">"#,
+        topic_id
+    )));
+    assert!(html_page.contains("Rust <small>All about Rust</small>"));
+}
+
+#[tokio::test]
+async fn subscribers_can_pick_topics_when_subscribing() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let rust = create_topic(&app, "Rust").await;
+    let go = create_topic(&app, "Go").await;
+
+    // Act
+    create_confirmed_subscriber_to(&app, "ursula@example.com", &[rust, go])
+        .await;
+
+    // Assert
+    let mut expected = vec![rust, go];
+    expected.sort();
+    assert_eq!(
+        subscriber_topic_ids(&app, "ursula@example.com").await,
+        expected
+    );
+}
+
+#[tokio::test]
+async fn subscribing_again_emails_the_preference_centre() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let rust = create_topic(&app, "Rust").await;
+    let go = create_topic(&app, "Go").await;
+    create_confirmed_subscriber_to(&app, "ursula@example.com", &[rust]).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = serde_urlencoded::to_string([
+        ("name", "le guin".to_string()),
+        ("email", "ursula@example.com".into()),
+        ("topic_id", go.to_string()),
+    ])
+    .unwrap();
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    // Whoever knows the address can't change the topics
+    assert_eq!(
+        subscriber_topic_ids(&app, "ursula@example.com").await,
+        [rust]
+    );
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_preferences_links(&email_request);
+    assert_eq!(links.html, links.plain_text);
+    assert_eq!(links.html.path(), "/subscriptions/preferences");
+    let token = unsubscribe_token(&app, "ursula@example.com").await;
+    let html_page = reqwest::get(links.html)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page
+        .contains(&format!(r#"name="topic_id" value="{}" checked>"#, rust)));
+    assert!(html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_topic_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [Uuid::new_v4().to_string(), "not-a-uuid".into()];
+
+    for topic_id in test_cases {
+        let body = serde_urlencoded::to_string([
+            ("name", "le guin"),
+            ("email", "ursula@example.com"),
+            ("topic_id", &topic_id),
+        ])
+        .unwrap();
+
+        // Act
+        let response = app.post_subscriptions(body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject topic {}",
+            topic_id
+        );
+    }
+}
+
+#[tokio::test]
+async fn the_preference_centre_requires_a_valid_token() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let get_response = app.get_preferences("not-a-token").await;
+    let post_response = app
+        .post_preferences(&[("unsubscribe_token", "not-a-token")])
+        .await;
+
+    // Assert
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_topics_in_the_preference_centre() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let rust = create_topic(&app, "Rust").await;
+    let go = create_topic(&app, "Go").await;
+    create_confirmed_subscriber_to(&app, "ursula@example.com", &[rust]).await;
+    let token = unsubscribe_token(&app, "ursula@example.com").await;
+
+    // Act - Part 1 - Look at the preferences
+    let html_page = app.get_preferences(&token).await.text().await.unwrap();
+    assert!(html_page
+        .contains(&format!(r#"name="topic_id" value="{}" checked>"#, rust)));
+    assert!(html_page.contains(&format!(r#"name="topic_id" value="{}">"#, go)));
+
+    // Act - Part 2 - Swap topics
+    let go_id = go.to_string();
+    let response = app
+        .post_preferences(&[
+            ("unsubscribe_token", &token),
+            ("topic_id", &go_id),
+        ])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(
+        html_page.contains("<p><i>Your preferences have been saved.</i></p>")
+    );
+    assert_eq!(subscriber_topic_ids(&app, "ursula@example.com").await, [go]);
+
+    // Act - Part 3 - Untick everything
+    app.post_preferences(&[("unsubscribe_token", &token)]).await;
+    assert!(subscriber_topic_ids(&app, "ursula@example.com")
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_topic_only_reach_its_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let rust = create_topic(&app, "Rust").await;
+    let go = create_topic(&app, "Go").await;
+    create_confirmed_subscriber_to(&app, "ursula@example.com", &[rust]).await;
+    create_confirmed_subscriber_to(&app, "octavia@example.com", &[go]).await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act - Part 1 - The form offers the topics
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<option value="{}">Rust (1 subscribers)</option>"#,
+        rust
+    )));
+
+    // Act - Part 2 - Publish to a topic
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "topic_id": rust.to_string(),
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@example.com");
+}
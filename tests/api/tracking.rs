@@ -0,0 +1,211 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app,
+    when_sending_an_email, TestApp,
+};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+/// Publish an issue linking to our website and deliver it,
+/// returns the HTML body subscribers got.
+async fn deliver_issue(app: &TestApp, tracking: bool) -> String {
+    create_confirmed_subscriber(app).await;
+    app.test_user.login(app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Read more at https://example.com/news",
+        "html_content": r#"<p><a href="https://example.com/news?a=1&amp;b=2">Read more</a></p>"#,
+        "tracking": tracking,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.body).unwrap();
+    body["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+/// The tracking links of an email, pointed at the test server.
+fn tracking_link(app: &TestApp, html: &str, prefix: &str) -> reqwest::Url {
+    let raw_link = linkify::LinkFinder::new()
+        .links(html)
+        .map(|l| htmlescape::decode_html(l.as_str()).unwrap())
+        .find(|l| l.contains(prefix))
+        .unwrap();
+    let mut link = reqwest::Url::parse(&raw_link).unwrap();
+    assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+#[tokio::test]
+async fn opens_and_clicks_of_tracked_issues_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = deliver_issue(&app, true).await;
+    assert!(!html.contains(r#"href="https://example.com/news"#));
+
+    // Act - Part 1 - Open
+    let response = app
+        .api_client
+        .get(tracking_link(&app, &html, "/t/o/"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+
+    // Act - Part 2 - Click, twice
+    for _ in 0..2 {
+        let response = app
+            .api_client
+            .get(tracking_link(&app, &html, "/t/c/"))
+            .send()
+            .await
+            .unwrap();
+        assert_is_redirect_to(&response, "https://example.com/news?a=1&b=2");
+    }
+
+    // Assert
+    let html_page = app.get_analytics_html().await;
+    assert!(html_page
+        .contains("<td>1 (100.0%)</td>\n            <td>1 (100.0%)</td>"));
+}
+
+#[tokio::test]
+async fn unsubscribing_is_never_tracked() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html = deliver_issue(&app, true).await;
+
+    // Assert
+    assert!(html.contains(r#"<a href="http://127.0.0.1/subscriptions/unsubscribe?unsubscribe_token="#));
+}
+
+#[tokio::test]
+async fn untracked_issues_are_sent_as_they_are() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html = deliver_issue(&app, false).await;
+
+    // Assert
+    assert!(html.starts_with(
+        r#"<p><a href="https://example.com/news?a=1&amp;b=2">Read more</a></p>"#
+    ));
+    assert!(!html.contains("/t/o/"));
+    let html_page = app.get_analytics_html().await;
+    assert!(html_page.contains("No tracked issues"));
+}
+
+#[tokio::test]
+async fn tampered_tracking_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = deliver_issue(&app, true).await;
+    let mut click_link = tracking_link(&app, &html, "/t/c/");
+    let mut open_link = tracking_link(&app, &html, "/t/o/");
+
+    // Act
+    click_link
+        .query_pairs_mut()
+        .clear()
+        .append_pair("url", "https://phishing.example.com");
+    open_link.set_path(&format!("{}x", open_link.path()));
+
+    // Assert
+    for link in [click_link, open_link] {
+        let response = app.api_client.get(link).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+    }
+    let n_events = sqlx::query!("SELECT COUNT(*) AS n FROM tracking_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_events, Some(0));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_analytics() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_analytics().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn unsubscribe_links_of_layouts_are_not_tracked() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_layout(
+            "",
+            &serde_json::json!({
+                "name": "Branded",
+                "html_template": r#"{{ content }}<a href="{{ unsubscribe_url }}">Leave</a>"#,
+                "text_template": "{{ content }}",
+            }),
+        )
+        .await;
+    let layout_id = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .trim_start_matches("/admin/layouts/")
+        .to_owned();
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Read more at https://example.com/news",
+        "html_content": r#"<p><a href="https://example.com/news">Read more</a></p>"#,
+        "layout_id": layout_id,
+        "tracking": true,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["HtmlBody"].as_str().unwrap();
+    // Only the link of the content is tracked
+    assert_eq!(html.matches("/t/c/").count(), 1);
+    assert!(html.contains(
+        r#"<a href="http://127.0.0.1/subscriptions/unsubscribe?unsubscribe_token"#
+    ));
+}